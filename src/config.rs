@@ -0,0 +1,37 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub fn config_dir() -> Option<PathBuf> {
+	dirs::config_dir().map(|config_dir| config_dir.join("xcmd"))
+}
+
+pub fn config_file(name: &str) -> Option<PathBuf> {
+	config_dir().map(|config_dir| config_dir.join(name))
+}
+
+pub fn read_config_file(name: &str) -> Option<String> {
+	let path = config_file(name)?;
+	match fs::read_to_string(&path) {
+		Ok(content) => {
+			log::info!("Loaded configuration file: {:?}", path);
+			Some(content)
+		}
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+		Err(e) => {
+			log::error!("Failed to read {:?}: {}", path, e);
+			None
+		}
+	}
+}
+
+pub fn write_config_file(name: &str, content: &str) -> io::Result<()> {
+	let path = config_file(name)
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No configuration directory."))?;
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	fs::write(&path, content)?;
+	log::info!("Saved configuration file: {:?}", path);
+	Ok(())
+}
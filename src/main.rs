@@ -4,22 +4,27 @@
 #[cfg(windows)]
 #[macro_use]
 mod com;
+mod config;
 mod data_source;
 mod repository;
 mod self_update;
+mod settings;
 #[cfg(windows)]
 mod shortcut;
 mod ui;
 
 use brotli::BrotliDecompress;
 use sciter::{RuntimeOptions, Window};
+use settings::Settings;
 use sha2::{Digest, Sha256};
+use std::cell::RefCell;
 use std::env;
 use std::fmt::Write;
 use std::fs::{self, File};
 use std::io;
 use std::path::Path;
-use ui::{WindowEventHandler, WindowSciterHandler};
+use std::rc::Rc;
+use ui::{get_theme_variable_names, Themes, WindowEventHandler, WindowSciterHandler};
 use xcmd_core::errors;
 
 macro_rules! lib_path {
@@ -132,9 +137,17 @@ fn main() -> Result<(), errors::AppError> {
 		sciter::SCRIPT_RUNTIME_FEATURES::ALLOW_EVAL as u8, // Enables eval
 	))?;
 	sciter::set_options(RuntimeOptions::DebugMode(true))?;
+	let settings = Settings::load();
+	let mut themes = Themes::load(&get_theme_variable_names());
+	if let Some(theme) = &settings.theme {
+		if !themes.select(theme) {
+			log::warn!("Color theme {} is not available.", theme);
+		}
+	}
+	let themes = Rc::new(RefCell::new(themes));
 	let mut window = Window::new();
-	window.event_handler(WindowEventHandler::new());
-	window.sciter_handler(WindowSciterHandler::new());
+	window.event_handler(WindowEventHandler::new(Rc::clone(&themes), settings));
+	window.sciter_handler(WindowSciterHandler::new(themes));
 	window.load_file("app://xcmd/shell.sciter.html");
 	window.set_title(&format!("Cross Commander {}", env!("CARGO_PKG_VERSION")));
 	window.run_app();
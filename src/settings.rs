@@ -0,0 +1,103 @@
+use crate::config;
+use serde_derive::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// State of the window, saved on exit and restored on start.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+	pub theme: Option<String>,
+	pub active_pane: u8,
+	pub window: Option<WindowGeometry>,
+	pub panes: Vec<PaneSettings>,
+	pub bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct WindowGeometry {
+	pub x: i32,
+	pub y: i32,
+	pub width: i32,
+	pub height: i32,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PaneSettings {
+	pub current_tab: usize,
+	pub tabs: Vec<TabSettings>,
+	pub columns: Vec<ColumnSettings>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TabSettings {
+	pub path: Option<String>,
+	/// Name of the connection profile, or none for the local file system.
+	pub connection: Option<String>,
+	/// Directories to go back to, the most recent last.
+	pub history: Vec<String>,
+	pub sort: SortSettings,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct SortSettings {
+	pub field: String,
+	pub descending: bool,
+	pub directories_first: bool,
+}
+
+impl Default for SortSettings {
+	fn default() -> Self {
+		SortSettings {
+			field: "path".to_owned(),
+			descending: false,
+			directories_first: true,
+		}
+	}
+}
+
+/// A directory in the hotlist.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Bookmark {
+	pub name: String,
+	pub path: String,
+	/// Name of the connection profile, or none for the local file system.
+	pub connection: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ColumnSettings {
+	pub field: String,
+	/// Width in dips, or none for the default width.
+	pub width: Option<u32>,
+}
+
+impl Settings {
+	pub fn load() -> Self {
+		match config::read_config_file(SETTINGS_FILE) {
+			Some(toml) => toml::from_str::<Settings>(&toml).unwrap_or_else(|e| {
+				log::error!("Failed to parse {}: {}", SETTINGS_FILE, e);
+				Settings::default()
+			}),
+			None => Settings::default(),
+		}
+	}
+
+	pub fn save(&self) {
+		let result = toml::to_string(self)
+			.map_err(|e| e.to_string())
+			.and_then(|toml| {
+				config::write_config_file(SETTINGS_FILE, &toml).map_err(|e| e.to_string())
+			});
+		if let Err(e) = result {
+			log::error!("Failed to save {}: {}", SETTINGS_FILE, e);
+		}
+	}
+
+	pub fn get_pane(&self, index: u8) -> Option<&PaneSettings> {
+		self.panes.get(index as usize)
+	}
+}
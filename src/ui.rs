@@ -1,13 +1,30 @@
+mod associations;
 mod column;
+mod file_reader;
+mod jobs_panel;
+mod key_bindings;
+mod multi_rename;
 mod palette;
 mod pane;
+mod quick_search;
+mod sort;
 mod template;
+mod text_layout;
+mod theme;
+mod viewer;
 mod window_event_handler;
 mod window_sciter_handler;
 
 pub use self::column::Column;
-pub use self::palette::Palette;
-pub use self::pane::Pane;
+pub use self::jobs_panel::JobsPanel;
+pub use self::palette::{Palette, PaletteItem};
+pub use self::pane::{Changes, Listing, Pane};
+pub use self::quick_search::{Matcher, QuickSearch};
+pub use self::sort::SortOrder;
 pub use self::template::Template;
-pub use self::window_event_handler::{mk_callback, WindowEventHandler, WindowState};
-pub use self::window_sciter_handler::WindowSciterHandler;
+pub use self::theme::Themes;
+pub use self::viewer::{OpenedFile, Viewer};
+pub use self::window_event_handler::{mk_callback, JobMessage, WindowEventHandler, WindowState};
+pub use self::window_sciter_handler::{
+	get_app_data, get_theme_variable_names, WindowSciterHandler,
+};
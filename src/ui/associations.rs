@@ -0,0 +1,392 @@
+use crate::config;
+use crate::ui::pane::lock_system;
+use crate::ui::quick_search::glob_to_regex;
+use crate::ui::JobMessage;
+use regex::RegexBuilder;
+use serde_derive::Deserialize;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use xcmd_core::api::{Error, System};
+use xcmd_core::archive;
+use xcmd_core::jobs::JobContext;
+
+const ASSOCIATIONS_FILE: &str = "associations.toml";
+const PLACEHOLDERS: &[&str] = &["{path}", "{dir}", "{selection}"];
+const SYNC_INTERVAL: Duration = Duration::from_millis(500);
+/// Programs exiting sooner have likely handed the file to a running instance,
+/// so the copy is synchronized until the job is cancelled.
+const HANDOFF_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+	Edit,
+	Open,
+}
+
+/// Programs to edit and open files with, read from `associations.toml`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Associations {
+	editor: Vec<Association>,
+	opener: Vec<Association>,
+}
+
+#[derive(Deserialize)]
+struct Association {
+	/// Globs matched against the file name, ignoring case.
+	patterns: Vec<String>,
+	command: String,
+}
+
+impl Association {
+	fn is_match(&self, filename: &str) -> bool {
+		self.patterns.iter().any(|pattern| {
+			RegexBuilder::new(&glob_to_regex(pattern))
+				.case_insensitive(true)
+				.build()
+				.map_or(false, |regex| regex.is_match(filename))
+		})
+	}
+}
+
+impl Associations {
+	pub fn load() -> Result<Associations, Error> {
+		let toml = config::read_config_file(ASSOCIATIONS_FILE)
+			.unwrap_or_else(|| include_str!("../../config/associations.toml").to_owned());
+		Ok(toml::from_str(&toml)?)
+	}
+
+	/// Returns the program and arguments for the action on the file, with
+	/// placeholders. Files without an association are edited with `$VISUAL`
+	/// or `$EDITOR` and else opened with the default application.
+	pub fn get_command(&self, action: Action, filename: &str) -> Vec<String> {
+		let associations = match action {
+			Action::Edit => &self.editor,
+			Action::Open => &self.opener,
+		};
+		let command = associations
+			.iter()
+			.find(|association| association.is_match(filename))
+			.map(|association| association.command.clone())
+			.or_else(|| match action {
+				Action::Edit => env::var("VISUAL")
+					.or_else(|_| env::var("EDITOR"))
+					.ok()
+					.filter(|editor| !editor.trim().is_empty()),
+				Action::Open => None,
+			});
+		let mut args = match command {
+			Some(command) => split_command(&command),
+			None => get_default_command(action),
+		};
+		if !args
+			.iter()
+			.any(|arg| PLACEHOLDERS.iter().any(|x| arg.contains(x)))
+		{
+			args.push("{path}".to_owned());
+		}
+		args
+	}
+}
+
+#[cfg(target_os = "windows")]
+fn get_default_command(action: Action) -> Vec<String> {
+	match action {
+		Action::Edit => vec!["notepad".to_owned()],
+		Action::Open => vec!["explorer".to_owned()],
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn get_default_command(action: Action) -> Vec<String> {
+	match action {
+		Action::Edit => vec!["open".to_owned(), "-t".to_owned()],
+		Action::Open => vec!["open".to_owned()],
+	}
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn get_default_command(_action: Action) -> Vec<String> {
+	vec!["xdg-open".to_owned()]
+}
+
+/// Splits a command at spaces, except within double quotes.
+fn split_command(command: &str) -> Vec<String> {
+	let mut args = Vec::new();
+	let mut arg: Option<String> = None;
+	let mut quoted = false;
+	for c in command.chars() {
+		match c {
+			'"' => {
+				quoted = !quoted;
+				arg.get_or_insert_with(String::new);
+			}
+			c if c.is_whitespace() && !quoted => args.extend(arg.take()),
+			c => arg.get_or_insert_with(String::new).push(c),
+		}
+	}
+	args.extend(arg);
+	args
+}
+
+/// Replaces the placeholders. A `{selection}` argument is replaced by one
+/// argument per selected path.
+pub fn expand_command(args: &[String], path: &str, dir: &str, selection: &[String]) -> Vec<String> {
+	let mut expanded = Vec::with_capacity(args.len());
+	for arg in args {
+		if arg == "{selection}" {
+			expanded.extend(selection.iter().cloned());
+		} else {
+			expanded.push(
+				arg.replace("{path}", path)
+					.replace("{dir}", dir)
+					.replace("{selection}", &selection.join(" ")),
+			);
+		}
+	}
+	expanded
+}
+
+fn spawn(args: &[String], dir: &str) -> io::Result<Child> {
+	let (program, args) = args
+		.split_first()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No program given."))?;
+	log::info!("Running {} {:?}", program, args);
+	Command::new(program)
+		.args(args)
+		.current_dir(dir)
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.spawn()
+}
+
+/// Runs a program without waiting for it.
+pub fn launch(args: &[String], dir: &str) -> io::Result<()> {
+	let mut child = spawn(args, dir)?;
+	thread::spawn(move || child.wait());
+	Ok(())
+}
+
+/// Downloads a remote file to a temporary copy, runs the command on the copy
+/// and uploads it, whenever it is saved, until the program exits or the job
+/// is cancelled. Entries of archives cannot be uploaded, so their copy is made
+/// read-only.
+pub fn edit_remote_file(
+	context: &JobContext<JobMessage>,
+	system: &Mutex<Box<dyn System>>,
+	path: &str,
+	command: &[String],
+) {
+	let (filename, read_only) = {
+		let mut locked_system = lock_system(system);
+		let read_only = archive::is_entry_path(&mut **locked_system, path);
+		(locked_system.get_filename(path), read_only)
+	};
+	let dir = match create_temp_dir() {
+		Ok(dir) => dir,
+		Err(e) => {
+			context.send(JobMessage::EditFailed(format!(
+				"Failed to edit {}: {}",
+				path, e
+			)));
+			return;
+		}
+	};
+	let local_path = dir.join(filename);
+	let result = sync_copy(context, system, path, &local_path, command, read_only);
+	let mut message = match result {
+		Ok(()) => None,
+		Err(e) => Some(format!("Failed to edit {}: {}", path, e)),
+	};
+	// A copy, which may not have been uploaded, is kept after errors.
+	if let (Some(message), true) = (&mut message, local_path.exists() && !read_only) {
+		message.push_str(&format!("\nThe copy is kept in {}.", dir.to_string_lossy()));
+	} else if let Err(e) = fs::remove_dir_all(&dir) {
+		log::warn!("Failed to remove {:?}: {}", dir, e);
+	}
+	if let Some(message) = message {
+		context.send(JobMessage::EditFailed(message));
+	}
+}
+
+/// Creates a new directory for temporary copies, which only the user can
+/// access.
+pub fn create_temp_dir() -> io::Result<PathBuf> {
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |x| x.subsec_nanos());
+	let dir = env::temp_dir().join(format!("xcmd-{}-{}", process::id(), nanos));
+	let mut builder = fs::DirBuilder::new();
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::DirBuilderExt;
+		builder.mode(0o700);
+	}
+	builder.create(&dir)?;
+	Ok(dir)
+}
+
+fn sync_copy(
+	context: &JobContext<JobMessage>,
+	system: &Mutex<Box<dyn System>>,
+	path: &str,
+	local_path: &Path,
+	command: &[String],
+	read_only: bool,
+) -> Result<(), Error> {
+	let mut reader = lock_system(system).open_read(path, 0)?;
+	io::copy(&mut reader, &mut fs::File::create(local_path)?)?;
+	if read_only {
+		let mut permissions = fs::metadata(local_path)?.permissions();
+		permissions.set_readonly(true);
+		fs::set_permissions(local_path, permissions)?;
+	}
+	let mut modified = get_modified(local_path)?;
+
+	let local = local_path.to_string_lossy().into_owned();
+	let dir = local_path
+		.parent()
+		.map_or_else(String::new, |x| x.to_string_lossy().into_owned());
+	let args = expand_command(command, &local, &dir, std::slice::from_ref(&local));
+	let mut child = spawn(&args, &dir)?;
+	let started = Instant::now();
+	let mut running_time = None;
+	loop {
+		thread::sleep(SYNC_INTERVAL);
+		if running_time.is_none() && child.try_wait()?.is_some() {
+			running_time = Some(started.elapsed());
+		}
+		let current = get_modified(local_path)?;
+		if current != modified && !read_only {
+			let mut writer = lock_system(system).open_write(path, false)?;
+			io::copy(&mut fs::File::open(local_path)?, &mut writer)?;
+			writer.flush()?;
+			log::info!("Uploaded {:?} to {}", local_path, path);
+			modified = current;
+		}
+		if context.is_cancelled() || running_time.map_or(false, |x| x >= HANDOFF_TIME) {
+			return Ok(());
+		}
+	}
+}
+
+fn get_modified(path: &Path) -> io::Result<(Option<SystemTime>, u64)> {
+	let metadata = fs::metadata(path)?;
+	Ok((metadata.modified().ok(), metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn strings(values: &[&str]) -> Vec<String> {
+		values.iter().map(|x| (*x).to_owned()).collect()
+	}
+
+	#[test]
+	fn commands_are_split_at_spaces_outside_of_quotes() {
+		assert_eq!(
+			split_command(r#"  code  --wait "{path}" "C:\Program Files\x" "" "#),
+			strings(&["code", "--wait", "{path}", r"C:\Program Files\x", ""])
+		);
+		assert_eq!(split_command(r#"a"b c"d"#), strings(&["ab cd"]));
+		assert!(split_command("   ").is_empty());
+	}
+
+	#[test]
+	fn placeholders_are_expanded() {
+		let args = strings(&[
+			"diff",
+			"{selection}",
+			"--dir={dir}",
+			"{path}",
+			"[{selection}]",
+		]);
+		let selection = strings(&["/a b/x", "/a b/y"]);
+		assert_eq!(
+			expand_command(&args, "/a b/x", "/a b", &selection),
+			strings(&[
+				"diff",
+				"/a b/x",
+				"/a b/y",
+				"--dir=/a b",
+				"/a b/x",
+				"[/a b/x /a b/y]"
+			])
+		);
+		assert_eq!(
+			expand_command(&args, "/x", "/", &[]),
+			strings(&["diff", "--dir=/", "/x", "[]"])
+		);
+	}
+
+	#[test]
+	fn commands_fall_back_to_the_environment_and_the_default() {
+		let associations: Associations = toml::from_str(
+			r#"
+			[[editor]]
+			patterns = ["*.TXT"]
+			command = "gedit --new-window"
+
+			[[opener]]
+			patterns = ["*.pdf"]
+			command = "evince {selection}"
+			"#,
+		)
+		.unwrap();
+		let mut default_edit = get_default_command(Action::Edit);
+		default_edit.push("{path}".to_owned());
+		let mut default_open = get_default_command(Action::Open);
+		default_open.push("{path}".to_owned());
+
+		env::set_var("VISUAL", "vim -p");
+		env::set_var("EDITOR", "nano");
+		assert_eq!(
+			associations.get_command(Action::Edit, "notes.txt"),
+			strings(&["gedit", "--new-window", "{path}"])
+		);
+		assert_eq!(
+			associations.get_command(Action::Edit, "main.rs"),
+			strings(&["vim", "-p", "{path}"])
+		);
+		assert_eq!(
+			associations.get_command(Action::Open, "a.pdf"),
+			strings(&["evince", "{selection}"])
+		);
+		assert_eq!(
+			associations.get_command(Action::Open, "main.rs"),
+			default_open
+		);
+
+		env::set_var("VISUAL", " ");
+		assert_eq!(
+			associations.get_command(Action::Edit, "main.rs"),
+			strings(&["nano", "{path}"])
+		);
+
+		env::remove_var("VISUAL");
+		env::remove_var("EDITOR");
+		assert_eq!(
+			associations.get_command(Action::Edit, "main.rs"),
+			default_edit
+		);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn temp_dirs_are_private() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = create_temp_dir().unwrap();
+		let mode = fs::metadata(&dir).unwrap().permissions().mode();
+		fs::remove_dir(&dir).unwrap();
+		assert_eq!(mode & 0o777, 0o700);
+	}
+}
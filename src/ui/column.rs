@@ -1,15 +1,26 @@
 pub struct Column {
 	name: String,
+	/// Width in dips, or none for the width from the style sheet.
+	width: Option<u32>,
 }
 
 impl Column {
 	pub fn new(name: &str) -> Self {
 		Column {
 			name: name.to_owned(),
+			width: None,
 		}
 	}
 
 	pub fn get_name(&self) -> &str {
 		&self.name
 	}
+
+	pub fn get_width(&self) -> Option<u32> {
+		self.width
+	}
+
+	pub fn set_width(&mut self, width: Option<u32>) {
+		self.width = width;
+	}
 }
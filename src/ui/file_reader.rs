@@ -0,0 +1,172 @@
+use crate::ui::pane::lock_system;
+use crate::ui::JobMessage;
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use xcmd_core::api::System;
+use xcmd_core::jobs::{JobContext, JobSpawner};
+
+pub const PAGE_SIZE: u64 = 64 * 1024;
+const CACHED_PAGES: usize = 64;
+
+/// Reads parts of a file through its system. Recently read pages are cached,
+/// so only the shown part of a large file is kept in memory. Missing pages are
+/// read by jobs, so the UI thread never waits for the system.
+pub struct FileReader {
+	system: Arc<Mutex<Box<dyn System>>>,
+	path: String,
+	size: u64,
+	/// Identifies the pages read for this reader.
+	generation: u64,
+	jobs: JobSpawner<JobMessage>,
+	/// The most recently used page last.
+	pages: RefCell<Vec<(u64, Rc<Vec<u8>>)>>,
+	/// Pages being read by jobs.
+	requested: RefCell<Vec<u64>>,
+}
+
+impl FileReader {
+	/// Creates a reader with the first page of the file already read.
+	pub fn new(
+		system: Arc<Mutex<Box<dyn System>>>,
+		path: &str,
+		size: u64,
+		first_page: Vec<u8>,
+		generation: u64,
+		jobs: JobSpawner<JobMessage>,
+	) -> Self {
+		FileReader {
+			system,
+			path: path.to_owned(),
+			size,
+			generation,
+			jobs,
+			pages: RefCell::new(vec![(0, Rc::new(first_page))]),
+			requested: RefCell::new(Vec::new()),
+		}
+	}
+
+	pub fn get_system(&self) -> Arc<Mutex<Box<dyn System>>> {
+		Arc::clone(&self.system)
+	}
+
+	pub fn get_path(&self) -> &str {
+		&self.path
+	}
+
+	pub fn get_size(&self) -> u64 {
+		self.size
+	}
+
+	pub fn get_generation(&self) -> u64 {
+		self.generation
+	}
+
+	/// Reads up to `length` bytes from the cached pages. Less is returned at
+	/// the end of the file, after an error, or when pages are still being
+	/// read. Missing pages are requested from a job.
+	pub fn read(&self, offset: u64, length: usize) -> Vec<u8> {
+		let end = self.size.min(offset + length as u64);
+		let mut data = Vec::with_capacity(end.saturating_sub(offset) as usize);
+		let mut position = offset;
+		while position < end {
+			let page_index = position / PAGE_SIZE;
+			let page = match self.get_page(page_index) {
+				Some(page) => page,
+				None => {
+					self.request_pages(page_index, (end - 1) / PAGE_SIZE);
+					break;
+				}
+			};
+			let start = (position - page_index * PAGE_SIZE) as usize;
+			let stop = page.len().min((end - page_index * PAGE_SIZE) as usize);
+			if start >= stop {
+				break;
+			}
+			data.extend_from_slice(&page[start..stop]);
+			position += (stop - start) as u64;
+		}
+		data
+	}
+
+	/// Adds pages read by a job, starting at `first_page`.
+	pub fn add_pages(&self, first_page: u64, new_pages: Vec<Vec<u8>>) {
+		let mut pages = self.pages.borrow_mut();
+		let mut requested = self.requested.borrow_mut();
+		for (page_index, page) in (first_page..).zip(new_pages) {
+			requested.retain(|index| *index != page_index);
+			pages.retain(|(index, _page)| *index != page_index);
+			if pages.len() == CACHED_PAGES {
+				pages.remove(0);
+			}
+			pages.push((page_index, Rc::new(page)));
+		}
+	}
+
+	fn get_page(&self, page_index: u64) -> Option<Rc<Vec<u8>>> {
+		let mut pages = self.pages.borrow_mut();
+		let position = pages
+			.iter()
+			.position(|(index, _page)| *index == page_index)?;
+		let entry = pages.remove(position);
+		let page = Rc::clone(&entry.1);
+		pages.push(entry);
+		Some(page)
+	}
+
+	/// Starts a job reading the missing pages from `first_page` up to
+	/// `last_page`, opening the file only once.
+	fn request_pages(&self, first_page: u64, last_page: u64) {
+		let mut requested = self.requested.borrow_mut();
+		if requested.contains(&first_page) {
+			return;
+		}
+		let pages = self.pages.borrow();
+		let page_count = (first_page..=last_page)
+			.take_while(|page_index| {
+				*page_index == first_page
+					|| !(requested.contains(page_index)
+						|| pages.iter().any(|(index, _page)| index == page_index))
+			})
+			.count() as u64;
+		requested.extend(first_page..first_page + page_count);
+		let system = self.get_system();
+		let path = self.path.clone();
+		let generation = self.generation;
+		self.jobs.spawn(&format!("Read {}", path), move |context| {
+			read_pages(context, &system, &path, first_page, page_count, generation);
+		});
+	}
+}
+
+/// Reads consecutive pages. A page, which cannot be read, is sent empty, so
+/// the viewer stops there instead of requesting it again.
+fn read_pages(
+	context: &JobContext<JobMessage>,
+	system: &Mutex<Box<dyn System>>,
+	path: &str,
+	first_page: u64,
+	page_count: u64,
+	generation: u64,
+) {
+	let mut pages = Vec::new();
+	let reader = lock_system(system).open_read(path, first_page * PAGE_SIZE);
+	let result = reader.and_then(|mut reader| {
+		while (pages.len() as u64) < page_count {
+			let mut page = Vec::with_capacity(PAGE_SIZE as usize);
+			reader.by_ref().take(PAGE_SIZE).read_to_end(&mut page)?;
+			pages.push(page);
+		}
+		Ok(())
+	});
+	if let Err(e) = result {
+		log::error!("Failed to read {}: {}", path, e);
+		pages.push(Vec::new());
+	}
+	context.send(JobMessage::ViewerPages {
+		generation,
+		first_page,
+		pages,
+	});
+}
@@ -0,0 +1,112 @@
+use sciter::{Element, Value};
+use separator::Separatable;
+use std::collections::HashMap;
+use xcmd_core::jobs::JobId;
+use xcmd_core::transfer::Progress;
+
+struct JobItem {
+	item: Element,
+	current_path: Element,
+	current_progress: Element,
+	total: Element,
+	total_progress: Element,
+}
+
+/// Shows running transfers with their progress, without blocking the panes.
+pub struct JobsPanel {
+	panel: Element,
+	items: HashMap<JobId, JobItem>,
+}
+
+impl JobsPanel {
+	pub fn new(element: &mut Element) -> JobsPanel {
+		JobsPanel {
+			panel: Element::from(element.as_ptr()),
+			items: HashMap::new(),
+		}
+	}
+
+	pub fn add(&mut self, id: JobId, title: &str) {
+		let mut item = Element::create("job").unwrap();
+		self.panel.append(&item).unwrap();
+		item.append(&Element::with_text("p", title).unwrap())
+			.unwrap();
+		let current_path = append_child(&mut item, "p", "current-path");
+		let current_progress = append_child(&mut item, "progress", "current-progress");
+		let total = append_child(&mut item, "p", "total");
+		let total_progress = append_child(&mut item, "progress", "total-progress");
+		let mut cancel = Element::with_text("button", "Cancel").unwrap();
+		item.append(&cancel).unwrap();
+		cancel.set_attribute("class", "cancel").unwrap();
+		cancel.set_attribute("job", &id.to_string()).unwrap();
+
+		self.items.insert(
+			id,
+			JobItem {
+				item,
+				current_path,
+				current_progress,
+				total,
+				total_progress,
+			},
+		);
+		self.update(id, &Progress::default());
+		self.panel.set_attribute("active", "true").unwrap();
+	}
+
+	pub fn remove(&mut self, id: JobId) {
+		if let Some(mut job_item) = self.items.remove(&id) {
+			job_item.item.destroy().unwrap();
+		}
+		if self.items.is_empty() {
+			self.panel.remove_attribute("active").unwrap();
+		}
+	}
+
+	pub fn update(&mut self, id: JobId, progress: &Progress) {
+		let job_item = match self.items.get_mut(&id) {
+			Some(job_item) => job_item,
+			None => return,
+		};
+		job_item
+			.current_path
+			.set_text(&progress.current_path)
+			.unwrap();
+		job_item
+			.current_progress
+			.set_value(get_percent(progress.current_bytes, progress.current_size))
+			.unwrap();
+		job_item
+			.total
+			.set_text(&format!(
+				"{} of {} files, {} of {} bytes",
+				progress.done_files.separated_string(),
+				progress.total_files.separated_string(),
+				progress.done_bytes.separated_string(),
+				progress.total_bytes.separated_string()
+			))
+			.unwrap();
+		job_item
+			.total_progress
+			.set_value(get_percent(progress.done_bytes, progress.total_bytes))
+			.unwrap();
+	}
+}
+
+fn append_child(parent: &mut Element, tag: &str, class: &str) -> Element {
+	let mut child = Element::create(tag).unwrap();
+	parent.append(&child).unwrap();
+	child.set_attribute("class", class).unwrap();
+	if tag == "progress" {
+		child.set_attribute("max", "100").unwrap();
+	}
+	child
+}
+
+fn get_percent(done: u64, total: u64) -> Value {
+	if total == 0 {
+		Value::from(0)
+	} else {
+		Value::from((done.min(total) * 100 / total) as i32)
+	}
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+pub const ALT: i32 = 0x1000_0000;
+pub const CTRL: i32 = 0x0100_0000;
+pub const SHIFT: i32 = 0x0010_0000;
+
+/// An entry of `keybindings.json`. A command starting with `-` removes the
+/// matching earlier binding instead of adding one.
+#[derive(serde_derive::Deserialize)]
+pub struct KeyBinding {
+	pub key: String,
+	pub command: String,
+	#[serde(default)]
+	pub when: Option<String>,
+}
+
+struct Binding {
+	key: String,
+	keys: Vec<i32>,
+	command: String,
+	when: Option<String>,
+}
+
+pub enum KeyResult {
+	Command(String),
+	/// The key started or continued a chord.
+	Pending,
+	/// The key ended a chord which is not bound.
+	Cancelled,
+	None,
+}
+
+/// Resolves key presses to commands. Later bindings take precedence, so user
+/// bindings appended after the defaults override them.
+#[derive(Default)]
+pub struct KeyBindings {
+	bindings: Vec<Binding>,
+	pending: Vec<i32>,
+}
+
+impl KeyBindings {
+	pub fn add(&mut self, key_bindings: &[KeyBinding], key_names: &HashMap<String, i32>) {
+		for key_binding in key_bindings {
+			let keys = match parse_keys(&key_binding.key, key_names) {
+				Some(keys) => keys,
+				None => {
+					log::warn!("Unknown key in key binding: {}", key_binding.key);
+					continue;
+				}
+			};
+			if key_binding.command.starts_with('-') {
+				let command = &key_binding.command[1..];
+				self.bindings.retain(|binding| {
+					binding.keys != keys
+						|| binding.command != command
+						|| (key_binding.when.is_some() && binding.when != key_binding.when)
+				});
+			} else {
+				self.bindings.push(Binding {
+					key: key_binding.key.to_owned(),
+					keys,
+					command: key_binding.command.to_owned(),
+					when: key_binding.when.to_owned(),
+				});
+			}
+		}
+	}
+
+	/// Returns the first key of each bound command, formatted for display.
+	pub fn get_command_keys(&self) -> HashMap<String, String> {
+		let mut command_keys = HashMap::new();
+		for binding in self.bindings.iter().rev() {
+			command_keys
+				.entry(binding.command.to_owned())
+				.or_insert_with(|| format_keys(&binding.key));
+		}
+		command_keys
+	}
+
+	/// Processes a key press. `is_context` tells, whether a context key used in
+	/// `when` clauses is set.
+	pub fn resolve(&mut self, key: i32, is_context: &dyn Fn(&str) -> bool) -> KeyResult {
+		let mut keys = std::mem::replace(&mut self.pending, Vec::new());
+		let was_pending = !keys.is_empty();
+		keys.push(key);
+		// The newest binding decides, so a newer chord hides an older binding
+		// of its first key and the other way around.
+		let binding = self.bindings.iter().rev().find(|binding| {
+			binding.keys.starts_with(&keys) && is_when(binding.when.as_deref(), is_context)
+		});
+		match binding {
+			Some(binding) if binding.keys.len() == keys.len() => {
+				KeyResult::Command(binding.command.to_owned())
+			}
+			Some(_binding) => {
+				self.pending = keys;
+				KeyResult::Pending
+			}
+			None if was_pending => KeyResult::Cancelled,
+			None => KeyResult::None,
+		}
+	}
+
+	pub fn is_pending(&self) -> bool {
+		!self.pending.is_empty()
+	}
+}
+
+/// Parses a key or a chord separated by spaces, e.g. `ctrl+k ctrl+s`.
+fn parse_keys(keys: &str, key_names: &HashMap<String, i32>) -> Option<Vec<i32>> {
+	keys.split_whitespace()
+		.map(|key| parse_key(key, key_names))
+		.collect()
+}
+
+fn parse_key(key: &str, key_names: &HashMap<String, i32>) -> Option<i32> {
+	let mut key = key;
+	let mut modifier = 0;
+	loop {
+		let mut found_modifier = false;
+		if key.starts_with("alt+") {
+			key = &key["alt+".len()..];
+			modifier |= ALT;
+			found_modifier = true;
+		}
+		if key.starts_with("shift+") {
+			key = &key["shift+".len()..];
+			modifier |= SHIFT;
+			found_modifier = true;
+		}
+		if key.starts_with("ctrl+") {
+			key = &key["ctrl+".len()..];
+			modifier |= CTRL;
+			found_modifier = true;
+		}
+		if !found_modifier {
+			break;
+		}
+	}
+	if let Some(key_index) = key_names.get(key) {
+		Some(modifier | *key_index)
+	} else {
+		None
+	}
+}
+
+/// Evaluates a `when` clause like `paneFocus && !quickSearchActive`. `||`
+/// binds weaker than `&&`. A missing clause is always true.
+fn is_when(when: Option<&str>, is_context: &dyn Fn(&str) -> bool) -> bool {
+	let when = match when {
+		Some(when) if !when.trim().is_empty() => when,
+		_ => return true,
+	};
+	when.split("||").any(|conjunction| {
+		conjunction.split("&&").all(|term| {
+			let term = term.trim();
+			if term.starts_with('!') {
+				!is_context(term[1..].trim())
+			} else {
+				is_context(term)
+			}
+		})
+	})
+}
+
+/// Formats a key binding for display, e.g. `Ctrl+K Ctrl+S` for `ctrl+k ctrl+s`.
+fn format_keys(keys: &str) -> String {
+	keys.split_whitespace()
+		.map(|key| {
+			key.split('+')
+				.map(|part| {
+					let mut chars = part.chars();
+					match chars.next() {
+						Some(first) => first.to_uppercase().chain(chars).collect(),
+						None => String::new(),
+					}
+				})
+				.collect::<Vec<String>>()
+				.join("+")
+		})
+		.collect::<Vec<String>>()
+		.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const K: i32 = 75;
+	const S: i32 = 83;
+
+	fn key_names() -> HashMap<String, i32> {
+		vec![("k".to_owned(), K), ("s".to_owned(), S)]
+			.into_iter()
+			.collect()
+	}
+
+	fn binding(key: &str, command: &str, when: Option<&str>) -> KeyBinding {
+		KeyBinding {
+			key: key.to_owned(),
+			command: command.to_owned(),
+			when: when.map(str::to_owned),
+		}
+	}
+
+	fn is_command(result: KeyResult, command: &str) -> bool {
+		matches!(result, KeyResult::Command(ref x) if x == command)
+	}
+
+	#[test]
+	fn chords_are_pending_until_complete() {
+		let mut key_bindings = KeyBindings::default();
+		key_bindings.add(&[binding("ctrl+k ctrl+s", "save", None)], &key_names());
+		let always = |_name: &str| true;
+		assert!(matches!(
+			key_bindings.resolve(CTRL | K, &always),
+			KeyResult::Pending
+		));
+		assert!(key_bindings.is_pending());
+		assert!(is_command(key_bindings.resolve(CTRL | S, &always), "save"));
+		assert!(!key_bindings.is_pending());
+
+		assert!(matches!(
+			key_bindings.resolve(CTRL | K, &always),
+			KeyResult::Pending
+		));
+		assert!(matches!(
+			key_bindings.resolve(S, &always),
+			KeyResult::Cancelled
+		));
+		assert!(!key_bindings.is_pending());
+		assert!(matches!(key_bindings.resolve(S, &always), KeyResult::None));
+	}
+
+	#[test]
+	fn newer_bindings_take_precedence() {
+		let always = |_name: &str| true;
+		let mut key_bindings = KeyBindings::default();
+		key_bindings.add(&[binding("ctrl+k", "kill", None)], &key_names());
+		key_bindings.add(&[binding("ctrl+k ctrl+s", "save", None)], &key_names());
+		assert!(matches!(
+			key_bindings.resolve(CTRL | K, &always),
+			KeyResult::Pending
+		));
+		assert!(is_command(key_bindings.resolve(CTRL | S, &always), "save"));
+
+		key_bindings.add(&[binding("ctrl+k", "kill all", None)], &key_names());
+		assert!(is_command(
+			key_bindings.resolve(CTRL | K, &always),
+			"kill all"
+		));
+	}
+
+	#[test]
+	fn when_clauses_are_evaluated() {
+		let is_context = |name: &str| name == "a" || name == "b";
+		assert!(is_when(None, &is_context));
+		assert!(is_when(Some(" "), &is_context));
+		assert!(is_when(Some("a && b"), &is_context));
+		assert!(!is_when(Some("a && c"), &is_context));
+		assert!(is_when(Some("c || a"), &is_context));
+		assert!(is_when(Some("!c"), &is_context));
+		assert!(!is_when(Some("! a"), &is_context));
+		assert!(!is_when(Some("c || a && !b"), &is_context));
+		assert!(is_when(Some("c || a && !d"), &is_context));
+
+		let mut key_bindings = KeyBindings::default();
+		key_bindings.add(
+			&[
+				binding("ctrl+s", "save", Some("a")),
+				binding("ctrl+s", "search", Some("c")),
+			],
+			&key_names(),
+		);
+		assert!(is_command(
+			key_bindings.resolve(CTRL | S, &is_context),
+			"save"
+		));
+	}
+
+	#[test]
+	fn bindings_are_removed_by_command_and_when() {
+		let always = |_name: &str| true;
+		let mut key_bindings = KeyBindings::default();
+		let defaults = [
+			binding("ctrl+s", "save", Some("a")),
+			binding("ctrl+s", "save", Some("b")),
+			binding("s", "save", None),
+		];
+		key_bindings.add(&defaults, &key_names());
+		key_bindings.add(&[binding("ctrl+s", "-save", Some("b"))], &key_names());
+		let is_b = |name: &str| name == "b";
+		assert!(matches!(
+			key_bindings.resolve(CTRL | S, &is_b),
+			KeyResult::None
+		));
+		let is_a = |name: &str| name == "a";
+		assert!(is_command(key_bindings.resolve(CTRL | S, &is_a), "save"));
+
+		key_bindings.add(&[binding("ctrl+s", "-save", None)], &key_names());
+		assert!(matches!(
+			key_bindings.resolve(CTRL | S, &always),
+			KeyResult::None
+		));
+		assert!(is_command(key_bindings.resolve(S, &always), "save"));
+		key_bindings.add(&[binding("s", "-other", None)], &key_names());
+		assert!(is_command(key_bindings.resolve(S, &always), "save"));
+	}
+}
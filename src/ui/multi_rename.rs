@@ -0,0 +1,566 @@
+use crate::ui::pane::{get_path_directory, get_path_filename};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use regex::{NoExpand, Regex, RegexBuilder};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use xcmd_core::api::{Error, System};
+
+/// Appended to the paths of files moved out of the way of another rename.
+const TEMPORARY_SUFFIX: &str = ".xcmd-rename~";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Case {
+	Unchanged,
+	Lower,
+	Upper,
+	Title,
+}
+
+/// The settings of the multi-rename dialog.
+pub struct RenameOptions {
+	/// Like `[N]_[C:3].[E]`, see `Renamer`.
+	pub template: String,
+	pub search: String,
+	pub replace: String,
+	pub regex: bool,
+	pub match_case: bool,
+	pub case: Case,
+	pub counter_start: i64,
+	pub counter_step: i64,
+}
+
+/// Characters of the name or the extension, 1-based and inclusive.
+#[derive(Clone, Copy)]
+struct Range {
+	start: usize,
+	end: Option<usize>,
+}
+
+enum Token {
+	Text(String),
+	Name(Option<Range>),
+	Extension(Option<Range>),
+	Counter(usize),
+	Date(char),
+	Parent,
+}
+
+/// Makes new names from a template with placeholders:
+///
+/// - `[N]` the name without extension, `[N2-5]`, `[N3-]` and `[N1]` parts of it,
+/// - `[E]` the extension, with the same ranges,
+/// - `[C]` a counter, `[C:3]` padded with zeros to 3 digits,
+/// - `[Y]`, `[M]`, `[D]`, `[h]`, `[m]` and `[s]` the modification time,
+/// - `[P]` the name of the parent directory.
+///
+/// Other brackets are kept. The search and the case change apply to the
+/// whole result.
+pub struct Renamer {
+	tokens: Vec<Token>,
+	search: Option<Regex>,
+	replace: String,
+	regex: bool,
+	case: Case,
+	counter_start: i64,
+	counter_step: i64,
+}
+
+impl Renamer {
+	pub fn new(options: &RenameOptions) -> Result<Self, String> {
+		let search = if options.search.is_empty() {
+			None
+		} else {
+			let pattern = if options.regex {
+				options.search.clone()
+			} else {
+				regex::escape(&options.search)
+			};
+			let regex = RegexBuilder::new(&pattern)
+				.case_insensitive(!options.match_case)
+				.build()
+				.map_err(|e| format!("Invalid search pattern: {}", e))?;
+			Some(regex)
+		};
+		Ok(Renamer {
+			tokens: parse_template(&options.template),
+			search,
+			replace: options.replace.clone(),
+			regex: options.regex,
+			case: options.case,
+			counter_start: options.counter_start,
+			counter_step: options.counter_step,
+		})
+	}
+
+	/// Returns the new name of the item at the index of the batch.
+	fn rename(&self, index: usize, item: &RenameItem) -> String {
+		let (name, extension) = split_extension(&item.name);
+		let modified = item.modified.map(DateTime::<Local>::from);
+		let mut new_name = String::new();
+		for token in &self.tokens {
+			match token {
+				Token::Text(text) => new_name.push_str(text),
+				Token::Name(range) => new_name.push_str(&get_range(name, *range)),
+				Token::Extension(range) => new_name.push_str(&get_range(extension, *range)),
+				Token::Counter(width) => {
+					let counter = self.counter_start + index as i64 * self.counter_step;
+					new_name.push_str(&format!("{:01$}", counter, width));
+				}
+				Token::Date(placeholder) => {
+					if let Some(modified) = modified {
+						new_name.push_str(&format_date(&modified, *placeholder));
+					}
+				}
+				Token::Parent => new_name.push_str(&item.parent_name),
+			}
+		}
+		if let Some(search) = &self.search {
+			new_name = if self.regex {
+				search.replace_all(&new_name, self.replace.as_str())
+			} else {
+				search.replace_all(&new_name, NoExpand(&self.replace))
+			}
+			.into_owned();
+		}
+		change_case(&new_name, self.case)
+	}
+}
+
+fn parse_template(template: &str) -> Vec<Token> {
+	let regex = Regex::new(r"\[(?:([NE])(?:(\d+)(-(\d*))?)?|C(?::(\d+))?|([YMDhms])|(P))\]")
+		.expect("regex");
+	let mut tokens = Vec::new();
+	let mut last_end = 0;
+	for captures in regex.captures_iter(template) {
+		let whole = captures.get(0).expect("capture 0");
+		if whole.start() > last_end {
+			tokens.push(Token::Text(template[last_end..whole.start()].to_owned()));
+		}
+		last_end = whole.end();
+		let get_number = |index: usize| {
+			captures
+				.get(index)
+				.and_then(|number| number.as_str().parse::<usize>().ok())
+		};
+		let token = if let Some(part) = captures.get(1) {
+			let range = get_number(2).map(|start| Range {
+				start,
+				end: match captures.get(3) {
+					Some(_) => get_number(4),
+					None => Some(start),
+				},
+			});
+			if part.as_str() == "N" {
+				Token::Name(range)
+			} else {
+				Token::Extension(range)
+			}
+		} else if let Some(placeholder) = captures.get(6) {
+			Token::Date(placeholder.as_str().chars().next().expect("placeholder"))
+		} else if captures.get(7).is_some() {
+			Token::Parent
+		} else {
+			Token::Counter(get_number(5).unwrap_or(0))
+		};
+		tokens.push(token);
+	}
+	if last_end < template.len() {
+		tokens.push(Token::Text(template[last_end..].to_owned()));
+	}
+	tokens
+}
+
+/// Splits at the last dot, unless the name starts with it, e.g. `.profile`.
+fn split_extension(name: &str) -> (&str, &str) {
+	match name.rfind('.') {
+		Some(index) if index > 0 => (&name[..index], &name[index + 1..]),
+		_ => (name, ""),
+	}
+}
+
+fn get_range(text: &str, range: Option<Range>) -> String {
+	match range {
+		Some(range) => {
+			let start = range.start.max(1) - 1;
+			let chars = text.chars().skip(start);
+			match range.end {
+				Some(end) if end > start => chars.take(end - start).collect(),
+				Some(_) => String::new(),
+				None => chars.collect(),
+			}
+		}
+		None => text.to_owned(),
+	}
+}
+
+fn format_date(date: &DateTime<Local>, placeholder: char) -> String {
+	match placeholder {
+		'Y' => format!("{:04}", date.year()),
+		'M' => format!("{:02}", date.month()),
+		'D' => format!("{:02}", date.day()),
+		'h' => format!("{:02}", date.hour()),
+		'm' => format!("{:02}", date.minute()),
+		_ => format!("{:02}", date.second()),
+	}
+}
+
+fn change_case(name: &str, case: Case) -> String {
+	match case {
+		Case::Unchanged => name.to_owned(),
+		Case::Lower => name.to_lowercase(),
+		Case::Upper => name.to_uppercase(),
+		Case::Title => {
+			let mut title = String::with_capacity(name.len());
+			let mut word_start = true;
+			for c in name.chars() {
+				if word_start {
+					title.extend(c.to_uppercase());
+				} else {
+					title.extend(c.to_lowercase());
+				}
+				word_start = !c.is_alphanumeric() && c != '\'';
+			}
+			title
+		}
+	}
+}
+
+fn is_valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name != "."
+		&& name != ".."
+		&& !name.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+}
+
+struct RenameItem {
+	path: String,
+	directory: String,
+	name: String,
+	parent_name: String,
+	modified: Option<SystemTime>,
+}
+
+/// A row of the preview, with the reason the file cannot be renamed.
+pub struct RenamePreview {
+	pub name: String,
+	pub new_name: String,
+	pub problem: Option<&'static str>,
+}
+
+/// The files to rename, with the names already taken in their directories.
+pub struct RenameBatch {
+	items: Vec<RenameItem>,
+	existing_names: HashSet<(String, String)>,
+}
+
+impl RenameBatch {
+	pub fn load(system: &mut dyn System, paths: &[String]) -> Result<Self, Error> {
+		let mut items = Vec::new();
+		let mut parent_names = HashMap::new();
+		let mut existing_names = HashSet::new();
+		for path in paths {
+			let directory = get_path_directory(path).to_owned();
+			if !parent_names.contains_key(&directory) {
+				for (child_path, _metadata) in system.read_dir(&directory)? {
+					let name = get_path_filename(&child_path).to_owned();
+					existing_names.insert((directory.clone(), name));
+				}
+				let parent_name = system.get_filename(&directory);
+				parent_names.insert(directory.clone(), parent_name);
+			}
+			items.push(RenameItem {
+				path: path.clone(),
+				name: get_path_filename(path).to_owned(),
+				parent_name: parent_names[&directory].clone(),
+				modified: system.get_metadata(path)?.modified,
+				directory,
+			});
+		}
+		Ok(RenameBatch {
+			items,
+			existing_names,
+		})
+	}
+
+	/// Returns the new names, checked for names that are invalid, used twice
+	/// or taken by files staying in place.
+	pub fn preview(&self, renamer: &Renamer) -> Vec<RenamePreview> {
+		let new_names: Vec<String> = self
+			.items
+			.iter()
+			.enumerate()
+			.map(|(index, item)| renamer.rename(index, item))
+			.collect();
+		let sources: HashSet<(&str, &str)> = self
+			.items
+			.iter()
+			.map(|item| (item.directory.as_str(), item.name.as_str()))
+			.collect();
+		let mut counts = HashMap::new();
+		for (item, new_name) in self.items.iter().zip(&new_names) {
+			*counts
+				.entry((item.directory.as_str(), new_name.as_str()))
+				.or_insert(0) += 1;
+		}
+		self.items
+			.iter()
+			.zip(&new_names)
+			.map(|(item, new_name)| {
+				let target = (item.directory.as_str(), new_name.as_str());
+				let problem = if !is_valid_name(new_name) {
+					Some("Invalid name")
+				} else if counts[&target] > 1 {
+					Some("Duplicate name")
+				} else if new_name != &item.name
+					&& !sources.contains(&target)
+					&& self
+						.existing_names
+						.contains(&(item.directory.clone(), new_name.clone()))
+				{
+					Some("File already exists")
+				} else {
+					None
+				};
+				RenamePreview {
+					name: item.name.clone(),
+					new_name: new_name.clone(),
+					problem,
+				}
+			})
+			.collect()
+	}
+
+	/// Returns the old and the new paths of the files whose name changes.
+	pub fn get_renames(
+		&self,
+		system: &mut dyn System,
+		previews: &[RenamePreview],
+	) -> Vec<(String, String)> {
+		self.items
+			.iter()
+			.zip(previews)
+			.filter(|(item, preview)| preview.new_name != item.name)
+			.map(|(item, preview)| {
+				let new_path = system.join_path(&item.directory, &preview.new_name);
+				(item.path.clone(), new_path)
+			})
+			.collect()
+	}
+}
+
+/// The renames done last, to be undone.
+pub struct RenameHistory {
+	pub system: Arc<Mutex<Box<dyn System>>>,
+	pub renames: Vec<(String, String)>,
+}
+
+/// Renames all files or none. Files taking the name of another file of the
+/// batch are moved out of the way first, so names can be swapped.
+pub fn apply_renames(system: &mut dyn System, renames: &[(String, String)]) -> Result<(), Error> {
+	let targets: HashSet<&str> = renames
+		.iter()
+		.map(|(_path, new_path)| new_path.as_str())
+		.collect();
+	let mut steps = Vec::new();
+	let mut final_steps = Vec::new();
+	for (index, (path, new_path)) in renames.iter().enumerate() {
+		if targets.contains(path.as_str()) {
+			let temporary_path = format!("{}{}{}", path, TEMPORARY_SUFFIX, index);
+			steps.push((path.clone(), temporary_path.clone()));
+			final_steps.push((temporary_path, new_path.clone()));
+		} else {
+			final_steps.push((path.clone(), new_path.clone()));
+		}
+	}
+	steps.extend(final_steps);
+	for (index, (path, new_path)) in steps.iter().enumerate() {
+		if let Err(e) = system.rename(path, new_path) {
+			for (path, new_path) in steps[..index].iter().rev() {
+				if let Err(e) = system.rename(new_path, path) {
+					log::error!("Failed to rename {} back to {}: {}", new_path, path, e);
+				}
+			}
+			return Err(e);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::path::{Path, PathBuf};
+	use xcmd_core::local::LocalSystem;
+
+	fn options(template: &str) -> RenameOptions {
+		RenameOptions {
+			template: template.to_owned(),
+			search: String::new(),
+			replace: String::new(),
+			regex: false,
+			match_case: false,
+			case: Case::Unchanged,
+			counter_start: 1,
+			counter_step: 1,
+		}
+	}
+
+	fn rename(options: &RenameOptions, index: usize, name: &str) -> String {
+		let item = RenameItem {
+			path: format!("/photos/{}", name),
+			directory: "/photos".to_owned(),
+			name: name.to_owned(),
+			parent_name: "photos".to_owned(),
+			modified: None,
+		};
+		Renamer::new(options).unwrap().rename(index, &item)
+	}
+
+	fn range(start: usize, end: Option<usize>) -> Option<Range> {
+		Some(Range { start, end })
+	}
+
+	#[test]
+	fn ranges_take_characters_counted_from_one() {
+		assert_eq!(get_range("abcdef", None), "abcdef");
+		assert_eq!(get_range("abcdef", range(2, Some(4))), "bcd");
+		assert_eq!(get_range("abcdef", range(3, None)), "cdef");
+		assert_eq!(get_range("abcdef", range(1, Some(1))), "a");
+		assert_eq!(get_range("abcdef", range(5, Some(10))), "ef");
+		assert_eq!(get_range("abcdef", range(4, Some(2))), "");
+		assert_eq!(get_range("äöü", range(2, Some(2))), "ö");
+	}
+
+	#[test]
+	fn templates_replace_placeholders_and_keep_other_text() {
+		assert_eq!(
+			rename(&options("[N]_[C:3].[E]"), 4, "image.jpeg"),
+			"image_005.jpeg"
+		);
+		assert_eq!(
+			rename(&options("[P]-[N2-3][E1]"), 0, "image.jpeg"),
+			"photos-maj"
+		);
+		assert_eq!(rename(&options("[X] [N3-]"), 0, "image.jpeg"), "[X] age");
+		assert_eq!(rename(&options("[N].[E]"), 0, ".profile"), ".profile.");
+		assert_eq!(rename(&options("[C]"), 2, "a"), "3");
+	}
+
+	#[test]
+	fn search_and_case_apply_to_the_result() {
+		let mut options = options("[N].[E]");
+		options.search = "IMG".to_owned();
+		options.replace = "$0photo".to_owned();
+		options.case = Case::Title;
+		assert_eq!(rename(&options, 0, "img_1.JPG"), "$0photo_1.Jpg");
+		options.regex = true;
+		options.search = r"img_(\d+)".to_owned();
+		options.replace = "${1}-x".to_owned();
+		options.case = Case::Unchanged;
+		assert_eq!(rename(&options, 0, "img_12.jpg"), "12-x.jpg");
+	}
+
+	/// A directory with a file for each name, containing its name.
+	fn create_files(test_name: &str, names: &[&str]) -> PathBuf {
+		let directory =
+			std::env::temp_dir().join(format!("xcmd-rename-{}-{}", std::process::id(), test_name));
+		let _ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(&directory).unwrap();
+		for name in names {
+			fs::write(directory.join(name), name).unwrap();
+		}
+		directory
+	}
+
+	fn read_files(directory: &Path) -> Vec<(String, String)> {
+		let mut files: Vec<(String, String)> = fs::read_dir(directory)
+			.unwrap()
+			.map(|entry| {
+				let path = entry.unwrap().path();
+				(
+					path.file_name().unwrap().to_string_lossy().into_owned(),
+					fs::read_to_string(&path).unwrap(),
+				)
+			})
+			.collect();
+		files.sort();
+		files
+	}
+
+	fn get_path(directory: &Path, name: &str) -> String {
+		directory.join(name).to_string_lossy().into_owned()
+	}
+
+	#[test]
+	fn names_within_the_batch_can_be_swapped() {
+		let directory = create_files("swap", &["a", "b", "c"]);
+		let renames = vec![
+			(get_path(&directory, "a"), get_path(&directory, "b")),
+			(get_path(&directory, "b"), get_path(&directory, "a")),
+			(get_path(&directory, "c"), get_path(&directory, "d")),
+		];
+		apply_renames(&mut LocalSystem::default(), &renames).unwrap();
+		assert_eq!(
+			read_files(&directory),
+			[
+				("a".to_owned(), "b".to_owned()),
+				("b".to_owned(), "a".to_owned()),
+				("d".to_owned(), "c".to_owned()),
+			]
+		);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn failed_renames_are_rolled_back() {
+		let directory = create_files("rollback", &["a", "b"]);
+		let renames = vec![
+			(get_path(&directory, "a"), get_path(&directory, "b")),
+			(get_path(&directory, "b"), get_path(&directory, "a")),
+			(get_path(&directory, "missing"), get_path(&directory, "c")),
+		];
+		assert!(apply_renames(&mut LocalSystem::default(), &renames).is_err());
+		assert_eq!(
+			read_files(&directory),
+			[
+				("a".to_owned(), "a".to_owned()),
+				("b".to_owned(), "b".to_owned()),
+			]
+		);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn preview_reports_invalid_duplicate_and_taken_names() {
+		let directory = create_files("preview", &["a.txt", "b.txt", "c.txt", "taken"]);
+		let paths = vec![
+			get_path(&directory, "a.txt"),
+			get_path(&directory, "b.txt"),
+			get_path(&directory, "c.txt"),
+		];
+		let mut system = LocalSystem::default();
+		let batch = RenameBatch::load(&mut system, &paths).unwrap();
+		let problems = |template: &str| {
+			batch
+				.preview(&Renamer::new(&options(template)).unwrap())
+				.into_iter()
+				.map(|preview| preview.problem)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(problems("[N]"), [None, None, None]);
+		assert_eq!(problems("x"), [Some("Duplicate name"); 3]);
+		assert_eq!(problems("[N]/"), [Some("Invalid name"); 3]);
+		let renamer = Renamer::new(&options("taken")).unwrap();
+		assert_eq!(batch.preview(&renamer)[0].problem, Some("Duplicate name"));
+		let previews: Vec<_> = RenameBatch::load(&mut system, &paths[..1])
+			.unwrap()
+			.preview(&renamer)
+			.into_iter()
+			.map(|preview| preview.problem)
+			.collect();
+		assert_eq!(previews, [Some("File already exists")]);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+}
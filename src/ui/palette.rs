@@ -1,49 +1,82 @@
 use sciter::Element;
 
+/// Command shown in the palette.
+pub struct PaletteItem {
+	pub command: String,
+	pub title: String,
+	pub key: Option<String>,
+}
+
 pub struct Palette {
 	active: bool,
 	pub active_index: u32,
 	palette: Element,
 	input: Element,
 	tbody: Element,
+	items: Vec<PaletteItem>,
+	query: String,
+	/// Indices of the items matching the query, best match first.
+	matches: Vec<usize>,
 }
 
 impl Palette {
 	pub fn new(element: &mut Element) -> Palette {
-		let mut palette = Palette {
+		Palette {
 			active: false,
 			active_index: 0,
 			palette: Element::from(element.as_ptr()),
 			input: element.find_first("input").unwrap().unwrap(),
 			tbody: element.find_first("tbody").unwrap().unwrap(),
-		};
-		let commands = vec![
-			"Edit File",
-			"Enter Item",
-			"Exit",
-			"Hide Palette",
-			"Move Down",
-			"Move Home",
-			"Move Page Down",
-			"Move Page Up",
-			"Move Up",
-			"Move to Line Start",
-			"Move to Line End",
-			"Select Up",
-			"Select Down",
-			"Show Palette",
-			"Switch Pane",
-			"Toggle Selection",
-			"Update Application",
-			"View File",
-		];
-		for (index, command) in commands.iter().enumerate() {
-			palette.append_row(command, index as u32);
+			items: Vec::new(),
+			query: String::new(),
+			matches: Vec::new(),
+		}
+	}
+
+	pub fn set_items(&mut self, mut items: Vec<PaletteItem>) {
+		items.sort_by(|a, b| a.title.cmp(&b.title));
+		self.items = items;
+		self.filter();
+	}
+
+	pub fn push_query(&mut self, c: char) {
+		self.query.push(c);
+		self.filter();
+	}
+
+	pub fn pop_query(&mut self) {
+		self.query.pop();
+		self.filter();
+	}
+
+	pub fn get_active_command(&self) -> Option<&str> {
+		self.matches
+			.get(self.active_index as usize)
+			.map(|index| self.items[*index].command.as_str())
+	}
+
+	fn filter(&mut self) {
+		self.input.set_text(&format!(">{}", self.query)).unwrap();
+		let mut matches: Vec<(i32, usize)> = self
+			.items
+			.iter()
+			.enumerate()
+			.filter_map(|(index, item)| fuzzy_match(&self.query, &item.title).map(|x| (x, index)))
+			.collect();
+		// Items are sorted by title, so equal scores keep that order.
+		matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+		self.matches = matches.into_iter().map(|(_score, index)| index).collect();
+
+		self.tbody.clear().unwrap();
+		self.active_index = 0;
+		for row_index in 0..self.matches.len() {
+			let item = &self.items[self.matches[row_index]];
+			let (title, key) = (item.title.clone(), item.key.clone().unwrap_or_default());
+			self.append_row(&title, &key, row_index as u32);
 		}
-		palette
 	}
 
-	fn append_row(&mut self, text: &str, index: u32) {
+	fn append_row(&mut self, text: &str, key: &str, index: u32) {
 		let mut row = Element::create("tr").unwrap();
 		if index == self.active_index {
 			row.set_attribute("active", "true").unwrap();
@@ -52,15 +85,24 @@ impl Palette {
 
 		let cell = Element::with_text("td", text).unwrap();
 		row.append(&cell).unwrap();
+		let mut key_cell = Element::with_text("td", key).unwrap();
+		row.append(&key_cell).unwrap();
+		key_cell.set_attribute("class", "align-right").unwrap();
 	}
 
 	pub fn get_item(&self, index: u32) -> Option<Element> {
 		self.tbody.child(index as usize)
 	}
 
+	pub fn is_active(&self) -> bool {
+		self.active
+	}
+
 	pub fn activate(&mut self, active: bool) {
 		self.active = active;
 		if self.active {
+			self.query.clear();
+			self.filter();
 			self.palette.set_attribute("active", "true").unwrap();
 		} else {
 			self.palette.remove_attribute("active").unwrap();
@@ -86,12 +128,12 @@ impl Palette {
 	}
 
 	pub fn move_end(&mut self) {
-		// if !self.files.is_empty() {
-		// 	let active_index = self.files.len() as u32 - 1;
-		// 	if self.active_index != active_index {
-		// 		self.set_active_item(active_index);
-		// 	}
-		// }
+		if !self.matches.is_empty() {
+			let active_index = self.matches.len() as u32 - 1;
+			if self.active_index != active_index {
+				self.set_active_item(active_index);
+			}
+		}
 	}
 
 	pub fn page_up(&mut self) {
@@ -143,7 +185,7 @@ impl Palette {
 		}
 	}
 
-	fn set_active_item(&mut self, active_index: u32) {
+	pub fn set_active_item(&mut self, active_index: u32) {
 		if let Some(mut new_item) = self.get_item(active_index) {
 			if let Some(mut old_item) = self.get_item(self.active_index) {
 				old_item.remove_attribute("active").expect("remove active");
@@ -158,3 +200,41 @@ impl Palette {
 		}
 	}
 }
+
+/// Matches the characters of the query in order, e.g. "cpf" matches "Copy
+/// File". Matches at word starts and consecutive characters score higher.
+fn fuzzy_match(query: &str, text: &str) -> Option<i32> {
+	let mut query_chars = query
+		.chars()
+		.filter(|c| !c.is_whitespace())
+		.flat_map(char::to_lowercase)
+		.peekable();
+	let mut score = 0;
+	let mut previous: Option<char> = None;
+	let mut consecutive = false;
+	for c in text.chars() {
+		let next = match query_chars.peek() {
+			Some(next) => *next,
+			None => break,
+		};
+		if c.to_lowercase().eq(std::iter::once(next)) {
+			query_chars.next();
+			score += 1;
+			if previous.map_or(true, |x| !x.is_alphanumeric()) {
+				score += 8;
+			}
+			if consecutive {
+				score += 4;
+			}
+			consecutive = true;
+		} else {
+			consecutive = false;
+		}
+		previous = Some(c);
+	}
+	if query_chars.peek().is_none() {
+		Some(score)
+	} else {
+		None
+	}
+}
@@ -1,89 +1,275 @@
 use crate::data_source::DataSource;
-use crate::ui::Column;
+use crate::settings::{ColumnSettings, PaneSettings, SortSettings, TabSettings};
+use crate::ui::quick_search::glob_to_regex;
+use crate::ui::{Column, JobMessage, Matcher, QuickSearch, SortOrder};
+use chrono::{DateTime, Local, Locale};
+use regex::RegexBuilder;
 use sciter::dom::event::{BEHAVIOR_EVENTS, CLICK_REASON};
+use sciter::dom::ELEMENT_STATE_BITS;
 use sciter::Element;
 use separator::Separatable;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+use std::path::{self, Path};
 use std::rc::Rc;
-use xcmd_core::api::{Error, File, Icon, System, Value};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use xcmd_core::api::{Error, File, Icon, System, Value, WatchEvent, Watcher};
+use xcmd_core::archive::{self, ArchiveSystem};
+use xcmd_core::jobs::JobSpawner;
+use xcmd_core::usage::Usage;
+
+/// Files of a directory, listed on a worker thread.
+pub struct Listing {
+	filename: String,
+	files: Vec<File>,
+	active_index: Option<usize>,
+}
+
+/// Files of the current directory changed by other programs, read again on a
+/// worker thread.
+pub struct Changes {
+	files: Vec<File>,
+	removed_paths: HashSet<String>,
+}
+
+const HISTORY_LIMIT: usize = 50;
+
+/// How often directories of systems, which cannot be watched, are listed.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A directory opened in a pane. The files of the current tab are kept in the
+/// data source of the pane, the other tabs keep theirs until they are shown.
+pub struct Tab {
+	system: Arc<Mutex<Box<dyn System>>>,
+	/// Name of the connection profile of the system, if it is remote.
+	connection: Option<String>,
+	parent: String,
+	title: String,
+	files: Vec<File>,
+	active_index: u32,
+	sort_order: SortOrder,
+	/// False until a listing of the current directory is received.
+	loaded: bool,
+	/// Directories left by navigating, the most recent last.
+	back: Vec<String>,
+	/// Directories left by going back, the most recent last.
+	forward: Vec<String>,
+}
+
+impl Tab {
+	pub fn new(system: Box<dyn System>, connection: Option<String>, parent: String) -> Self {
+		Tab {
+			system: Arc::new(Mutex::new(system)),
+			connection,
+			title: get_path_filename(&parent).to_owned(),
+			parent,
+			files: Vec::new(),
+			active_index: 0,
+			sort_order: SortOrder::default(),
+			loaded: false,
+			back: Vec::new(),
+			forward: Vec::new(),
+		}
+	}
+}
 
 pub struct Pane {
+	index: u8,
 	active: bool,
-	pub system: Box<dyn System>,
-	field_names: Vec<String>,
+	tabs: Vec<Tab>,
+	current_tab: usize,
+	jobs: JobSpawner<JobMessage>,
+	generation: u64,
+	/// Watches the directory of the current tab, once it is listed.
+	watcher: Option<(Watcher, Receiver<WatchEvent>)>,
+	/// When to list the directory again, if it cannot be watched.
+	next_poll: Option<Instant>,
+	/// True while the current directory is listed again to apply changes.
+	reloading: bool,
+	/// True while the changed files are read again.
+	reading_changes: bool,
+	/// Changed, whenever the calculated directory sizes are dropped.
+	sizes_generation: u64,
+	quick_search: Option<QuickSearch>,
+	field_names: Arc<Vec<String>>,
 	pub data_source: Rc<RefCell<FilesDataSource>>,
-	parent: String,
 	pane: Element,
-	tab: Element,
+	tabs_element: Element,
 	input: Element,
+	/// True while the path bar has the focus.
+	editing_path: bool,
 	vtable: Element,
+	status: Element,
 	files_height: i32,
 	item_height: i32,
 }
 
 impl Pane {
-	pub fn new(element: &mut Element, active: bool, mut system: Box<dyn System>) -> Pane {
+	pub fn new(
+		element: &mut Element,
+		index: u8,
+		active: bool,
+		system: Box<dyn System>,
+		jobs: JobSpawner<JobMessage>,
+	) -> Pane {
 		let mut field_names: Vec<String> = Vec::new();
 		field_names.push("path".to_owned());
 		field_names.push("extension".to_owned());
 		field_names.push("size".to_owned());
+		field_names.push("modified_on".to_owned());
 		field_names.push("created_on".to_owned());
 		field_names.push("attributes".to_owned());
-		let root = &system.get_root(&Rc::new(field_names.clone())).unwrap();
+		field_names.push("owner".to_owned());
+		field_names.push("link_target".to_owned());
+		let field_names = Arc::new(field_names);
+		let mut system: Box<dyn System> = Box::new(ArchiveSystem::new(system));
+		let root = &system.get_root(&field_names).unwrap();
 		let parent_path = get_path(&root);
 		log::info!("parent_path={}", parent_path);
 		let mut pane = Pane {
+			index,
 			active,
-			system,
+			tabs: vec![Tab::new(system, None, parent_path)],
+			current_tab: 0,
+			jobs,
+			generation: 0,
+			watcher: None,
+			next_poll: None,
+			reloading: false,
+			reading_changes: false,
+			sizes_generation: 0,
+			quick_search: None,
 			data_source: Rc::new(RefCell::new(FilesDataSource::new())),
 			field_names,
-			parent: parent_path,
 			pane: Element::from(element.as_ptr()),
-			tab: element.find_first("tab").unwrap().unwrap(),
+			tabs_element: element.find_first("tabs").unwrap().unwrap(),
 			input: element.find_first("input").unwrap().unwrap(),
+			editing_path: false,
 			vtable: element.find_first("vtable").unwrap().unwrap(),
+			status: element.find_first("status").unwrap().unwrap(),
 			files_height: 640,
 			item_height: 32,
 		};
 		if pane.active {
 			element.set_attribute("class", "pane-active").unwrap();
-			pane.tab.set_attribute("class", "tab-active").unwrap();
 		}
+		pane.show_tabs();
 		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut pane.data_source) {
 			data_source.columns.push(Column::new("Name"));
 			data_source.columns.push(Column::new("Ext"));
 			data_source.columns.push(Column::new("Size"));
-			data_source.columns.push(Column::new("Date"));
+			data_source.columns.push(Column::new("Modified"));
+			data_source.columns.push(Column::new("Created"));
 			data_source.columns.push(Column::new("Attributes"));
+			data_source.columns.push(Column::new("Owner"));
+			data_source.columns.push(Column::new("Link"));
 		}
 		pane
 	}
 
+	/// Lists the files on a worker thread. The listing is shown, once the
+	/// window receives it and passes it to `set_listing`.
 	pub fn update(&mut self, selected_path: Option<&str>) {
-		let now = std::time::Instant::now();
-		let filename = &self.system.get_filename(&self.parent);
-		self.input.set_text(&self.parent).unwrap();
-		self.tab.set_text(filename).unwrap();
-		match self.list_files() {
-			Ok(files) => {
-				let mut active_index: Option<usize> = None;
-				let mut path_index: Option<usize> = None;
-				for (index, file) in files.iter().enumerate() {
-					if active_index == None {
-						path_index = path_index.or(file.get_field_index("path"));
-						if Some(get_path_at(&file, path_index).as_ref()) == selected_path {
-							active_index = Some(index);
-						}
+		self.show_parent();
+		self.generation += 1;
+		self.tab_mut().loaded = false;
+		self.stop_watching();
+		self.spawn_listing(selected_path);
+	}
+
+	/// Lists the current directory again and merges the files into the shown
+	/// ones, keeping the active item and the selection.
+	fn reload(&mut self) {
+		self.reloading = true;
+		self.spawn_listing(None);
+	}
+
+	fn spawn_listing(&mut self, selected_path: Option<&str>) {
+		let pane = self.index;
+		let generation = self.generation;
+		let system = Arc::clone(&self.tab().system);
+		let parent = self.tab().parent.clone();
+		let field_names = Arc::clone(&self.field_names);
+		let selected_path = selected_path.map(str::to_owned);
+		let sort_order = self.data_source.borrow().sort_order;
+		self.jobs
+			.spawn(&format!("List {}", parent), move |context| {
+				let now = std::time::Instant::now();
+				let listing = list_files(
+					&mut **lock_system(&system),
+					&parent,
+					&field_names,
+					sort_order,
+					selected_path.as_ref().map(String::as_str),
+				);
+				log::info!("{:?}", now.elapsed());
+				context.send(JobMessage::Listing {
+					pane,
+					generation,
+					listing,
+				});
+			});
+	}
+
+	pub fn set_listing(&mut self, generation: u64, listing: Result<Listing, Error>) {
+		if generation != self.generation {
+			// The pane moved on to another directory meanwhile.
+			return;
+		}
+		if std::mem::replace(&mut self.reloading, false) {
+			match listing {
+				Ok(listing) => self.change_files(|files| {
+					let selected_paths: HashSet<String> = files
+						.iter()
+						.filter(|file| file.selected)
+						.map(get_path)
+						.collect();
+					*files = listing.files;
+					for file in files.iter_mut() {
+						file.selected = selected_paths.contains(&get_path(file));
 					}
-				}
+				}),
+				Err(e) => log::warn!("Failed to list {} again: {}", self.get_parent(), e),
+			}
+			if self.next_poll.is_some() {
+				self.next_poll = Some(Instant::now() + POLL_INTERVAL);
+			}
+			return;
+		}
+		self.end_quick_search(false);
+		match listing {
+			Ok(mut listing) => {
+				let tab = self.tab_mut();
+				tab.title = listing.filename;
+				tab.loaded = true;
+				self.show_tabs();
 				if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
-					data_source.files = files;
+					let files = &mut listing.files;
+					let active_path = listing
+						.active_index
+						.and_then(|index| files.get(index))
+						.map(get_path);
+					// Sorting by size includes the calculated sizes.
+					if apply_directory_sizes(files, &data_source.directory_sizes) {
+						data_source.sort_order.sort(files);
+						listing.active_index = active_path.and_then(|active_path| {
+							files.iter().position(|file| get_path(file) == active_path)
+						});
+					}
+					data_source.files = listing.files;
+					data_source.hidden_files.clear();
+					data_source.marks.clear();
 				}
-				self.set_active_item(active_index.unwrap_or(0) as u32);
+				self.set_active_item(listing.active_index.unwrap_or(0) as u32);
+				self.show_selection();
+				self.start_watching();
 			}
 			Err(e) => log::error!("Error: {}", e),
 		}
-		log::info!("{:?}", now.elapsed());
 		self.vtable
 			.send_event(
 				BEHAVIOR_EVENTS::CHANGE,
@@ -93,6 +279,695 @@ impl Pane {
 			.unwrap();
 	}
 
+	pub fn set_system(
+		&mut self,
+		mut system: Box<dyn System>,
+		connection: Option<String>,
+	) -> Result<(), Error> {
+		let root = system.get_root(&self.field_names)?;
+		let tab = self.tab_mut();
+		tab.parent = get_path(&root);
+		tab.system = Arc::new(Mutex::new(Box::new(ArchiveSystem::new(system))));
+		tab.connection = connection;
+		tab.back.clear();
+		tab.forward.clear();
+		self.clear_directory_sizes();
+		self.update(None);
+		Ok(())
+	}
+
+	pub fn get_connection(&self) -> Option<&str> {
+		self.tab().connection.as_deref()
+	}
+
+	/// Lists another directory in the current tab. The current one can be
+	/// returned to with `go_back`.
+	pub fn navigate(&mut self, path: &str) {
+		let tab = self.tab_mut();
+		if tab.parent == path {
+			return;
+		}
+		let previous_parent = std::mem::replace(&mut tab.parent, path.to_owned());
+		tab.back.push(previous_parent.clone());
+		if tab.back.len() > HISTORY_LIMIT {
+			tab.back.remove(0);
+		}
+		tab.forward.clear();
+		self.update(Some(&previous_parent));
+	}
+
+	pub fn go_back(&mut self) {
+		let tab = self.tab_mut();
+		if let Some(path) = tab.back.pop() {
+			let previous_parent = std::mem::replace(&mut tab.parent, path);
+			tab.forward.push(previous_parent.clone());
+			self.update(Some(&previous_parent));
+		}
+	}
+
+	pub fn go_forward(&mut self) {
+		let tab = self.tab_mut();
+		if let Some(path) = tab.forward.pop() {
+			let previous_parent = std::mem::replace(&mut tab.parent, path);
+			tab.back.push(previous_parent.clone());
+			self.update(Some(&previous_parent));
+		}
+	}
+
+	/// Returns the directories visited in the current tab, the most recent
+	/// first, without duplicates.
+	pub fn get_history(&self) -> Vec<String> {
+		let tab = self.tab();
+		let mut history: Vec<String> = Vec::new();
+		for path in tab.back.iter().rev().chain(tab.forward.iter().rev()) {
+			if *path != tab.parent && !history.contains(path) {
+				history.push(path.clone());
+			}
+		}
+		history
+	}
+
+	/// Returns the system of the current tab.
+	pub fn get_system(&self) -> Arc<Mutex<Box<dyn System>>> {
+		Arc::clone(&self.tab().system)
+	}
+
+	fn tab(&self) -> &Tab {
+		&self.tabs[self.current_tab]
+	}
+
+	fn tab_mut(&mut self) -> &mut Tab {
+		&mut self.tabs[self.current_tab]
+	}
+
+	/// Opens a tab after the current one and shows it.
+	pub fn open_tab(&mut self, tab: Tab) {
+		self.save_tab();
+		self.current_tab += 1;
+		self.tabs.insert(self.current_tab, tab);
+		self.load_tab();
+	}
+
+	/// Opens the root directory of the system in a new tab.
+	pub fn open_system_tab(
+		&mut self,
+		mut system: Box<dyn System>,
+		connection: Option<String>,
+	) -> Result<(), Error> {
+		let root = system.get_root(&self.field_names)?;
+		let system = Box::new(ArchiveSystem::new(system));
+		self.open_tab(Tab::new(system, connection, get_path(&root)));
+		Ok(())
+	}
+
+	/// Opens the directory of the current tab in a new tab.
+	pub fn duplicate_tab(&mut self) -> Result<(), Error> {
+		let system = self.duplicate_system()?;
+		let tab = self.tab();
+		let tab = Tab::new(system, tab.connection.clone(), tab.parent.clone());
+		self.open_tab(tab);
+		Ok(())
+	}
+
+	/// Closes the current tab and returns it. The last tab is never closed.
+	pub fn close_tab(&mut self) -> Option<Tab> {
+		if self.tabs.len() < 2 {
+			return None;
+		}
+		self.save_tab();
+		let tab = self.tabs.remove(self.current_tab);
+		self.current_tab = self.current_tab.min(self.tabs.len() - 1);
+		self.load_tab();
+		Some(tab)
+	}
+
+	pub fn select_tab(&mut self, index: usize) {
+		if index < self.tabs.len() && index != self.current_tab {
+			self.save_tab();
+			self.current_tab = index;
+			self.load_tab();
+		}
+	}
+
+	/// Selects the next tab, or the previous one for a negative offset.
+	pub fn switch_tab(&mut self, offset: isize) {
+		let count = self.tabs.len() as isize;
+		let index = (self.current_tab as isize + offset).rem_euclid(count);
+		self.select_tab(index as usize);
+	}
+
+	/// Moves the files shown in the pane to the current tab.
+	fn save_tab(&mut self) {
+		self.end_quick_search(true);
+		let (files, active_index, sort_order) =
+			if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+				(
+					std::mem::replace(&mut data_source.files, Vec::new()),
+					data_source.active_index,
+					data_source.sort_order,
+				)
+			} else {
+				return;
+			};
+		let tab = self.tab_mut();
+		tab.files = files;
+		tab.active_index = active_index;
+		tab.sort_order = sort_order;
+	}
+
+	/// Shows the files of the current tab, or lists them, if they were not
+	/// received yet.
+	fn load_tab(&mut self) {
+		// Listings of the previous tab are no longer wanted.
+		self.generation += 1;
+		self.stop_watching();
+		let tab = self.tab_mut();
+		let files = std::mem::replace(&mut tab.files, Vec::new());
+		let (active_index, sort_order, loaded) = (tab.active_index, tab.sort_order, tab.loaded);
+		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+			data_source.files = files;
+			data_source.hidden_files.clear();
+			data_source.sort_order = sort_order;
+		}
+		self.clear_directory_sizes();
+		self.show_parent();
+		self.show_tabs();
+		self.set_active_item(active_index);
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+		if loaded {
+			self.show_selection();
+			// The directory may have changed while the tab was hidden.
+			self.start_watching();
+			self.reload();
+		} else {
+			self.update(None);
+		}
+	}
+
+	/// Watches the current directory for changes made by other programs, or
+	/// polls it, if the system cannot watch. The watcher is started on a
+	/// worker thread and passed to `set_watcher` by the window.
+	fn start_watching(&mut self) {
+		let pane = self.index;
+		let generation = self.generation;
+		let system = Arc::clone(&self.tab().system);
+		let parent = self.tab().parent.clone();
+		self.jobs
+			.spawn(&format!("Watch {}", parent), move |context| {
+				let (sender, receiver) = mpsc::channel();
+				let watcher = lock_system(&system).watch(&parent, sender);
+				context.send(JobMessage::Watching {
+					pane,
+					generation,
+					watcher,
+					receiver,
+				});
+			});
+	}
+
+	pub fn set_watcher(
+		&mut self,
+		generation: u64,
+		watcher: Result<Option<Watcher>, Error>,
+		receiver: Receiver<WatchEvent>,
+	) {
+		if generation != self.generation {
+			// The watcher of a directory no longer shown is dropped.
+			return;
+		}
+		match watcher {
+			Ok(Some(watcher)) => self.watcher = Some((watcher, receiver)),
+			Ok(None) => self.next_poll = Some(Instant::now() + POLL_INTERVAL),
+			Err(e) => {
+				log::warn!("Failed to watch {}: {}", self.get_parent(), e);
+				self.next_poll = Some(Instant::now() + POLL_INTERVAL);
+			}
+		}
+	}
+
+	fn stop_watching(&mut self) {
+		self.watcher = None;
+		self.next_poll = None;
+		self.reloading = false;
+		self.reading_changes = false;
+	}
+
+	/// Applies the changes reported by the watcher, or lists the directory
+	/// again, when it is due to be polled. Returns true, if a job was started.
+	pub fn poll_changes(&mut self) -> bool {
+		if self.reloading || self.reading_changes {
+			return false;
+		}
+		if let Some((_watcher, receiver)) = &self.watcher {
+			let events: Vec<WatchEvent> = receiver.try_iter().collect();
+			if !events.is_empty() {
+				return self.read_changes(events);
+			}
+		} else if let Some(next_poll) = self.next_poll {
+			if Instant::now() >= next_poll {
+				self.reload();
+				return true;
+			}
+		}
+		false
+	}
+
+	/// Reads the changed files again on a worker thread. They are passed to
+	/// `set_changes` by the window. Falls back to listing the directory again,
+	/// if changes were missed.
+	fn read_changes(&mut self, events: Vec<WatchEvent>) -> bool {
+		let parent = self.tab().parent.clone();
+		let mut paths: Vec<String> = Vec::new();
+		for event in events {
+			match event {
+				WatchEvent::Changed(path) | WatchEvent::Removed(path) => {
+					// Changes of the directory itself are not shown.
+					if Path::new(&path).parent() == Some(Path::new(&parent))
+						&& !paths.contains(&path)
+					{
+						paths.push(path);
+					}
+				}
+				WatchEvent::Rescan => {
+					self.reload();
+					return true;
+				}
+			}
+		}
+		if paths.is_empty() {
+			return false;
+		}
+		self.reading_changes = true;
+		let pane = self.index;
+		let generation = self.generation;
+		let system = Arc::clone(&self.tab().system);
+		let field_names = Arc::clone(&self.field_names);
+		self.jobs
+			.spawn(&format!("Read changes of {}", parent), move |context| {
+				let changes = read_changed_files(&mut **lock_system(&system), paths, &field_names);
+				context.send(JobMessage::Changes {
+					pane,
+					generation,
+					changes,
+				});
+			});
+		true
+	}
+
+	/// Updates the changed files. Lists the directory again, if a file cannot
+	/// be matched by its path, which `changes` tells with `None`.
+	pub fn set_changes(&mut self, generation: u64, changes: Option<Changes>) {
+		if generation != self.generation || !self.reading_changes {
+			return;
+		}
+		self.reading_changes = false;
+		let changes = match changes {
+			Some(changes) => changes,
+			None => {
+				self.reload();
+				return;
+			}
+		};
+		if !changes.files.is_empty() || !changes.removed_paths.is_empty() {
+			let removed_paths = changes.removed_paths;
+			let changed_files = changes.files;
+			self.change_files(|files| {
+				files.retain(|file| !removed_paths.contains(&get_path(file)));
+				for mut changed_file in changed_files {
+					let path = get_path(&changed_file);
+					match files.iter().position(|file| get_path(file) == path) {
+						Some(index) => {
+							changed_file.selected = files[index].selected;
+							files[index] = changed_file;
+						}
+						None => files.push(changed_file),
+					}
+				}
+			});
+		}
+	}
+
+	/// Changes the files of the current directory, keeping the active item and
+	/// the files hidden by the quick filter. New files are shown.
+	fn change_files<F>(&mut self, change: F)
+	where
+		F: FnOnce(&mut Vec<File>),
+	{
+		let active_path = self.get_active_path();
+		let active_index =
+			if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+				let hidden_paths: HashSet<String> =
+					data_source.hidden_files.iter().map(get_path).collect();
+				let mut files = std::mem::replace(&mut data_source.files, Vec::new());
+				files.append(&mut data_source.hidden_files);
+				change(&mut files);
+				apply_directory_sizes(&mut files, &data_source.directory_sizes);
+				data_source.sort_order.sort(&mut files);
+				let (hidden_files, visible_files) = files
+					.into_iter()
+					.partition(|file| hidden_paths.contains(&get_path(file)));
+				data_source.files = visible_files;
+				data_source.hidden_files = hidden_files;
+				let last_index = data_source.files.len().saturating_sub(1);
+				data_source
+					.files
+					.iter()
+					.position(|file| Some(get_path(file)) == active_path)
+					.unwrap_or_else(|| (data_source.active_index as usize).min(last_index))
+			} else {
+				return;
+			};
+		self.set_active_item(active_index as u32);
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+		self.show_selection();
+	}
+
+	fn show_parent(&mut self) {
+		let parent = &self.tabs[self.current_tab].parent;
+		self.input.set_text(parent).unwrap();
+		self.show_status("\u{a0}");
+		self.input.remove_attribute("error").unwrap();
+	}
+
+	fn show_status(&mut self, text: &str) {
+		self.status.set_text(text).unwrap();
+	}
+
+	/// Shows the number and the size of the selected files in the status bar.
+	/// Directories count with their total size, once it is calculated.
+	fn show_selection(&mut self) {
+		let (selected, total) = count_selection(&self.data_source.borrow().files);
+		let text = format!(
+			"{} of {} bytes in {} of {} files, {} of {} directories selected",
+			selected.bytes.separated_string(),
+			total.bytes.separated_string(),
+			selected.files,
+			total.files,
+			selected.directories,
+			total.directories
+		);
+		self.show_status(&text);
+	}
+
+	/// Marks the path bar as invalid and shows the message in the status bar.
+	pub fn show_path_error(&mut self, message: &str) {
+		self.input.set_attribute("error", "").unwrap();
+		self.show_status(message);
+	}
+
+	/// Focuses the path bar and selects its text for typing a path.
+	pub fn edit_path(&mut self) {
+		self.input
+			.set_state(ELEMENT_STATE_BITS::STATE_FOCUS, None, true)
+			.unwrap();
+		self.input
+			.call_method("selectAll", &sciter::make_args!())
+			.ok();
+	}
+
+	/// Called, when the path bar gets or loses the focus. A path left without
+	/// pressing Enter is discarded.
+	pub fn set_editing_path(&mut self, editing_path: bool) {
+		self.editing_path = editing_path;
+		if editing_path {
+			self.end_quick_search(true);
+		} else {
+			self.show_parent();
+		}
+	}
+
+	pub fn is_editing_path(&self) -> bool {
+		self.editing_path
+	}
+
+	pub fn get_path_text(&self) -> String {
+		self.input.get_value().as_string().unwrap_or_default()
+	}
+
+	/// Discards the typed path and shows the current directory again.
+	pub fn cancel_path_edit(&mut self) {
+		self.show_parent();
+	}
+
+	/// Checks on a worker thread, that the path is a directory, and lists it
+	/// in the current tab, once the window passes the result to
+	/// `set_checked_path`. A relative path is resolved against the current
+	/// directory.
+	pub fn open_path(&mut self, path: &str) {
+		let pane = self.index;
+		let generation = self.generation;
+		let system = Arc::clone(&self.tab().system);
+		let parent = self.tab().parent.clone();
+		let field_names = Arc::clone(&self.field_names);
+		let path = path.to_owned();
+		self.jobs.spawn(&format!("Open {}", path), move |context| {
+			let result = check_directory(&mut **lock_system(&system), &parent, &path, &field_names);
+			context.send(JobMessage::PathChecked {
+				pane,
+				generation,
+				path,
+				result,
+			});
+		});
+	}
+
+	/// Lists the checked directory, unless the pane moved on to another one
+	/// meanwhile. Returns true, if the directory is listed.
+	pub fn set_checked_path(
+		&mut self,
+		generation: u64,
+		path: &str,
+		result: Result<String, Error>,
+	) -> bool {
+		if generation != self.generation {
+			return false;
+		}
+		match result {
+			Ok(directory) => {
+				self.navigate(&directory);
+				true
+			}
+			Err(e) => {
+				self.show_path_error(&format!("Cannot open {}: {}", path, e));
+				false
+			}
+		}
+	}
+
+	/// Lists the directories matching the name being typed in the path bar on
+	/// a worker thread. They are completed by `set_path_completion`.
+	pub fn complete_path(&mut self) {
+		let text = self.get_path_text();
+		let (directory, prefix) = split_path_text(&text);
+		let tab = self.tab();
+		let home_path = match expand_home(directory) {
+			Some(home_path) if tab.connection.is_none() => Some(home_path),
+			_ => None,
+		};
+		let pane = self.index;
+		let generation = self.generation;
+		let system = Arc::clone(&tab.system);
+		let parent = tab.parent.clone();
+		let field_names = Arc::clone(&self.field_names);
+		let directory = directory.to_owned();
+		let prefix = prefix.to_owned();
+		self.jobs
+			.spawn(&format!("Complete {}", text), move |context| {
+				let mut system = lock_system(&system);
+				let directory_path = match (directory.as_str(), home_path) {
+					("", _) => parent,
+					(_, Some(home_path)) => home_path,
+					_ => resolve_path(&mut **system, &parent, &directory),
+				};
+				let names =
+					list_directory_names(&mut **system, &directory_path, &prefix, &field_names);
+				drop(system);
+				context.send(JobMessage::PathCompletion {
+					pane,
+					generation,
+					text,
+					names,
+				});
+			});
+	}
+
+	/// Completes the typed name with the matching directories, unless the text
+	/// of the path bar changed meanwhile. If the name is ambiguous, the
+	/// directories are shown in the status bar.
+	pub fn set_path_completion(
+		&mut self,
+		generation: u64,
+		text: &str,
+		names: Result<Vec<String>, Error>,
+	) {
+		if generation != self.generation || !self.editing_path || self.get_path_text() != text {
+			return;
+		}
+		let mut names = match names {
+			Ok(names) => names,
+			Err(e) => {
+				self.show_path_error(&format!("Cannot complete the path: {}", e));
+				return;
+			}
+		};
+		let (directory, prefix) = split_path_text(text);
+		let separator = if self.tab().connection.is_none() {
+			path::MAIN_SEPARATOR
+		} else {
+			'/'
+		};
+		names.sort_by_key(|name| name.to_lowercase());
+		let completion = match names.as_slice() {
+			[] => {
+				self.show_status("No matching directory");
+				return;
+			}
+			[name] => format!("{}{}{}", directory, name, separator),
+			_ => {
+				let common_prefix = get_common_prefix(&names);
+				if common_prefix.chars().count() <= prefix.chars().count() {
+					self.show_status(&names.join("  "));
+					return;
+				}
+				format!("{}{}", directory, common_prefix)
+			}
+		};
+		self.input.set_text(&completion).unwrap();
+		let length = completion.chars().count() as i32;
+		self.input
+			.call_method("setSelection", &sciter::make_args!(length, length))
+			.ok();
+		self.input.remove_attribute("error").unwrap();
+		self.show_status("\u{a0}");
+	}
+
+	fn show_tabs(&mut self) {
+		self.tabs_element.clear().unwrap();
+		for (index, tab) in self.tabs.iter().enumerate() {
+			let mut element = Element::with_text("tab", &tab.title).unwrap();
+			self.tabs_element.append(&element).unwrap();
+			if index == self.current_tab {
+				element.set_attribute("current", "").unwrap();
+			}
+		}
+	}
+
+	pub fn get_settings(&self) -> PaneSettings {
+		let data_source = self.data_source.borrow();
+		let sort_order = data_source.sort_order;
+		let tabs = self
+			.tabs
+			.iter()
+			.enumerate()
+			.map(|(index, tab)| {
+				let sort_order = if index == self.current_tab {
+					sort_order
+				} else {
+					tab.sort_order
+				};
+				TabSettings {
+					path: Some(tab.parent.clone()),
+					connection: tab.connection.clone(),
+					history: tab.back.clone(),
+					sort: SortSettings {
+						field: self.field_names[sort_order.field_index].clone(),
+						descending: sort_order.descending,
+						directories_first: sort_order.directories_first,
+					},
+				}
+			})
+			.collect();
+		PaneSettings {
+			tabs,
+			current_tab: self.current_tab,
+			columns: self
+				.field_names
+				.iter()
+				.zip(&data_source.columns)
+				.map(|(field_name, column)| ColumnSettings {
+					field: field_name.clone(),
+					width: column.get_width(),
+				})
+				.collect(),
+		}
+	}
+
+	/// Restores the tabs and column widths. Each tab is given with its
+	/// system, which is already connected. The directory of a tab is kept, if
+	/// the saved one no longer exists.
+	pub fn apply_settings(
+		&mut self,
+		settings: &PaneSettings,
+		tabs: Vec<(Box<dyn System>, &TabSettings)>,
+	) {
+		if !tabs.is_empty() {
+			self.tabs = tabs
+				.into_iter()
+				.map(|(system, settings)| self.restore_tab(system, settings))
+				.collect();
+			self.current_tab = settings.current_tab.min(self.tabs.len() - 1);
+		}
+		for column_settings in &settings.columns {
+			if let Some(field_index) = self
+				.field_names
+				.iter()
+				.position(|x| *x == column_settings.field)
+			{
+				if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+					data_source.columns[field_index].set_width(column_settings.width);
+				}
+			}
+		}
+		self.load_tab();
+	}
+
+	fn restore_tab(&self, system: Box<dyn System>, settings: &TabSettings) -> Tab {
+		let mut system: Box<dyn System> = Box::new(ArchiveSystem::new(system));
+		let mut parent = None;
+		if let Some(path) = &settings.path {
+			match system.get_file(path, &self.field_names) {
+				Ok(ref file) if file.is_dir || archive::is_archive_path(path) => {
+					parent = Some(get_path(file))
+				}
+				Ok(_) => log::warn!("Not a directory: {}", path),
+				Err(e) => log::warn!("Failed to restore {}: {}", path, e),
+			}
+		}
+		let parent = match parent {
+			Some(parent) => parent,
+			None => system
+				.get_root(&self.field_names)
+				.map(|root| get_path(&root))
+				.unwrap_or_default(),
+		};
+		let mut tab = Tab::new(system, settings.connection.clone(), parent);
+		tab.back = settings.history.clone();
+		if let Some(field_index) = self
+			.field_names
+			.iter()
+			.position(|x| *x == settings.sort.field)
+		{
+			tab.sort_order = SortOrder {
+				field_index,
+				descending: settings.sort.descending,
+				directories_first: settings.sort.directories_first,
+			};
+		}
+		tab
+	}
+
 	pub fn get_active_path(&self) -> Option<String> {
 		let data_source = self.data_source.borrow();
 		if let Some(active_file) = data_source.files.get(data_source.active_index as usize) {
@@ -115,11 +990,13 @@ impl Pane {
 	}
 
 	pub fn move_down(&mut self) {
-		let active_index = std::cmp::min(
-			self.data_source.borrow().files.len() as u32 - 1,
-			self.data_source.borrow().active_index + 1,
-		);
-		self.set_active_item(active_index);
+		if !self.data_source.borrow().files.is_empty() {
+			let active_index = std::cmp::min(
+				self.data_source.borrow().files.len() as u32 - 1,
+				self.data_source.borrow().active_index + 1,
+			);
+			self.set_active_item(active_index);
+		}
 	}
 
 	pub fn move_home(&mut self) {
@@ -175,6 +1052,142 @@ impl Pane {
 				None,
 			)
 			.unwrap();
+		self.show_selection();
+	}
+
+	/// Selects or deselects the shown files, for which the predicate is true.
+	pub fn select_where<F>(&mut self, select: bool, predicate: F)
+	where
+		F: Fn(&File) -> bool,
+	{
+		self.change_selection(|_index, file| {
+			if predicate(file) {
+				file.selected = select;
+			}
+		});
+	}
+
+	/// Selects or deselects the shown files matching the pattern, see
+	/// `get_pattern_matches`.
+	pub fn select_pattern(&mut self, pattern: &str, select: bool) -> Result<(), regex::Error> {
+		let matches = get_pattern_matches(&self.data_source.borrow().files, pattern, select)?;
+		self.change_selection(|index, file| {
+			if matches.binary_search(&index).is_ok() {
+				file.selected = select;
+			}
+		});
+		Ok(())
+	}
+
+	pub fn invert_selection(&mut self) {
+		self.change_selection(|_index, file| file.selected = !file.selected);
+	}
+
+	/// Selects the files with the extension of the active file.
+	pub fn select_same_extension(&mut self) {
+		let extension = {
+			let data_source = self.data_source.borrow();
+			match data_source.files.get(data_source.active_index as usize) {
+				Some(file) if !file.is_dir => get_extension(file).to_lowercase(),
+				_ => return,
+			}
+		};
+		self.select_where(true, |file| {
+			!file.is_dir && get_extension(file).to_lowercase() == extension
+		});
+	}
+
+	fn change_selection<F>(&mut self, change: F)
+	where
+		F: Fn(usize, &mut File),
+	{
+		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+			for (index, file) in data_source.files.iter_mut().enumerate() {
+				if !is_parent_item(file) {
+					change(index, file);
+				}
+			}
+		}
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+		self.show_selection();
+	}
+
+	/// Returns the file names of the selected files.
+	pub fn get_selected_names(&self) -> Vec<String> {
+		self.data_source
+			.borrow()
+			.files
+			.iter()
+			.filter(|file| file.selected && !is_parent_item(file))
+			.map(|file| get_path_filename(&get_path(file)).to_owned())
+			.collect()
+	}
+
+	/// Returns the path of the active directory, if it is selected and its
+	/// size was not calculated yet.
+	pub fn get_directory_to_size(&self) -> Option<String> {
+		let data_source = self.data_source.borrow();
+		match data_source.files.get(data_source.active_index as usize) {
+			Some(file) if file.is_dir && file.selected && !is_parent_item(file) => {
+				let path = get_path(file);
+				if data_source.directory_sizes.contains_key(&path) {
+					None
+				} else {
+					Some(path)
+				}
+			}
+			_ => None,
+		}
+	}
+
+	/// Identifies the calculated directory sizes, which are dropped when
+	/// another tab is shown or the pane is refreshed.
+	pub fn get_sizes_generation(&self) -> u64 {
+		self.sizes_generation
+	}
+
+	/// Remembers the total sizes of directory trees and shows them in the
+	/// size column, unless the sizes were dropped meanwhile.
+	pub fn set_directory_sizes(&mut self, generation: u64, sizes: Vec<(String, u64)>) {
+		if generation != self.sizes_generation {
+			return;
+		}
+		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+			data_source.directory_sizes.extend(sizes);
+		}
+		self.change_files(|_files| {});
+	}
+
+	fn clear_directory_sizes(&mut self) {
+		self.sizes_generation += 1;
+		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+			data_source.directory_sizes.clear();
+		}
+	}
+
+	/// Marks the files by file name with the results of a comparison and
+	/// selects the ones, which are not identical. The marks are kept, until
+	/// another directory is listed.
+	pub fn set_marks(&mut self, marks: HashMap<String, &'static str>) {
+		let different: HashSet<String> = marks
+			.iter()
+			.filter(|(_filename, mark)| **mark != "identical")
+			.map(|(filename, _mark)| filename.clone())
+			.collect();
+		if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+			data_source.marks = marks;
+		}
+		self.change_files(|files| {
+			for file in files.iter_mut() {
+				file.selected = different.contains(get_path_filename(&get_path(file)));
+			}
+		});
 	}
 
 	fn set_active_item(&mut self, active_index: u32) {
@@ -196,26 +1209,343 @@ impl Pane {
 	}
 
 	pub fn enter_item(&mut self) {
-		let previous_parent = self.parent.clone();
 		if let Some(new_parent) = self.get_active_path() {
-			self.parent = new_parent;
-			self.update(Some(&previous_parent));
+			self.navigate(&new_parent);
+		}
+	}
+
+	/// Sorts by the field, or reverses the order, if the pane is sorted by it.
+	pub fn sort_by(&mut self, field_index: usize) {
+		if field_index < self.field_names.len() {
+			let sort_order = self.data_source.borrow().sort_order.toggle(field_index);
+			self.set_sort_order(sort_order);
+		}
+	}
+
+	pub fn sort_by_field(&mut self, field_name: &str) {
+		if let Some(field_index) = self.field_names.iter().position(|x| x == field_name) {
+			self.sort_by(field_index);
 		}
 	}
 
-	pub fn list_files(&mut self) -> Result<Vec<File>, Error> {
-		let field_names = Rc::new((&self.field_names).clone());
-		log::info!("parent={}", self.parent);
-		let parent = self.system.get_file(&self.parent, &field_names)?;
-		log::info!("list files");
-		self.system.list_files(&parent, &field_names)
+	pub fn toggle_directories_first(&mut self) {
+		let mut sort_order = self.data_source.borrow().sort_order;
+		sort_order.directories_first = !sort_order.directories_first;
+		self.set_sort_order(sort_order);
 	}
+
+	fn set_sort_order(&mut self, sort_order: SortOrder) {
+		let active_path = self.get_active_path();
+		let active_index =
+			if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+				data_source.sort_order = sort_order;
+				sort_order.sort(&mut data_source.files);
+				data_source
+					.files
+					.iter()
+					.position(|file| Some(get_path(file)) == active_path)
+					.unwrap_or(0)
+			} else {
+				return;
+			};
+		self.set_active_item(active_index as u32);
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+	}
+
+	pub fn is_quick_search_active(&self) -> bool {
+		self.quick_search.is_some()
+	}
+
+	pub fn start_quick_search(&mut self, filter: bool) {
+		self.quick_search = Some(QuickSearch {
+			query: String::new(),
+			filter,
+		});
+		self.input.set_attribute("quick-search", "").unwrap();
+		self.apply_quick_search();
+	}
+
+	/// Stops editing the query. Unless `show_all` is set, a filter stays
+	/// applied until the pane lists another directory.
+	pub fn end_quick_search(&mut self, show_all: bool) {
+		if self.quick_search.take().is_some() {
+			self.input.remove_attribute("quick-search").unwrap();
+			self.show_parent();
+		}
+		if show_all && !self.data_source.borrow().hidden_files.is_empty() {
+			self.show_files(|_file| Some(0), 0);
+		}
+	}
+
+	pub fn push_quick_search(&mut self, c: char) {
+		if let Some(quick_search) = &mut self.quick_search {
+			quick_search.query.push(c);
+			self.apply_quick_search();
+		}
+	}
+
+	pub fn pop_quick_search(&mut self) {
+		if let Some(quick_search) = &mut self.quick_search {
+			quick_search.query.pop();
+			self.apply_quick_search();
+		}
+	}
+
+	/// Jumps to the next or previous file matching the query.
+	pub fn find_next_quick_search(&mut self, backwards: bool) {
+		let matcher = match &self.quick_search {
+			Some(quick_search) => match Matcher::new(&quick_search.query) {
+				Ok(matcher) => matcher,
+				Err(_) => return,
+			},
+			None => return,
+		};
+		let next_index = {
+			let data_source = self.data_source.borrow();
+			let active_index = data_source.active_index as usize;
+			let count = data_source.files.len();
+			(1..count)
+				.map(|offset| {
+					if backwards {
+						(active_index + count - offset) % count
+					} else {
+						(active_index + offset) % count
+					}
+				})
+				.find(|index| get_match_rank(&matcher, &data_source.files[*index]).is_some())
+		};
+		if let Some(next_index) = next_index {
+			self.set_active_item(next_index as u32);
+		}
+	}
+
+	fn apply_quick_search(&mut self) {
+		let (query, filter) = match &self.quick_search {
+			Some(quick_search) => (quick_search.query.clone(), quick_search.filter),
+			None => return,
+		};
+		self.input.set_text(&query).unwrap();
+		let matcher = match Matcher::new(&query) {
+			Ok(matcher) => matcher,
+			Err(e) => {
+				log::debug!("Invalid quick search {}: {}", query, e);
+				return;
+			}
+		};
+		if query.is_empty() {
+			return;
+		}
+		if filter {
+			self.show_files(|file| get_match_rank(&matcher, file), 1);
+		} else {
+			let best_index = {
+				let data_source = self.data_source.borrow();
+				get_best_match(&matcher, &data_source.files)
+			};
+			if let Some(best_index) = best_index {
+				self.set_active_item(best_index as u32);
+			}
+		}
+	}
+
+	/// Shows the files with a rank up to `max_rank` and hides the others.
+	fn show_files<F>(&mut self, get_rank: F, max_rank: u8)
+	where
+		F: Fn(&File) -> Option<u8>,
+	{
+		let active_path = self.get_active_path();
+		let active_index =
+			if let Ok(ref mut data_source) = RefCell::try_borrow_mut(&mut self.data_source) {
+				let mut files = std::mem::replace(&mut data_source.files, Vec::new());
+				files.append(&mut data_source.hidden_files);
+				data_source.sort_order.sort(&mut files);
+				let (visible_files, hidden_files) = files.into_iter().partition(|file| {
+					is_parent_item(file) || get_rank(file).map_or(false, |rank| rank <= max_rank)
+				});
+				data_source.files = visible_files;
+				data_source.hidden_files = hidden_files;
+				let best_index = data_source
+					.files
+					.iter()
+					.enumerate()
+					.filter(|(_index, file)| !is_parent_item(file))
+					.min_by_key(|(_index, file)| get_rank(file))
+					.map(|(index, _file)| index);
+				data_source
+					.files
+					.iter()
+					.position(|file| Some(get_path(file)) == active_path)
+					.filter(|index| get_rank(&data_source.files[*index]) == Some(0))
+					.or(best_index)
+					.unwrap_or(0)
+			} else {
+				return;
+			};
+		self.set_active_item(active_index as u32);
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+	}
+
+	pub fn get_parent(&self) -> &str {
+		&self.tab().parent
+	}
+
+	pub fn duplicate_system(&mut self) -> Result<Box<dyn System>, Error> {
+		lock_system(&self.tab().system).duplicate()
+	}
+
+	/// Lists the current directory again and drops the calculated directory
+	/// sizes, which may have changed.
+	pub fn refresh(&mut self) {
+		self.clear_directory_sizes();
+		let active_path = self.get_active_path();
+		self.update(active_path.as_ref().map(String::as_str));
+	}
+
+	/// Returns paths of selected files or the active file, if nothing is selected.
+	pub fn get_selected_paths(&self) -> Vec<String> {
+		let data_source = self.data_source.borrow();
+		let selected_paths: Vec<String> = data_source
+			.files
+			.iter()
+			.filter(|file| file.selected && !is_parent_item(file))
+			.map(get_path)
+			.collect();
+		if selected_paths.is_empty() {
+			self.get_active_item_path().into_iter().collect()
+		} else {
+			selected_paths
+		}
+	}
+
+	/// Returns the path of the active item, if it is not a directory.
+	pub fn get_active_file_path(&self) -> Option<String> {
+		let data_source = self.data_source.borrow();
+		match data_source.files.get(data_source.active_index as usize) {
+			Some(active_file) if !active_file.is_dir && !is_parent_item(active_file) => {
+				Some(get_path(active_file))
+			}
+			_ => None,
+		}
+	}
+
+	pub fn get_active_item_path(&self) -> Option<String> {
+		let data_source = self.data_source.borrow();
+		match data_source.files.get(data_source.active_index as usize) {
+			Some(active_file) if !is_parent_item(active_file) => Some(get_path(active_file)),
+			_ => None,
+		}
+	}
+
+	pub fn get_filename(&mut self, path: &str) -> String {
+		lock_system(&self.tab().system).get_filename(path)
+	}
+
+	pub fn create_directory(&mut self, name: &str) -> Result<(), Error> {
+		let tab = self.tab();
+		let mut system = lock_system(&tab.system);
+		let path = system.join_path(&tab.parent, name);
+		system.create_directory(&path)?;
+		drop(system);
+		self.update(Some(&path));
+		Ok(())
+	}
+
+	/// Renames the item within its directory, which may differ from the
+	/// listed one, e.g. in search results.
+	pub fn rename_item(&mut self, path: &str, new_name: &str) -> Result<(), Error> {
+		let tab = self.tab();
+		let mut system = lock_system(&tab.system);
+		let new_path = system.join_path(get_path_directory(path), new_name);
+		system.rename(path, &new_path)?;
+		drop(system);
+		self.update(Some(&new_path));
+		Ok(())
+	}
+}
+
+pub fn lock_system(system: &Mutex<Box<dyn System>>) -> MutexGuard<'_, Box<dyn System>> {
+	system.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn list_files(
+	system: &mut dyn System,
+	parent: &str,
+	field_names: &Arc<Vec<String>>,
+	sort_order: SortOrder,
+	selected_path: Option<&str>,
+) -> Result<Listing, Error> {
+	log::info!("parent={}", parent);
+	let filename = system.get_filename(parent);
+	let parent = system.get_file(parent, field_names)?;
+	log::info!("list files");
+	let mut files = system.list_files(&parent, field_names)?;
+	sort_order.sort(&mut files);
+	let mut active_index: Option<usize> = None;
+	let mut path_index: Option<usize> = None;
+	for (index, file) in files.iter().enumerate() {
+		if active_index == None {
+			path_index = path_index.or(file.get_field_index("path"));
+			if Some(get_path_at(&file, path_index).as_ref()) == selected_path {
+				active_index = Some(index);
+			}
+		}
+	}
+	Ok(Listing {
+		filename,
+		files,
+		active_index,
+	})
+}
+
+/// Reads the changed paths again. Returns `None`, if a file cannot be matched
+/// by its path and the directory has to be listed again.
+fn read_changed_files(
+	system: &mut dyn System,
+	paths: Vec<String>,
+	field_names: &Arc<Vec<String>>,
+) -> Option<Changes> {
+	let mut changes = Changes {
+		files: Vec::new(),
+		removed_paths: HashSet::new(),
+	};
+	for path in paths {
+		if system.get_metadata(&path).is_err() {
+			changes.removed_paths.insert(path);
+			continue;
+		}
+		match system.get_file(&path, field_names) {
+			// Links are resolved to their target.
+			Ok(file) if get_path(&file) == path => changes.files.push(file),
+			_ => return None,
+		}
+	}
+	Some(changes)
 }
 
 pub struct FilesDataSource {
 	active_index: u32,
 	columns: Vec<Column>,
 	files: Vec<File>,
+	/// Files hidden by the quick filter.
+	hidden_files: Vec<File>,
+	/// Results of comparing the directory to the other pane, by file name.
+	marks: HashMap<String, &'static str>,
+	/// Total sizes of directory trees by path, once calculated.
+	directory_sizes: HashMap<String, u64>,
+	sort_order: SortOrder,
+	locale: Locale,
 }
 
 impl FilesDataSource {
@@ -224,6 +1554,11 @@ impl FilesDataSource {
 			active_index: 0,
 			columns: Vec::new(),
 			files: Vec::new(),
+			hidden_files: Vec::new(),
+			marks: HashMap::new(),
+			directory_sizes: HashMap::new(),
+			sort_order: SortOrder::default(),
+			locale: get_locale(),
 		}
 	}
 }
@@ -233,7 +1568,25 @@ impl DataSource for FilesDataSource {
 		let columns = &self.columns;
 		let mut data = sciter::Value::array(columns.len());
 		for (index, column) in columns.iter().enumerate() {
-			data.set(index, column.get_name());
+			let name = if index == self.sort_order.field_index {
+				let arrow = if self.sort_order.descending {
+					"\u{25bc}"
+				} else {
+					"\u{25b2}"
+				};
+				format!("{} {}", column.get_name(), arrow)
+			} else {
+				column.get_name().to_owned()
+			};
+			let mut item = sciter::Value::map();
+			item.set_item(sciter::Value::from("name"), sciter::Value::from(name));
+			if let Some(width) = column.get_width() {
+				item.set_item(
+					sciter::Value::from("width"),
+					sciter::Value::from(width as i32),
+				);
+			}
+			data.set(index, item);
 		}
 		data
 	}
@@ -255,19 +1608,26 @@ impl DataSource for FilesDataSource {
 				if file.selected {
 					row.set_item(sciter::Value::from("selected"), sciter::Value::from(true));
 				}
+				if let Some(mark) = self.marks.get(get_path_filename(&get_path(file))) {
+					row.set_item(sciter::Value::from("mark"), sciter::Value::from(*mark));
+				}
 
 				let mut cells = sciter::Value::array(columns.len());
 				for (index, value) in (&file.fields).iter().enumerate() {
 					// let column = &columns[index];
 					let mut cell = sciter::Value::map();
-					let text = if let Value::Size { bytes } = value {
-						cell.set_item(
-							sciter::Value::from("textAlign"),
-							sciter::Value::from("right"),
-						);
-						bytes.separated_string()
-					} else {
-						value.to_string()
+					let text = match value {
+						Value::Size { bytes } => {
+							cell.set_item(
+								sciter::Value::from("textAlign"),
+								sciter::Value::from("right"),
+							);
+							bytes.separated_string()
+						}
+						Value::DateTime { time } => DateTime::<Local>::from(*time)
+							.format_localized("%x %X", self.locale)
+							.to_string(),
+						_ => value.to_string(),
 					};
 					cell.set_item(sciter::Value::from("text"), sciter::Value::from(text));
 
@@ -299,6 +1659,13 @@ impl DataSource for FilesDataSource {
 	}
 }
 
+/// Returns the user's locale, e.g. `de_DE` for `de-DE`, used to format dates.
+fn get_locale() -> Locale {
+	sys_locale::get_locale()
+		.and_then(|locale| Locale::try_from(locale.replace('-', "_").as_str()).ok())
+		.unwrap_or(Locale::POSIX)
+}
+
 pub fn get_path(file: &File) -> String {
 	if let Some(path_index) = file.get_field_index("path") {
 		if let Value::Path { path, .. } = &file.fields[path_index] {
@@ -311,6 +1678,230 @@ pub fn get_path(file: &File) -> String {
 	}
 }
 
+/// Expands `~` at the start of a local path to the home directory.
+pub fn expand_home(path: &str) -> Option<String> {
+	if path == "~" || path.starts_with("~/") || path.starts_with("~\\") {
+		let mut home = dirs::home_dir()?;
+		let rest = path[1..].trim_start_matches(|c| c == '/' || c == '\\');
+		if !rest.is_empty() {
+			home.push(rest);
+		}
+		Some(home.to_string_lossy().into_owned())
+	} else {
+		None
+	}
+}
+
+fn resolve_path(system: &mut dyn System, parent: &str, path: &str) -> String {
+	if path.starts_with('/') || Path::new(path).is_absolute() {
+		path.to_owned()
+	} else {
+		system.join_path(parent, path)
+	}
+}
+
+/// Resolves the path against the parent and returns the path of the
+/// directory, if it is one or an archive.
+fn check_directory(
+	system: &mut dyn System,
+	parent: &str,
+	path: &str,
+	field_names: &Arc<Vec<String>>,
+) -> Result<String, Error> {
+	let path = resolve_path(system, parent, path);
+	if !system.get_metadata(&path)?.is_dir && !archive::is_archive_path(&path) {
+		return Err(Error::IoError(io::Error::new(
+			io::ErrorKind::Other,
+			"Not a directory",
+		)));
+	}
+	Ok(get_path(&system.get_file(&path, field_names)?))
+}
+
+/// Splits the text of the path bar into the typed directory, ending with a
+/// separator, and the name being typed.
+fn split_path_text(text: &str) -> (&str, &str) {
+	text.split_at(
+		text.rfind(|c| c == '/' || c == '\\')
+			.map_or(0, |index| index + 1),
+	)
+}
+
+/// Returns the names of the subdirectories starting with the prefix,
+/// ignoring case.
+fn list_directory_names(
+	system: &mut dyn System,
+	directory_path: &str,
+	prefix: &str,
+	field_names: &Arc<Vec<String>>,
+) -> Result<Vec<String>, Error> {
+	let parent = system.get_file(directory_path, field_names)?;
+	let prefix_lowercase = prefix.to_lowercase();
+	Ok(system
+		.list_files(&parent, field_names)?
+		.iter()
+		.filter(|file| file.is_dir && !is_parent_item(file))
+		.map(|file| get_path_filename(&get_path(file)).to_owned())
+		.filter(|name| name.to_lowercase().starts_with(&prefix_lowercase))
+		.collect())
+}
+
+/// Returns the longest prefix of the names, ignoring case. The case of the
+/// first name is kept.
+fn get_common_prefix(names: &[String]) -> String {
+	let mut common_prefix: Vec<char> = names[0].chars().collect();
+	for name in &names[1..] {
+		let length = common_prefix
+			.iter()
+			.zip(name.chars())
+			.take_while(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+			.count();
+		common_prefix.truncate(length);
+	}
+	common_prefix.into_iter().collect()
+}
+
+pub fn get_path_filename(path: &str) -> &str {
+	path.rsplit(|c| c == '/' || c == '\\')
+		.next()
+		.unwrap_or(path)
+}
+
+/// Returns the directory of the path, keeping the separator of a root.
+pub fn get_path_directory(path: &str) -> &str {
+	let directory = &path[..path.len() - get_path_filename(path).len()];
+	let trimmed = directory.trim_end_matches(|c| c == '/' || c == '\\');
+	if trimmed.is_empty() || trimmed.ends_with(':') {
+		directory
+	} else {
+		trimmed
+	}
+}
+
+fn get_match_rank(matcher: &Matcher, file: &File) -> Option<u8> {
+	if is_parent_item(file) {
+		None
+	} else {
+		matcher.get_rank(get_path_filename(&get_path(file)))
+	}
+}
+
+/// Returns the first prefix match or else the first other match.
+fn get_best_match(matcher: &Matcher, files: &[File]) -> Option<usize> {
+	files
+		.iter()
+		.enumerate()
+		.filter_map(|(index, file)| get_match_rank(matcher, file).map(|rank| (rank, index)))
+		.min()
+		.map(|(_rank, index)| index)
+}
+
+/// Adds up the selected files and all files, except the parent item.
+/// Directories count with their total size, once it is calculated.
+fn count_selection(files: &[File]) -> (Usage, Usage) {
+	let mut selected = Usage::default();
+	let mut total = Usage::default();
+	for file in files.iter().filter(|file| !is_parent_item(file)) {
+		let mut usages = vec![&mut total];
+		if file.selected {
+			usages.push(&mut selected);
+		}
+		for usage in usages {
+			if file.is_dir {
+				usage.directories += 1;
+			} else {
+				usage.files += 1;
+			}
+			usage.bytes += get_size(file);
+		}
+	}
+	(selected, total)
+}
+
+/// Returns the indices of the files matching globs separated by `;`, or a
+/// regular expression starting with `/`, ignoring the case. Patterns ending
+/// with a separator match directories, other patterns match files when
+/// selecting and both when deselecting. The parent item never matches.
+fn get_pattern_matches(
+	files: &[File],
+	pattern: &str,
+	select: bool,
+) -> Result<Vec<usize>, regex::Error> {
+	let directories = pattern.len() > 1 && pattern.ends_with(['/', '\\']);
+	let pattern = if directories {
+		&pattern[..pattern.len() - 1]
+	} else {
+		pattern
+	};
+	let regex = match pattern.strip_prefix('/') {
+		Some(regex) => regex.to_owned(),
+		None => pattern
+			.split(';')
+			.map(|glob| glob_to_regex(glob.trim()))
+			.collect::<Vec<String>>()
+			.join("|"),
+	};
+	let regex = RegexBuilder::new(&regex).case_insensitive(true).build()?;
+	Ok(files
+		.iter()
+		.enumerate()
+		.filter(|(_index, file)| {
+			!is_parent_item(file)
+				&& (file.is_dir == directories || (!select && !directories))
+				&& regex.is_match(get_path_filename(&get_path(file)))
+		})
+		.map(|(index, _file)| index)
+		.collect())
+}
+
+/// Replaces the sizes of directories with their calculated total sizes.
+/// Returns, whether any size was known.
+fn apply_directory_sizes(files: &mut [File], directory_sizes: &HashMap<String, u64>) -> bool {
+	let mut applied = false;
+	for file in files
+		.iter_mut()
+		.filter(|file| file.is_dir && !is_parent_item(file))
+	{
+		if let (Some(bytes), Some(index)) = (
+			directory_sizes.get(&get_path(file)),
+			file.get_field_index("size"),
+		) {
+			file.fields[index] = Value::Size { bytes: *bytes };
+			applied = true;
+		}
+	}
+	applied
+}
+
+fn get_size(file: &File) -> u64 {
+	match file
+		.get_field_index("size")
+		.map(|index| &file.fields[index])
+	{
+		Some(Value::Size { bytes }) => *bytes,
+		_ => 0,
+	}
+}
+
+fn get_extension(file: &File) -> &str {
+	match file
+		.get_field_index("extension")
+		.map(|index| &file.fields[index])
+	{
+		Some(Value::String { string }) => string,
+		_ => "",
+	}
+}
+
+pub fn is_parent_item(file: &File) -> bool {
+	if let Some(path_index) = file.get_field_index("path") {
+		if let Value::Path { name, .. } = &file.fields[path_index] {
+			return name == "[..]";
+		}
+	}
+	false
+}
+
 fn get_path_at(file: &File, path_index: Option<usize>) -> String {
 	if let Some(path_index) = path_index {
 		if let Value::Path { path, .. } = &file.fields[path_index] {
@@ -322,3 +1913,103 @@ fn get_path_at(file: &File, path_index: Option<usize>) -> String {
 		"".to_owned()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn new_file(name: &str, is_dir: bool, bytes: u64, selected: bool) -> File {
+		let extension = match name.rfind('.') {
+			Some(index) if !is_dir && index > 0 => &name[index + 1..],
+			_ => "",
+		};
+		File {
+			selected,
+			is_dir,
+			field_names: Arc::new(vec![
+				"path".to_owned(),
+				"size".to_owned(),
+				"extension".to_owned(),
+			]),
+			fields: vec![
+				Value::Path {
+					path: format!("/tmp/{}", name),
+					name: name.to_owned(),
+					icon: Icon::Local(String::new()),
+				},
+				Value::Size { bytes },
+				Value::String {
+					string: extension.to_owned(),
+				},
+			],
+		}
+	}
+
+	fn new_files() -> Vec<File> {
+		vec![
+			new_file("[..]", true, 0, false),
+			new_file("docs", true, 300, true),
+			new_file("src", true, 0, false),
+			new_file("Cargo.toml", false, 20, true),
+			new_file("README.md", false, 100, false),
+			new_file("notes.md", false, 5, true),
+		]
+	}
+
+	#[test]
+	fn selection_is_counted_without_the_parent_item() {
+		let (selected, total) = count_selection(&new_files());
+		assert_eq!(
+			(selected.files, selected.directories, selected.bytes),
+			(2, 1, 325)
+		);
+		assert_eq!((total.files, total.directories, total.bytes), (3, 2, 425));
+	}
+
+	#[test]
+	fn globs_select_files_ignoring_case() {
+		let files = new_files();
+		assert_eq!(get_pattern_matches(&files, "*.MD", true).unwrap(), [4, 5]);
+		assert_eq!(
+			get_pattern_matches(&files, "cargo.*; readme*", true).unwrap(),
+			[3, 4]
+		);
+		assert_eq!(get_pattern_matches(&files, "*", true).unwrap(), [3, 4, 5]);
+	}
+
+	#[test]
+	fn patterns_ending_with_a_separator_select_directories() {
+		let files = new_files();
+		assert_eq!(get_pattern_matches(&files, "*/", true).unwrap(), [1, 2]);
+		assert_eq!(get_pattern_matches(&files, "d*\\", false).unwrap(), [1]);
+	}
+
+	#[test]
+	fn deselecting_matches_files_and_directories() {
+		let files = new_files();
+		assert_eq!(
+			get_pattern_matches(&files, "*", false).unwrap(),
+			[1, 2, 3, 4, 5]
+		);
+	}
+
+	#[test]
+	fn regular_expressions_start_with_a_slash() {
+		let files = new_files();
+		assert_eq!(get_pattern_matches(&files, "/^[rn]", true).unwrap(), [4, 5]);
+		assert!(get_pattern_matches(&files, "/(", true).is_err());
+	}
+
+	#[test]
+	fn quick_search_prefers_prefix_matches() {
+		let files = new_files();
+		let matcher = Matcher::new("md").unwrap();
+		assert_eq!(get_best_match(&matcher, &files), Some(4));
+		let matcher = Matcher::new("no").unwrap();
+		assert_eq!(get_best_match(&matcher, &files), Some(5));
+		let matcher = Matcher::new("..").unwrap();
+		assert_eq!(get_best_match(&matcher, &files), None);
+		let matcher = Matcher::new("*.toml").unwrap();
+		assert_eq!(get_best_match(&matcher, &files), Some(3));
+	}
+}
@@ -0,0 +1,73 @@
+use regex::{Regex, RegexBuilder};
+
+/// Query typed into a pane to jump to or filter files by name.
+pub struct QuickSearch {
+	pub query: String,
+	/// Hides files that do not match, instead of only jumping to the best match.
+	pub filter: bool,
+}
+
+/// Matches file names against a query. Queries starting with `/` are regular
+/// expressions, queries containing `*` or `?` are globs and other queries
+/// match prefixes and substrings.
+pub enum Matcher {
+	Text(String),
+	Pattern(Regex),
+}
+
+impl Matcher {
+	pub fn new(query: &str) -> Result<Matcher, regex::Error> {
+		if query.starts_with('/') {
+			Ok(Matcher::Pattern(
+				RegexBuilder::new(&query[1..])
+					.case_insensitive(true)
+					.build()?,
+			))
+		} else if query.contains(|c| c == '*' || c == '?') {
+			Ok(Matcher::Pattern(
+				RegexBuilder::new(&glob_to_regex(query))
+					.case_insensitive(true)
+					.build()?,
+			))
+		} else {
+			Ok(Matcher::Text(query.to_lowercase()))
+		}
+	}
+
+	/// Returns 0 for prefix matches, 1 for other matches and `None`, if the
+	/// name does not match.
+	pub fn get_rank(&self, name: &str) -> Option<u8> {
+		match self {
+			Matcher::Text(text) => {
+				let name = name.to_lowercase();
+				if name.starts_with(text.as_str()) {
+					Some(0)
+				} else if name.contains(text.as_str()) {
+					Some(1)
+				} else {
+					None
+				}
+			}
+			Matcher::Pattern(regex) => {
+				if regex.is_match(name) {
+					Some(0)
+				} else {
+					None
+				}
+			}
+		}
+	}
+}
+
+pub fn glob_to_regex(glob: &str) -> String {
+	let mut pattern = String::from("^");
+	for c in glob.chars() {
+		match c {
+			'*' => pattern.push_str(".*"),
+			'?' => pattern.push('.'),
+			_ => pattern.push_str(&regex::escape(&c.to_string())),
+		}
+	}
+	pattern.push('$');
+	pattern
+}
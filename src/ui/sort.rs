@@ -0,0 +1,153 @@
+use crate::ui::pane::{get_path_filename, is_parent_item};
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+use xcmd_core::api::{File, Value};
+
+/// Order of files in a pane. The parent item always stays on top.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortOrder {
+	pub field_index: usize,
+	pub descending: bool,
+	pub directories_first: bool,
+}
+
+impl Default for SortOrder {
+	fn default() -> Self {
+		SortOrder {
+			field_index: 0,
+			descending: false,
+			directories_first: true,
+		}
+	}
+}
+
+impl SortOrder {
+	/// Sorts by another field, or reverses the order, if it is the current one.
+	pub fn toggle(&self, field_index: usize) -> SortOrder {
+		SortOrder {
+			field_index,
+			descending: field_index == self.field_index && !self.descending,
+			directories_first: self.directories_first,
+		}
+	}
+
+	pub fn sort(&self, files: &mut Vec<File>) {
+		files.sort_by(|a, b| self.compare(a, b));
+	}
+
+	fn compare(&self, a: &File, b: &File) -> Ordering {
+		let parent_ordering = is_parent_item(b).cmp(&is_parent_item(a));
+		if parent_ordering != Ordering::Equal {
+			return parent_ordering;
+		}
+		if self.directories_first && a.is_dir != b.is_dir {
+			return b.is_dir.cmp(&a.is_dir);
+		}
+		let ordering = compare_fields(a, b, self.field_index).then_with(|| compare_fields(a, b, 0));
+		if self.descending {
+			ordering.reverse()
+		} else {
+			ordering
+		}
+	}
+}
+
+fn compare_fields(a: &File, b: &File, field_index: usize) -> Ordering {
+	match (a.fields.get(field_index), b.fields.get(field_index)) {
+		(Some(a), Some(b)) => compare_values(a, b),
+		(a, b) => a.is_some().cmp(&b.is_some()),
+	}
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+	match (a, b) {
+		(Value::Path { path: a, .. }, Value::Path { path: b, .. }) => {
+			compare_natural(get_path_filename(a), get_path_filename(b))
+		}
+		(Value::String { string: a }, Value::String { string: b }) => compare_natural(a, b),
+		(Value::Size { bytes: a }, Value::Size { bytes: b }) => a.cmp(b),
+		(Value::DateTime { time: a }, Value::DateTime { time: b }) => a.cmp(b),
+		(Value::Permissions { mode: a }, Value::Permissions { mode: b }) => a.cmp(b),
+		(Value::Attributes { attributes: a }, Value::Attributes { attributes: b }) => a.cmp(b),
+		(
+			Value::Owner {
+				user: a_user,
+				group: a_group,
+			},
+			Value::Owner {
+				user: b_user,
+				group: b_group,
+			},
+		) => compare_natural(a_user, b_user).then_with(|| compare_natural(a_group, b_group)),
+		(Value::Link { target: a }, Value::Link { target: b }) => compare_natural(a, b),
+		(Value::Empty, Value::Empty) => Ordering::Equal,
+		(Value::Empty, _) => Ordering::Less,
+		(_, Value::Empty) => Ordering::Greater,
+		_ => compare_natural(&a.to_string(), &b.to_string()),
+	}
+}
+
+/// Compares case-insensitively and numbers by their value, so that `file9`
+/// comes before `file10`.
+pub fn compare_natural(a: &str, b: &str) -> Ordering {
+	let mut a_chars = a.chars().peekable();
+	let mut b_chars = b.chars().peekable();
+	loop {
+		let ordering = match (a_chars.peek(), b_chars.peek()) {
+			(None, None) => return a.cmp(b),
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+				let a_number = take_number(&mut a_chars);
+				let b_number = take_number(&mut b_chars);
+				let a_digits = a_number.trim_start_matches('0');
+				let b_digits = b_number.trim_start_matches('0');
+				a_digits
+					.len()
+					.cmp(&b_digits.len())
+					.then_with(|| a_digits.cmp(b_digits))
+			}
+			(Some(x), Some(y)) => {
+				let ordering = x.to_lowercase().cmp(y.to_lowercase());
+				a_chars.next();
+				b_chars.next();
+				ordering
+			}
+		};
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+	}
+}
+
+fn take_number(chars: &mut Peekable<Chars<'_>>) -> String {
+	let mut number = String::new();
+	while let Some(c) = chars.peek() {
+		if !c.is_ascii_digit() {
+			break;
+		}
+		number.push(*c);
+		chars.next();
+	}
+	number
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn numbers_are_compared_by_value() {
+		let mut names = vec!["file10", "file9", "file1", "file010", "file"];
+		names.sort_by(|a, b| compare_natural(a, b));
+		assert_eq!(names, ["file", "file1", "file9", "file010", "file10"]);
+	}
+
+	#[test]
+	fn letters_are_compared_ignoring_case() {
+		assert_eq!(compare_natural("apple", "Banana"), Ordering::Less);
+		assert_eq!(compare_natural("Apple", "apple"), "Apple".cmp("apple"));
+		assert_eq!(compare_natural("a2b", "A10a"), Ordering::Less);
+	}
+}
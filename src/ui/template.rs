@@ -1,5 +1,5 @@
 use regex::{Captures, Regex};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct Template {
 	regex: Regex,
@@ -14,8 +14,16 @@ impl Template {
 		}
 	}
 
-	pub fn parse_toml(toml: &str) -> HashMap<String, String> {
-		toml::from_str::<HashMap<String, String>>(toml).unwrap()
+	pub fn parse_toml(toml: &str) -> Result<HashMap<String, String>, toml::de::Error> {
+		toml::from_str::<HashMap<String, String>>(toml)
+	}
+
+	/// Returns the names of all variables used in the template.
+	pub fn get_variable_names(&self) -> HashSet<String> {
+		self.regex
+			.captures_iter(&self.template)
+			.map(|captures| captures[1].to_owned())
+			.collect()
 	}
 
 	pub fn render(&self, map: &HashMap<String, String>) -> String {
@@ -24,7 +32,10 @@ impl Template {
 		regex
 			.replace_all(&self.template, |captures: &Captures| {
 				let value = captures.get(1).expect("capture 1").as_str();
-				map.get(value).unwrap_or(&default_value)
+				map.get(value).unwrap_or_else(|| {
+					log::error!("Missing template variable: {}", value);
+					&default_value
+				})
 			})
 			.to_string()
 	}
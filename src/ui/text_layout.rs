@@ -0,0 +1,319 @@
+use std::convert::TryFrom;
+
+/// Rows without line breaks are broken after this many characters, so a row
+/// never has to be read in full from a huge file.
+const MAX_ROW_CHARS: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+	Utf8,
+	Utf16Le,
+	Utf16Be,
+	Latin1,
+}
+
+/// The result of inspecting the start of a file.
+pub struct Detection {
+	pub encoding: Encoding,
+	/// Length of the byte order mark, which is not shown.
+	pub bom_length: u64,
+	pub is_binary: bool,
+}
+
+impl Encoding {
+	/// Detects the encoding from the byte order mark or else the content of
+	/// the sample. Text without a known encoding is read as Latin-1.
+	pub fn detect(sample: &[u8]) -> Detection {
+		let (encoding, bom_length) = if sample.starts_with(&[0xef, 0xbb, 0xbf]) {
+			(Some(Encoding::Utf8), 3)
+		} else if sample.starts_with(&[0xff, 0xfe]) {
+			(Some(Encoding::Utf16Le), 2)
+		} else if sample.starts_with(&[0xfe, 0xff]) {
+			(Some(Encoding::Utf16Be), 2)
+		} else {
+			(None, 0)
+		};
+		if let Some(encoding) = encoding {
+			return Detection {
+				encoding,
+				bom_length,
+				is_binary: false,
+			};
+		}
+		let count_zeros = |start: usize| {
+			sample
+				.iter()
+				.skip(start)
+				.step_by(2)
+				.filter(|x| **x == 0)
+				.count()
+		};
+		let (even_zeros, odd_zeros) = (count_zeros(0), count_zeros(1));
+		let half = sample.len() / 2;
+		let encoding = if half > 0 && odd_zeros > half / 2 && even_zeros == 0 {
+			Encoding::Utf16Le
+		} else if half > 0 && even_zeros > half / 2 && odd_zeros == 0 {
+			Encoding::Utf16Be
+		} else if is_utf8(sample) {
+			Encoding::Utf8
+		} else {
+			Encoding::Latin1
+		};
+		Detection {
+			encoding,
+			bom_length: 0,
+			is_binary: even_zeros + odd_zeros > 0
+				&& (encoding == Encoding::Utf8 || encoding == Encoding::Latin1),
+		}
+	}
+
+	pub fn get_name(self) -> &'static str {
+		match self {
+			Encoding::Utf8 => "UTF-8",
+			Encoding::Utf16Le => "UTF-16 LE",
+			Encoding::Utf16Be => "UTF-16 BE",
+			Encoding::Latin1 => "Latin-1",
+		}
+	}
+
+	/// Returns the encoding chosen after this one by the user.
+	pub fn next(self) -> Encoding {
+		match self {
+			Encoding::Utf8 => Encoding::Latin1,
+			Encoding::Latin1 => Encoding::Utf16Le,
+			Encoding::Utf16Le => Encoding::Utf16Be,
+			Encoding::Utf16Be => Encoding::Utf8,
+		}
+	}
+
+	/// Returns the size of a code unit in bytes.
+	pub fn get_unit_size(self) -> usize {
+		match self {
+			Encoding::Utf16Le | Encoding::Utf16Be => 2,
+			Encoding::Utf8 | Encoding::Latin1 => 1,
+		}
+	}
+
+	fn get_unit(self, bytes: &[u8], index: usize) -> u16 {
+		match self {
+			Encoding::Utf16Le => u16::from_le_bytes([bytes[index], bytes[index + 1]]),
+			Encoding::Utf16Be => u16::from_be_bytes([bytes[index], bytes[index + 1]]),
+			Encoding::Utf8 | Encoding::Latin1 => u16::from(bytes[index]),
+		}
+	}
+
+	/// Tells, whether a code unit starts a character.
+	fn is_char_start(self, unit: u16) -> bool {
+		match self {
+			Encoding::Utf8 => unit & 0xc0 != 0x80,
+			Encoding::Utf16Le | Encoding::Utf16Be => !(0xdc00..0xe000).contains(&unit),
+			Encoding::Latin1 => true,
+		}
+	}
+
+	pub fn decode(self, bytes: &[u8]) -> String {
+		match self {
+			Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+			Encoding::Utf16Le | Encoding::Utf16Be => {
+				let units: Vec<u16> = (0..bytes.len() / 2)
+					.map(|index| self.get_unit(bytes, index * 2))
+					.collect();
+				String::from_utf16_lossy(&units)
+			}
+			Encoding::Latin1 => bytes.iter().map(|x| char::from(*x)).collect(),
+		}
+	}
+
+	/// Encodes text for searching. Characters missing in Latin-1 are replaced
+	/// by `?`.
+	pub fn encode(self, text: &str) -> Vec<u8> {
+		match self {
+			Encoding::Utf8 => text.as_bytes().to_vec(),
+			Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+			Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+			Encoding::Latin1 => text
+				.chars()
+				.map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+				.collect(),
+		}
+	}
+}
+
+/// Splits text into the rows shown by the viewer.
+#[derive(Clone, Copy)]
+pub struct TextLayout {
+	pub encoding: Encoding,
+	/// Number of characters, after which rows are wrapped.
+	pub wrap_width: Option<usize>,
+}
+
+impl TextLayout {
+	/// Returns the length in bytes of the row at the start of the data,
+	/// including its line break. Returns `None`, if the data ends before the
+	/// row does, unless the data reaches the end of the file.
+	pub fn get_row_length(&self, data: &[u8], at_end: bool) -> Option<usize> {
+		let encoding = self.encoding;
+		let unit_size = encoding.get_unit_size();
+		let max_chars = self.wrap_width.unwrap_or(MAX_ROW_CHARS).max(1);
+		let mut chars = 0;
+		let mut index = 0;
+		while index + unit_size <= data.len() {
+			let unit = encoding.get_unit(data, index);
+			if unit == u16::from(b'\n') {
+				return Some(index + unit_size);
+			}
+			if unit == u16::from(b'\r') {
+				let next_index = index + unit_size;
+				return if next_index + unit_size <= data.len() {
+					if encoding.get_unit(data, next_index) == u16::from(b'\n') {
+						Some(next_index + unit_size)
+					} else {
+						Some(next_index)
+					}
+				} else if at_end {
+					Some(data.len())
+				} else {
+					None
+				};
+			}
+			if encoding.is_char_start(unit) {
+				if chars == max_chars {
+					return Some(index);
+				}
+				chars += 1;
+			}
+			index += unit_size;
+		}
+		if at_end && !data.is_empty() {
+			Some(data.len())
+		} else {
+			None
+		}
+	}
+
+	/// Decodes a row for display, without its line break.
+	pub fn decode_row(&self, row: &[u8]) -> String {
+		let mut text = String::with_capacity(row.len());
+		for c in self
+			.encoding
+			.decode(row)
+			.trim_end_matches(&['\n', '\r'][..])
+			.chars()
+		{
+			match c {
+				'\t' => text.push_str("    "),
+				c if c.is_control() => text.push('\u{b7}'),
+				c => text.push(c),
+			}
+		}
+		text
+	}
+}
+
+fn is_utf8(sample: &[u8]) -> bool {
+	match std::str::from_utf8(sample) {
+		Ok(_) => true,
+		// The sample may end in the middle of a character.
+		Err(e) => e.error_len().is_none(),
+	}
+}
+
+/// Finds the pattern in the data, ignoring the case of ASCII letters. Matches
+/// start at a multiple of `alignment`.
+pub fn find_ignore_case(data: &[u8], pattern: &[u8], alignment: usize) -> Option<usize> {
+	if pattern.is_empty() || data.len() < pattern.len() {
+		return None;
+	}
+	(0..=data.len() - pattern.len())
+		.step_by(alignment)
+		.find(|index| data[*index..*index + pattern.len()].eq_ignore_ascii_case(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn layout(encoding: Encoding, wrap_width: Option<usize>) -> TextLayout {
+		TextLayout {
+			encoding,
+			wrap_width,
+		}
+	}
+
+	#[test]
+	fn encoding_is_detected_from_the_bom() {
+		let detection = Encoding::detect(b"\xef\xbb\xbfabc");
+		assert!(detection.encoding == Encoding::Utf8);
+		assert_eq!(detection.bom_length, 3);
+		let detection = Encoding::detect(b"\xff\xfea\0");
+		assert!(detection.encoding == Encoding::Utf16Le);
+		assert_eq!(detection.bom_length, 2);
+		assert!(!detection.is_binary);
+		let detection = Encoding::detect(b"\xfe\xff\0a");
+		assert!(detection.encoding == Encoding::Utf16Be);
+		assert_eq!(detection.bom_length, 2);
+	}
+
+	#[test]
+	fn encoding_is_detected_from_the_content() {
+		let detection = Encoding::detect(&Encoding::Utf16Le.encode("Hello\r\n"));
+		assert!(detection.encoding == Encoding::Utf16Le);
+		assert_eq!(detection.bom_length, 0);
+		assert!(!detection.is_binary);
+		let detection = Encoding::detect(&Encoding::Utf16Be.encode("Hello\r\n"));
+		assert!(detection.encoding == Encoding::Utf16Be);
+		assert!(!detection.is_binary);
+		// The sample may end within a character.
+		assert!(Encoding::detect(&"aé".as_bytes()[..2]).encoding == Encoding::Utf8);
+		assert!(Encoding::detect(b"caf\xe9").encoding == Encoding::Latin1);
+		let detection = Encoding::detect(b"ab\0\x01cd\0e");
+		assert!(detection.encoding == Encoding::Utf8);
+		assert!(detection.is_binary);
+	}
+
+	#[test]
+	fn rows_end_after_line_breaks() {
+		let layout = layout(Encoding::Utf8, None);
+		assert_eq!(layout.get_row_length(b"ab\ncd", false), Some(3));
+		assert_eq!(layout.get_row_length(b"ab\r\ncd", false), Some(4));
+		assert_eq!(layout.get_row_length(b"ab\rcd", false), Some(3));
+		assert_eq!(layout.get_row_length(b"ab", false), None);
+		assert_eq!(layout.get_row_length(b"ab", true), Some(2));
+		assert_eq!(layout.get_row_length(b"", true), None);
+	}
+
+	#[test]
+	fn line_breaks_split_across_chunks_wait_for_more_data() {
+		let layout8 = layout(Encoding::Utf8, None);
+		assert_eq!(layout8.get_row_length(b"ab\r", false), None);
+		assert_eq!(layout8.get_row_length(b"ab\r", true), Some(3));
+		let layout16 = layout(Encoding::Utf16Le, None);
+		let data = Encoding::Utf16Le.encode("ab\r\n");
+		assert_eq!(layout16.get_row_length(&data[..6], false), None);
+		assert_eq!(layout16.get_row_length(&data[..7], false), None);
+		assert_eq!(layout16.get_row_length(&data, false), Some(8));
+	}
+
+	#[test]
+	fn rows_are_wrapped_after_characters() {
+		let layout8 = layout(Encoding::Utf8, Some(2));
+		assert_eq!(layout8.get_row_length("äöü\n".as_bytes(), false), Some(4));
+		assert_eq!(layout8.get_row_length("ü\n".as_bytes(), false), Some(3));
+		assert_eq!(layout8.get_row_length("ä€".as_bytes(), false), None);
+		let layout16 = layout(Encoding::Utf16Be, Some(2));
+		let data = Encoding::Utf16Be.encode("😀😀😀");
+		assert_eq!(layout16.get_row_length(&data, false), Some(8));
+		let layout = layout(Encoding::Utf8, None);
+		let data = vec![b'x'; MAX_ROW_CHARS + 1];
+		assert_eq!(layout.get_row_length(&data, false), Some(MAX_ROW_CHARS));
+	}
+
+	#[test]
+	fn matches_ignore_case_and_are_aligned() {
+		assert_eq!(find_ignore_case(b"Hello World", b"WORLD", 1), Some(6));
+		assert_eq!(find_ignore_case(b"Hello", b"", 1), None);
+		assert_eq!(find_ignore_case(b"Hello", b"Hello!", 1), None);
+		assert_eq!(find_ignore_case(b"xab_AB", b"ab", 2), Some(4));
+		assert_eq!(find_ignore_case(b"xab", b"ab", 2), None);
+	}
+}
@@ -0,0 +1,167 @@
+use crate::config;
+use crate::ui::Template;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+const THEME_EXTENSION: &str = ".color-theme.toml";
+
+const BUILT_IN_THEMES: &[(&str, &str)] = &[
+	("dark", include_str!("../../config/dark.color-theme.toml")),
+	("light", include_str!("../../config/light.color-theme.toml")),
+	(
+		"classic",
+		include_str!("../../config/classic.color-theme.toml"),
+	),
+];
+
+pub struct Theme {
+	name: String,
+	variables: HashMap<String, String>,
+	/// Variables used by the templates, but not defined by the theme.
+	missing: Vec<String>,
+	/// Variables defined by the theme, but not used by any template.
+	unknown: Vec<String>,
+}
+
+impl Theme {
+	fn new(
+		name: &str,
+		variables: HashMap<String, String>,
+		variable_names: &HashSet<String>,
+	) -> Self {
+		let mut missing: Vec<String> = variable_names
+			.iter()
+			.filter(|x| !variables.contains_key(*x))
+			.cloned()
+			.collect();
+		missing.sort();
+		let mut unknown: Vec<String> = variables
+			.keys()
+			.filter(|x| !variable_names.contains(*x))
+			.cloned()
+			.collect();
+		unknown.sort();
+		Theme {
+			name: name.to_owned(),
+			variables,
+			missing,
+			unknown,
+		}
+	}
+
+	pub fn get_name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.missing.is_empty()
+	}
+
+	/// Describes the missing and unknown variables, if there are any.
+	pub fn get_problems(&self) -> Vec<String> {
+		let missing = self
+			.missing
+			.iter()
+			.map(|x| format!("Missing variable '{}'.", x));
+		let unknown = self
+			.unknown
+			.iter()
+			.map(|x| format!("Unknown variable '{}'.", x));
+		missing.chain(unknown).collect()
+	}
+
+	pub fn render(&self, template: &Template) -> String {
+		template.render(&self.variables)
+	}
+}
+
+/// The built-in color themes and the `*.color-theme.toml` files in the
+/// configuration directory. User themes replace built-in themes of the same
+/// name.
+pub struct Themes {
+	themes: Vec<Theme>,
+	current: usize,
+}
+
+impl Themes {
+	/// Loads the themes and validates them against the variables used by the
+	/// templates.
+	pub fn load(variable_names: &HashSet<String>) -> Self {
+		let mut themes: Vec<Theme> = BUILT_IN_THEMES
+			.iter()
+			.map(|(name, toml)| {
+				let variables = Template::parse_toml(toml).expect("built-in theme");
+				Theme::new(name, variables, variable_names)
+			})
+			.collect();
+		for (name, toml) in read_user_themes() {
+			match Template::parse_toml(&toml) {
+				Ok(variables) => {
+					let theme = Theme::new(&name, variables, variable_names);
+					themes.retain(|x| x.name != name);
+					themes.push(theme);
+				}
+				Err(e) => log::error!("Failed to parse theme {}: {}", name, e),
+			}
+		}
+		for theme in &themes {
+			for problem in theme.get_problems() {
+				log::warn!("Theme {}: {}", theme.name, problem);
+			}
+		}
+		let current = themes.iter().position(|x| x.name == "dark").unwrap_or(0);
+		Themes { themes, current }
+	}
+
+	pub fn get_names(&self) -> Vec<&str> {
+		self.themes.iter().map(|x| x.name.as_str()).collect()
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Theme> {
+		self.themes.iter().find(|x| x.name == name)
+	}
+
+	pub fn get_current(&self) -> &Theme {
+		&self.themes[self.current]
+	}
+
+	/// Makes the theme current. Returns false, if there is no valid theme with
+	/// the name.
+	pub fn select(&mut self, name: &str) -> bool {
+		match self.themes.iter().position(|x| x.name == name) {
+			Some(index) if self.themes[index].is_valid() => {
+				self.current = index;
+				true
+			}
+			_ => false,
+		}
+	}
+}
+
+fn read_user_themes() -> Vec<(String, String)> {
+	let config_dir = match config::config_dir() {
+		Some(config_dir) => config_dir,
+		None => return Vec::new(),
+	};
+	let read_dir = match fs::read_dir(&config_dir) {
+		Ok(read_dir) => read_dir,
+		Err(_) => return Vec::new(),
+	};
+	let mut user_themes = Vec::new();
+	for entry in read_dir.filter_map(Result::ok) {
+		let file_name = entry.file_name().to_string_lossy().into_owned();
+		if !file_name.ends_with(THEME_EXTENSION) {
+			continue;
+		}
+		let name = file_name[..file_name.len() - THEME_EXTENSION.len()].to_owned();
+		match fs::read_to_string(entry.path()) {
+			Ok(toml) => {
+				log::info!("Loaded color theme: {:?}", entry.path());
+				user_themes.push((name, toml));
+			}
+			Err(e) => log::error!("Failed to read {:?}: {}", entry.path(), e),
+		}
+	}
+	user_themes.sort();
+	user_themes
+}
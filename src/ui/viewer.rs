@@ -0,0 +1,1026 @@
+use crate::data_source::DataSource;
+use crate::ui::file_reader::{FileReader, PAGE_SIZE};
+use crate::ui::pane::{get_path_filename, lock_system};
+use crate::ui::text_layout::{find_ignore_case, Encoding, TextLayout};
+use crate::ui::JobMessage;
+use sciter::dom::event::{BEHAVIOR_EVENTS, CLICK_REASON};
+use sciter::dom::ELEMENT_STATE_BITS;
+use sciter::{Element, Value};
+use separator::Separatable;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use xcmd_core::api::{Error, System};
+use xcmd_core::jobs::{JobContext, JobId, JobSpawner};
+
+/// The offset of every this many rows is kept in the index of text rows.
+const INDEX_STEP: u64 = 256;
+const INDEX_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const READ_SIZE: usize = 256 * 1024;
+const HEX_ROW_SIZE: u64 = 16;
+/// Larger images are shown in hex mode, as they are read into memory.
+const IMAGE_SIZE_LIMIT: u64 = 32 * 1024 * 1024;
+const IMAGE_TYPES: &[(&str, &str)] = &[
+	("png", "image/png"),
+	("jpg", "image/jpeg"),
+	("jpeg", "image/jpeg"),
+	("gif", "image/gif"),
+	("bmp", "image/bmp"),
+	("webp", "image/webp"),
+	("svg", "image/svg+xml"),
+	("ico", "image/x-icon"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ViewMode {
+	Text,
+	Hex,
+	Image,
+}
+
+/// Offsets of text rows, built on a worker thread.
+#[derive(Default)]
+struct RowIndex {
+	/// Offsets of the rows at multiples of `INDEX_STEP`.
+	checkpoints: Vec<u64>,
+	row_count: u64,
+	finished: bool,
+}
+
+/// A file opened on a worker thread with its first page, which is used to
+/// detect the encoding.
+pub struct OpenedFile {
+	system: Arc<Mutex<Box<dyn System>>>,
+	size: u64,
+	first_page: Vec<u8>,
+}
+
+/// Rows of the viewed file for the vtable. Rows are read, when they are shown.
+pub struct ViewerDataSource {
+	reader: Option<FileReader>,
+	mode: ViewMode,
+	layout: TextLayout,
+	/// Offset of the first row, after the byte order mark.
+	start: u64,
+	index: RowIndex,
+	/// The row of the last match of the search.
+	active_index: Option<u64>,
+}
+
+impl ViewerDataSource {
+	pub fn new() -> Self {
+		ViewerDataSource {
+			reader: None,
+			mode: ViewMode::Text,
+			layout: TextLayout {
+				encoding: Encoding::Utf8,
+				wrap_width: None,
+			},
+			start: 0,
+			index: RowIndex::default(),
+			active_index: None,
+		}
+	}
+
+	fn get_row_count(&self) -> u64 {
+		match (&self.reader, self.mode) {
+			(Some(_reader), ViewMode::Text) => self.index.row_count,
+			(Some(reader), ViewMode::Hex) => (reader.get_size() + HEX_ROW_SIZE - 1) / HEX_ROW_SIZE,
+			_ => 0,
+		}
+	}
+
+	/// Returns the offset of the first byte of a row.
+	fn get_row_offset(&self, row_index: u64) -> Option<u64> {
+		if self.mode == ViewMode::Hex {
+			return Some(row_index * HEX_ROW_SIZE);
+		}
+		let mut row_offset = None;
+		let mut skip = row_index % INDEX_STEP;
+		self.walk_rows(row_index, |offset, _row| {
+			if skip == 0 {
+				row_offset = Some(offset);
+				false
+			} else {
+				skip -= 1;
+				true
+			}
+		});
+		row_offset
+	}
+
+	/// Returns the row containing the byte at the offset.
+	fn get_offset_row(&self, offset: u64) -> Option<u64> {
+		if self.mode == ViewMode::Hex {
+			return Some(offset / HEX_ROW_SIZE);
+		}
+		let checkpoints = &self.index.checkpoints;
+		let checkpoint = match checkpoints.binary_search(&offset) {
+			Ok(checkpoint) => checkpoint,
+			Err(0) => return None,
+			Err(checkpoint) => checkpoint - 1,
+		};
+		let mut row_index = checkpoint as u64 * INDEX_STEP;
+		let mut found = false;
+		self.walk_rows(row_index, |row_offset, row| {
+			if offset < row_offset + row.len() as u64 {
+				found = true;
+				false
+			} else {
+				row_index += 1;
+				true
+			}
+		});
+		if found {
+			Some(row_index)
+		} else {
+			None
+		}
+	}
+
+	/// Passes rows to `f`, starting at the indexed row before `row_index`,
+	/// until it returns false or the file ends.
+	fn walk_rows<F>(&self, row_index: u64, mut f: F)
+	where
+		F: FnMut(u64, &[u8]) -> bool,
+	{
+		let reader = match &self.reader {
+			Some(reader) => reader,
+			None => return,
+		};
+		let mut buffer_offset = match self
+			.index
+			.checkpoints
+			.get((row_index / INDEX_STEP) as usize)
+		{
+			Some(offset) => *offset,
+			None => return,
+		};
+		let mut buffer = reader.read(buffer_offset, READ_SIZE);
+		let mut position = 0;
+		loop {
+			let at_end = buffer_offset + buffer.len() as u64 >= reader.get_size();
+			match self.layout.get_row_length(&buffer[position..], at_end) {
+				Some(length) => {
+					let row = &buffer[position..position + length];
+					if !f(buffer_offset + position as u64, row) {
+						return;
+					}
+					position += length;
+				}
+				// Nothing more could be read.
+				None if at_end || position == 0 => return,
+				None => {
+					buffer_offset += position as u64;
+					buffer = reader.read(buffer_offset, READ_SIZE);
+					position = 0;
+				}
+			}
+		}
+	}
+
+	fn get_text_rows(&self, row_index: u64, row_count: usize) -> Vec<String> {
+		let mut rows = Vec::with_capacity(row_count);
+		let mut skip = row_index % INDEX_STEP;
+		self.walk_rows(row_index, |_offset, row| {
+			if skip > 0 {
+				skip -= 1;
+			} else {
+				rows.push(self.layout.decode_row(row));
+			}
+			rows.len() < row_count
+		});
+		rows
+	}
+
+	fn get_hex_rows(&self, row_index: u64, row_count: usize) -> Vec<Vec<String>> {
+		let reader = match &self.reader {
+			Some(reader) => reader,
+			None => return Vec::new(),
+		};
+		let offset = row_index * HEX_ROW_SIZE;
+		let data = reader.read(offset, row_count * HEX_ROW_SIZE as usize);
+		data.chunks(HEX_ROW_SIZE as usize)
+			.enumerate()
+			.map(|(index, bytes)| {
+				let mut hex = String::new();
+				for (byte_index, byte) in bytes.iter().enumerate() {
+					if byte_index == 8 {
+						hex.push(' ');
+					}
+					write!(&mut hex, "{:02x} ", byte).unwrap();
+				}
+				let text = bytes
+					.iter()
+					.map(|byte| {
+						if (0x20..0x7f).contains(byte) {
+							char::from(*byte)
+						} else {
+							'.'
+						}
+					})
+					.collect();
+				let row_offset = offset + index as u64 * HEX_ROW_SIZE;
+				vec![format!("{:010x}", row_offset), hex, text]
+			})
+			.collect()
+	}
+}
+
+impl DataSource for ViewerDataSource {
+	fn data_source_columns(&self) -> Value {
+		let columns: Vec<(String, Option<i32>)> = match (&self.reader, self.mode) {
+			(Some(reader), ViewMode::Text) => {
+				vec![(get_path_filename(reader.get_path()).to_owned(), None)]
+			}
+			(Some(_reader), ViewMode::Hex) => vec![
+				("Offset".to_owned(), Some(90)),
+				("Hex".to_owned(), Some(420)),
+				("Text".to_owned(), Some(150)),
+			],
+			_ => Vec::new(),
+		};
+		let mut data = Value::array(columns.len());
+		for (index, (name, width)) in columns.into_iter().enumerate() {
+			let mut item = Value::map();
+			item.set_item(Value::from("name"), Value::from(name));
+			if let Some(width) = width {
+				item.set_item(Value::from("width"), Value::from(width));
+			}
+			data.set(index, item);
+		}
+		data
+	}
+
+	fn data_source_row_count(&self) -> i32 {
+		self.get_row_count().min(i32::MAX as u64) as i32
+	}
+
+	fn data_source_rows_data(&self, row_index: i32, row_count: i32) -> Value {
+		let mut rows = Value::array(row_count as usize);
+		let row_index = row_index.max(0) as u64;
+		let cells: Vec<Vec<String>> = match self.mode {
+			ViewMode::Text => self
+				.get_text_rows(row_index, row_count as usize)
+				.into_iter()
+				.map(|text| vec![text])
+				.collect(),
+			ViewMode::Hex => self.get_hex_rows(row_index, row_count as usize),
+			ViewMode::Image => Vec::new(),
+		};
+		for (index, texts) in cells.into_iter().enumerate() {
+			let mut row = Value::map();
+			if self.active_index == Some(row_index + index as u64) {
+				row.set_item(Value::from("active"), Value::from(true));
+			}
+			let mut row_cells = Value::array(texts.len());
+			for (cell_index, text) in texts.into_iter().enumerate() {
+				let mut cell = Value::map();
+				cell.set_item(Value::from("text"), Value::from(text));
+				row_cells.set(cell_index, cell);
+			}
+			row.set_item(Value::from("cells"), row_cells);
+			rows.set(index, row);
+		}
+		rows
+	}
+}
+
+/// Shows a file as text, hex dump or image on top of the panes. Files are
+/// read through their system, so remote files can be viewed, too.
+pub struct Viewer {
+	active: bool,
+	/// True while the search input has the focus.
+	searching: bool,
+	/// Identifies the jobs of the current file and layout.
+	generation: u64,
+	jobs: JobSpawner<JobMessage>,
+	index_job: Option<JobId>,
+	search_job: Option<JobId>,
+	image_job: Option<JobId>,
+	is_image: bool,
+	wrap: bool,
+	/// Offset of the last match of the search.
+	found_offset: Option<u64>,
+	pub data_source: Rc<RefCell<ViewerDataSource>>,
+	viewer: Element,
+	title: Element,
+	input: Element,
+	vtable: Element,
+	image: Element,
+	status: Element,
+}
+
+impl Viewer {
+	pub fn new(element: &mut Element, jobs: JobSpawner<JobMessage>) -> Viewer {
+		Viewer {
+			active: false,
+			searching: false,
+			generation: 0,
+			jobs,
+			index_job: None,
+			search_job: None,
+			image_job: None,
+			is_image: false,
+			wrap: false,
+			found_offset: None,
+			data_source: Rc::new(RefCell::new(ViewerDataSource::new())),
+			viewer: Element::from(element.as_ptr()),
+			title: element.find_first("header").unwrap().unwrap(),
+			input: element.find_first("input").unwrap().unwrap(),
+			vtable: element.find_first("vtable").unwrap().unwrap(),
+			image: element.find_first("img").unwrap().unwrap(),
+			status: element.find_first("status").unwrap().unwrap(),
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.active
+	}
+
+	pub fn is_searching(&self) -> bool {
+		self.searching
+	}
+
+	/// Called, when the search input gets or loses the focus.
+	pub fn set_searching(&mut self, searching: bool) {
+		self.searching = searching;
+	}
+
+	/// Opens the file on a worker thread. Directories are not viewed.
+	pub fn open(&mut self, system: Arc<Mutex<Box<dyn System>>>, path: &str) {
+		self.cancel_jobs();
+		let generation = self.generation;
+		let path = path.to_owned();
+		self.jobs.spawn(&format!("Open {}", path), move |context| {
+			let file = open_file(system, &path);
+			context.send(JobMessage::ViewerOpened {
+				generation,
+				path,
+				file,
+			});
+		});
+	}
+
+	/// Shows the file opened on a worker thread in text mode, in hex mode if
+	/// it looks binary, or as an image.
+	pub fn set_opened(
+		&mut self,
+		generation: u64,
+		path: &str,
+		file: Result<Option<OpenedFile>, Error>,
+	) -> Result<(), Error> {
+		if generation != self.generation {
+			return Ok(());
+		}
+		let file = match file? {
+			Some(file) => file,
+			None => return Ok(()),
+		};
+		let detection = Encoding::detect(&file.first_page);
+		let reader = FileReader::new(
+			file.system,
+			path,
+			file.size,
+			file.first_page,
+			generation,
+			self.jobs.clone(),
+		);
+		self.is_image = get_image_type(path).is_some() && reader.get_size() <= IMAGE_SIZE_LIMIT;
+		let mode = if self.is_image {
+			ViewMode::Image
+		} else if detection.is_binary {
+			ViewMode::Hex
+		} else {
+			ViewMode::Text
+		};
+		self.title.set_text(path).unwrap();
+		{
+			let mut data_source = self.data_source.borrow_mut();
+			data_source.reader = Some(reader);
+			data_source.layout.encoding = detection.encoding;
+			data_source.start = detection.bom_length;
+		}
+		self.active = true;
+		self.viewer.set_attribute("active", "").unwrap();
+		self.set_mode(mode);
+		Ok(())
+	}
+
+	/// Adds pages of the file read on a worker thread and shows them.
+	pub fn add_pages(&mut self, generation: u64, first_page: u64, pages: Vec<Vec<u8>>) {
+		let found_row = {
+			let data_source = self.data_source.borrow();
+			match &data_source.reader {
+				Some(reader) if reader.get_generation() == generation => {
+					reader.add_pages(first_page, pages)
+				}
+				_ => return,
+			}
+			// The row of a match may only be found, once its page is read.
+			match self.found_offset {
+				Some(offset) if data_source.active_index.is_none() => {
+					data_source.get_offset_row(offset)
+				}
+				_ => None,
+			}
+		};
+		if found_row.is_some() {
+			self.data_source.borrow_mut().active_index = found_row;
+		}
+		self.refresh(found_row);
+	}
+
+	pub fn close(&mut self) {
+		self.cancel_jobs();
+		self.active = false;
+		self.found_offset = None;
+		self.viewer.remove_attribute("active").unwrap();
+		self.image.remove_attribute("src").unwrap();
+		let mut data_source = self.data_source.borrow_mut();
+		data_source.reader = None;
+		data_source.index = RowIndex::default();
+		data_source.active_index = None;
+	}
+
+	/// Switches between the hex dump and the text or image.
+	pub fn toggle_hex(&mut self) {
+		let mode = match self.data_source.borrow().mode {
+			ViewMode::Hex if self.is_image => ViewMode::Image,
+			ViewMode::Hex => ViewMode::Text,
+			ViewMode::Text | ViewMode::Image => ViewMode::Hex,
+		};
+		self.set_mode(mode);
+	}
+
+	/// Wraps text rows at the width of the viewer.
+	pub fn toggle_wrap(&mut self) {
+		self.wrap = !self.wrap;
+		let wrap_width = if self.wrap {
+			self.vtable
+				.call_method("getTextColumns", &sciter::make_args!())
+				.ok()
+				.and_then(|columns| columns.to_int())
+				.map(|columns| columns.max(1) as usize)
+		} else {
+			None
+		};
+		let mode = {
+			let mut data_source = self.data_source.borrow_mut();
+			data_source.layout.wrap_width = wrap_width;
+			data_source.mode
+		};
+		self.set_mode(mode);
+	}
+
+	/// Reads the text with the next encoding.
+	pub fn next_encoding(&mut self) {
+		{
+			let mut data_source = self.data_source.borrow_mut();
+			data_source.layout.encoding = data_source.layout.encoding.next();
+		}
+		self.set_mode(ViewMode::Text);
+	}
+
+	fn set_mode(&mut self, mode: ViewMode) {
+		self.cancel_jobs();
+		self.found_offset = None;
+		{
+			let mut data_source = self.data_source.borrow_mut();
+			data_source.mode = mode;
+			data_source.index = RowIndex::default();
+			data_source.active_index = None;
+		}
+		let mode_name = match mode {
+			ViewMode::Text => "text",
+			ViewMode::Hex => "hex",
+			ViewMode::Image => "image",
+		};
+		self.viewer.set_attribute("mode", mode_name).unwrap();
+		match mode {
+			ViewMode::Text => self.start_indexing(),
+			ViewMode::Image => self.show_image(),
+			ViewMode::Hex => {}
+		}
+		self.show_status(None);
+		self.refresh(Some(0));
+	}
+
+	fn cancel_jobs(&mut self) {
+		self.generation += 1;
+		for id in self
+			.index_job
+			.take()
+			.into_iter()
+			.chain(self.search_job.take())
+			.chain(self.image_job.take())
+		{
+			self.jobs.cancel(id);
+		}
+	}
+
+	fn start_indexing(&mut self) {
+		let (system, path, start, layout) = {
+			let data_source = self.data_source.borrow();
+			let reader = match &data_source.reader {
+				Some(reader) => reader,
+				None => return,
+			};
+			(
+				reader.get_system(),
+				reader.get_path().to_owned(),
+				data_source.start,
+				data_source.layout,
+			)
+		};
+		let generation = self.generation;
+		self.index_job = Some(self.jobs.spawn(&format!("Index {}", path), move |context| {
+			index_rows(context, &system, &path, start, layout, generation);
+		}));
+	}
+
+	/// Adds rows indexed on a worker thread.
+	pub fn add_index(
+		&mut self,
+		generation: u64,
+		checkpoints: Vec<u64>,
+		row_count: u64,
+		finished: bool,
+	) {
+		if generation != self.generation {
+			return;
+		}
+		{
+			let mut data_source = self.data_source.borrow_mut();
+			let index = &mut data_source.index;
+			index.checkpoints.extend(checkpoints);
+			index.row_count = row_count;
+			index.finished = finished;
+		}
+		if finished {
+			self.index_job = None;
+		}
+		self.show_status(None);
+		self.refresh(None);
+	}
+
+	fn show_image(&mut self) {
+		let (system, path) = {
+			let data_source = self.data_source.borrow();
+			let reader = match &data_source.reader {
+				Some(reader) => reader,
+				None => return,
+			};
+			(reader.get_system(), reader.get_path().to_owned())
+		};
+		let generation = self.generation;
+		self.image_job = Some(self.jobs.spawn(&format!("Read {}", path), move |context| {
+			let data_url = read_image(&system, &path);
+			context.send(JobMessage::ViewerImage {
+				generation,
+				data_url,
+			});
+		}));
+	}
+
+	/// Shows the image read on a worker thread.
+	pub fn set_image(&mut self, generation: u64, data_url: Result<String, Error>) {
+		if generation != self.generation {
+			return;
+		}
+		self.image_job = None;
+		match data_url {
+			Ok(data_url) => self.image.set_attribute("src", &data_url).unwrap(),
+			Err(e) => {
+				log::error!("Failed to read the image: {}", e);
+				self.show_status(Some(&format!("Failed to read the image: {}", e)));
+			}
+		}
+	}
+
+	/// Focuses the search input.
+	pub fn find(&mut self) {
+		self.input
+			.set_state(ELEMENT_STATE_BITS::STATE_FOCUS, None, true)
+			.unwrap();
+		self.input
+			.call_method("selectAll", &sciter::make_args!())
+			.ok();
+	}
+
+	/// Searches the text typed in the search input after the last match or
+	/// else from the first shown row, ignoring the case of ASCII letters.
+	pub fn find_next(&mut self) {
+		let query = self.input.get_value().as_string().unwrap_or_default();
+		let (system, path, pattern, alignment, start, offset) = {
+			let data_source = self.data_source.borrow();
+			let reader = match &data_source.reader {
+				Some(reader) => reader,
+				None => return,
+			};
+			let encoding = data_source.layout.encoding;
+			let alignment = if data_source.mode == ViewMode::Text {
+				encoding.get_unit_size()
+			} else {
+				1
+			};
+			let offset = match self.found_offset {
+				Some(found_offset) => found_offset + alignment as u64,
+				None => data_source
+					.get_row_offset(self.get_top_row())
+					.unwrap_or(data_source.start),
+			};
+			(
+				reader.get_system(),
+				reader.get_path().to_owned(),
+				encoding.encode(&query),
+				alignment,
+				data_source.start,
+				offset,
+			)
+		};
+		if pattern.is_empty() {
+			return;
+		}
+		if let Some(id) = self.search_job.take() {
+			self.jobs.cancel(id);
+		}
+		let generation = self.generation;
+		self.search_job = Some(
+			self.jobs
+				.spawn(&format!("Find in {}", path), move |context| {
+					let found_offset =
+						find_text(context, &system, &path, &pattern, alignment, start, offset);
+					context.send(JobMessage::ViewerFound {
+						generation,
+						offset: found_offset,
+					});
+				}),
+		);
+		self.show_status(Some(&format!("Searching {}...", query)));
+	}
+
+	/// Shows the match found on a worker thread.
+	pub fn set_found(&mut self, generation: u64, offset: Option<u64>) {
+		if generation != self.generation {
+			return;
+		}
+		self.search_job = None;
+		let offset = match offset {
+			Some(offset) => offset,
+			None => {
+				self.show_status(Some("Not found"));
+				return;
+			}
+		};
+		self.found_offset = Some(offset);
+		let row_index = self.data_source.borrow().get_offset_row(offset);
+		self.data_source.borrow_mut().active_index = row_index;
+		let status = format!("Found at offset {}", offset.separated_string());
+		self.show_status(Some(&status));
+		self.refresh(row_index);
+	}
+
+	pub fn scroll(&mut self, rows: i64) {
+		let top_row = self.get_top_row() as i64 + rows;
+		self.scroll_to(top_row.max(0) as u64);
+	}
+
+	pub fn scroll_pages(&mut self, pages: i64) {
+		let page_size = self
+			.vtable
+			.call_method("getPageSize", &sciter::make_args!())
+			.ok()
+			.and_then(|page_size| page_size.to_int())
+			.unwrap_or(1)
+			.max(1);
+		self.scroll(pages * i64::from(page_size));
+	}
+
+	pub fn scroll_home(&mut self) {
+		self.scroll_to(0);
+	}
+
+	pub fn scroll_end(&mut self) {
+		let row_count = self.data_source.borrow().get_row_count();
+		self.scroll_to(row_count);
+	}
+
+	fn scroll_to(&mut self, row_index: u64) {
+		let row_count = self.data_source.borrow().get_row_count();
+		let row_index = row_index
+			.min(row_count.saturating_sub(1))
+			.min(i32::MAX as u64);
+		self.vtable
+			.call_method("scrollToRow", &sciter::make_args!(row_index as i32))
+			.ok();
+	}
+
+	fn get_top_row(&self) -> u64 {
+		self.vtable
+			.call_method("getTopRow", &sciter::make_args!())
+			.ok()
+			.and_then(|row| row.to_int())
+			.unwrap_or(0)
+			.max(0) as u64
+	}
+
+	/// Shows the rows again, scrolling to the row, if it is given.
+	fn refresh(&mut self, row_index: Option<u64>) {
+		self.vtable
+			.send_event(
+				BEHAVIOR_EVENTS::CHANGE,
+				Some(CLICK_REASON::SYNTHESIZED),
+				None,
+			)
+			.unwrap();
+		if let Some(row_index) = row_index {
+			self.vtable
+				.call_method("onChange", &sciter::make_args!(row_index as i32))
+				.unwrap();
+		}
+	}
+
+	/// Shows the size, mode and encoding with the message.
+	fn show_status(&mut self, message: Option<&str>) {
+		let status = {
+			let data_source = self.data_source.borrow();
+			let size = data_source
+				.reader
+				.as_ref()
+				.map_or(0, |reader| reader.get_size());
+			let mut status = format!("{} bytes", size.separated_string());
+			match data_source.mode {
+				ViewMode::Text => {
+					let index = &data_source.index;
+					write!(
+						&mut status,
+						" | Text | {} | {} rows{}",
+						data_source.layout.encoding.get_name(),
+						index.row_count.separated_string(),
+						if index.finished { "" } else { "..." }
+					)
+					.unwrap();
+					if self.wrap {
+						status.push_str(" | Wrap");
+					}
+				}
+				ViewMode::Hex => status.push_str(" | Hex"),
+				ViewMode::Image => status.push_str(" | Image"),
+			}
+			if let Some(message) = message {
+				write!(&mut status, " | {}", message).unwrap();
+			}
+			status
+		};
+		self.status.set_text(&status).unwrap();
+	}
+}
+
+/// Returns the size and the first page of the file, or None for a directory.
+fn open_file(system: Arc<Mutex<Box<dyn System>>>, path: &str) -> Result<Option<OpenedFile>, Error> {
+	let metadata = lock_system(&system).get_metadata(path)?;
+	if metadata.is_dir {
+		return Ok(None);
+	}
+	let reader = lock_system(&system).open_read(path, 0)?;
+	let mut first_page = Vec::with_capacity(PAGE_SIZE as usize);
+	reader.take(PAGE_SIZE).read_to_end(&mut first_page)?;
+	Ok(Some(OpenedFile {
+		system,
+		size: metadata.size,
+		first_page,
+	}))
+}
+
+/// Reads the whole image and returns it as a data URL.
+fn read_image(system: &Mutex<Box<dyn System>>, path: &str) -> Result<String, Error> {
+	let image_type = get_image_type(path).unwrap_or("image/png");
+	let reader = lock_system(system).open_read(path, 0)?;
+	let mut data = Vec::new();
+	reader.take(IMAGE_SIZE_LIMIT).read_to_end(&mut data)?;
+	Ok(format!(
+		"data:{};base64,{}",
+		image_type,
+		encode_base64(&data)
+	))
+}
+
+/// Counts the rows of the file and sends the offsets of every `INDEX_STEP`th
+/// row, while it proceeds.
+fn index_rows(
+	context: &JobContext<JobMessage>,
+	system: &Mutex<Box<dyn System>>,
+	path: &str,
+	start: u64,
+	layout: TextLayout,
+	generation: u64,
+) {
+	let send = |checkpoints: Vec<u64>, row_count: u64, finished: bool| {
+		context.send(JobMessage::ViewerIndex {
+			generation,
+			checkpoints,
+			row_count,
+			finished,
+		})
+	};
+	let mut reader = match lock_system(system).open_read(path, start) {
+		Ok(reader) => reader,
+		Err(e) => {
+			log::error!("Failed to read {}: {}", path, e);
+			send(Vec::new(), 0, true);
+			return;
+		}
+	};
+	let mut buffer: Vec<u8> = Vec::new();
+	let mut chunk = vec![0; READ_SIZE];
+	let mut offset = start;
+	let mut row_count: u64 = 0;
+	let mut checkpoints = Vec::new();
+	let mut last_sent = Instant::now();
+	let mut at_end = false;
+	while !at_end {
+		if context.is_cancelled() {
+			return;
+		}
+		let length = reader.read(&mut chunk).unwrap_or_else(|e| {
+			log::error!("Failed to read {}: {}", path, e);
+			0
+		});
+		at_end = length == 0;
+		buffer.extend_from_slice(&chunk[..length]);
+		let mut position = 0;
+		while let Some(row_length) = layout.get_row_length(&buffer[position..], at_end) {
+			if row_count % INDEX_STEP == 0 {
+				checkpoints.push(offset + position as u64);
+			}
+			row_count += 1;
+			position += row_length;
+		}
+		buffer.drain(..position);
+		offset += position as u64;
+		if at_end || last_sent.elapsed() >= INDEX_PROGRESS_INTERVAL {
+			send(std::mem::take(&mut checkpoints), row_count, at_end);
+			last_sent = Instant::now();
+		}
+	}
+}
+
+/// Returns the offset of the first match at or after `offset`, whose distance
+/// from `start` is a multiple of `alignment`.
+fn find_text(
+	context: &JobContext<JobMessage>,
+	system: &Mutex<Box<dyn System>>,
+	path: &str,
+	pattern: &[u8],
+	alignment: usize,
+	start: u64,
+	offset: u64,
+) -> Option<u64> {
+	let reader_offset = get_aligned_offset(alignment, start, offset);
+	let reader = lock_system(system).open_read(path, reader_offset);
+	let result = reader.and_then(|mut reader| {
+		let is_cancelled = || context.is_cancelled();
+		find_in_reader(
+			&mut reader,
+			pattern,
+			alignment,
+			reader_offset,
+			&is_cancelled,
+		)
+		.map_err(Error::from)
+	});
+	match result {
+		Ok(found_offset) => found_offset,
+		Err(e) => {
+			log::error!("Failed to read {}: {}", path, e);
+			None
+		}
+	}
+}
+
+/// Returns the first offset at or after `offset`, whose distance from `start`
+/// is a multiple of `alignment`.
+fn get_aligned_offset(alignment: usize, start: u64, offset: u64) -> u64 {
+	let misalignment = offset.saturating_sub(start) % alignment as u64;
+	offset + (alignment as u64 - misalignment) % alignment as u64
+}
+
+/// Returns the offset of the first match, which starts at a multiple of
+/// `alignment` in the data read. `reader_offset` is the offset of the reader
+/// in the file. Nothing is found, once `is_cancelled` returns true.
+fn find_in_reader(
+	reader: &mut dyn Read,
+	pattern: &[u8],
+	alignment: usize,
+	reader_offset: u64,
+	is_cancelled: &dyn Fn() -> bool,
+) -> io::Result<Option<u64>> {
+	let mut buffer_offset = reader_offset;
+	let mut buffer: Vec<u8> = Vec::new();
+	let mut chunk = vec![0; READ_SIZE];
+	loop {
+		if is_cancelled() {
+			return Ok(None);
+		}
+		let length = reader.read(&mut chunk)?;
+		buffer.extend_from_slice(&chunk[..length]);
+		if let Some(index) = find_ignore_case(&buffer, pattern, alignment) {
+			return Ok(Some(buffer_offset + index as u64));
+		}
+		if length == 0 {
+			return Ok(None);
+		}
+		// Keep the end, which may hold the start of a match.
+		let keep = pattern.len().saturating_sub(1);
+		let drained = buffer.len().saturating_sub(keep) / alignment * alignment;
+		buffer.drain(..drained);
+		buffer_offset += drained as u64;
+	}
+}
+
+fn get_image_type(path: &str) -> Option<&'static str> {
+	let extension = get_path_filename(path).rsplit('.').next()?.to_lowercase();
+	IMAGE_TYPES
+		.iter()
+		.find(|(image_extension, _image_type)| *image_extension == extension)
+		.map(|(_image_extension, image_type)| *image_type)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut text = String::with_capacity((data.len() + 2) / 3 * 4);
+	for chunk in data.chunks(3) {
+		let bytes = [
+			chunk[0],
+			chunk.get(1).copied().unwrap_or(0),
+			chunk.get(2).copied().unwrap_or(0),
+		];
+		let bits = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+		for index in 0..4 {
+			if index <= chunk.len() {
+				text.push(char::from(
+					ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3f],
+				));
+			} else {
+				text.push('=');
+			}
+		}
+	}
+	text
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn find(data: &[u8], pattern: &[u8], alignment: usize, start: u64, offset: u64) -> Option<u64> {
+		let reader_offset = get_aligned_offset(alignment, start, offset);
+		let mut reader = &data[reader_offset as usize..];
+		find_in_reader(&mut reader, pattern, alignment, reader_offset, &|| false).unwrap()
+	}
+
+	#[test]
+	fn matches_across_reads_are_found() {
+		let mut data = vec![b'x'; READ_SIZE * 2 + 10];
+		data[READ_SIZE - 3..READ_SIZE + 3].copy_from_slice(b"Needle");
+		data[READ_SIZE * 2 + 2..READ_SIZE * 2 + 8].copy_from_slice(b"NEEDLE");
+		let first = (READ_SIZE - 3) as u64;
+		let second = (READ_SIZE * 2 + 2) as u64;
+		assert_eq!(find(&data, b"needle", 1, 0, 0), Some(first));
+		assert_eq!(find(&data, b"needle", 1, 0, first + 1), Some(second));
+		assert_eq!(find(&data, b"needle", 1, 0, second + 1), None);
+		assert_eq!(find(&data, b"needles", 1, 0, 0), None);
+	}
+
+	#[test]
+	fn utf16_matches_are_aligned_to_code_units() {
+		let mut data = vec![0xff, 0xfe];
+		// "a" in UTF-16 LE also occurs between the first two code units.
+		data.extend(Encoding::Utf16Le.encode("\u{6100}\u{6200}a"));
+		let pattern = Encoding::Utf16Le.encode("A");
+		assert_eq!(find(&data, &pattern, 2, 2, 0), Some(6));
+		assert_eq!(find(&data, &pattern, 2, 2, 3), Some(6));
+		assert_eq!(find(&data, &pattern, 1, 2, 0), Some(3));
+	}
+
+	#[test]
+	fn cancelled_search_finds_nothing() {
+		let mut reader = &b"needle"[..];
+		let found = find_in_reader(&mut reader, b"needle", 1, 0, &|| true).unwrap();
+		assert_eq!(found, None);
+	}
+
+	#[test]
+	fn base64_is_padded() {
+		assert_eq!(encode_base64(b""), "");
+		assert_eq!(encode_base64(b"f"), "Zg==");
+		assert_eq!(encode_base64(b"fo"), "Zm8=");
+		assert_eq!(encode_base64(b"foo"), "Zm9v");
+		assert_eq!(encode_base64(b"foob"), "Zm9vYg==");
+		assert_eq!(encode_base64(&[0xfb, 0xff]), "+/8=");
+	}
+}
@@ -1,19 +1,58 @@
+use crate::config;
 use crate::data_source::DataSource;
 use crate::self_update::update_self;
-use crate::ui::{Palette, Pane};
+use crate::settings::{Bookmark, PaneSettings, Settings, TabSettings, WindowGeometry};
+use crate::ui::associations::{edit_remote_file, expand_command, launch, Action, Associations};
+use crate::ui::key_bindings::{KeyBinding, KeyBindings, KeyResult, ALT, CTRL, SHIFT};
+use crate::ui::multi_rename::{
+	apply_renames, Case, RenameBatch, RenameHistory, RenameOptions, Renamer,
+};
+use crate::ui::pane::{expand_home, get_path, get_path_directory, get_path_filename, lock_system};
+use crate::ui::quick_search::glob_to_regex;
+use crate::ui::{
+	get_app_data, Changes, JobsPanel, Listing, OpenedFile, Palette, PaletteItem, Pane, Template,
+	Themes, Viewer,
+};
+use chrono::{Local, NaiveDate, TimeZone};
+use regex::RegexBuilder;
 use sciter::dom::event::{EventReason, BEHAVIOR_EVENTS, EVENT_GROUPS, PHASE_MASK};
 use sciter::dom::{ELEMENT_STATE_BITS, HELEMENT};
 use sciter::{Element, EventHandler, Value};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::process::Command;
+use separator::Separatable;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use xcmd_core::api::System;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use xcmd_core::api::{Error, Metadata, System, WatchEvent, Watcher};
+use xcmd_core::archive;
+use xcmd_core::compare::{
+	compare_directories, get_sync_conflicts, get_sync_copies, join_relative_path, CompareMode,
+	Comparison, Difference, SyncDirection,
+};
+use xcmd_core::delete::delete_tree;
+use xcmd_core::jobs::{JobContext, JobEvent, JobQueue};
 use xcmd_core::local::LocalSystem;
-use xcmd_core::sftp::SftpSystem;
+use xcmd_core::search::{lock_results, search, SearchQuery, SearchResults, SearchResultsSystem};
+use xcmd_core::sftp::{
+	ConnectionProfile, HostKey, HostKeyDecision, HostKeyStatus, KnownHostsFiles, SftpSystem,
+};
+use xcmd_core::transfer::{
+	Conflict, ConflictResolution, Progress, Transfer, TransferMode, TransferState,
+};
+use xcmd_core::trash::{self, TrashSystem, TRASH_PATH};
+use xcmd_core::usage::{get_children_usage, get_usage, ChildUsage, Usage};
 
 type Callback = Box<dyn (Fn(&mut WindowState, &Element) -> ()) + 'static>;
 
+struct Command {
+	title: String,
+	callback: Callback,
+}
+
 pub fn mk_callback<F>(f: F) -> Callback
 where
 	F: (Fn(&mut WindowState, &Element) -> ()) + 'static,
@@ -21,11 +60,109 @@ where
 	Box::new(f) as Callback
 }
 
-#[derive(serde_derive::Deserialize)]
-struct KeyBinding {
-	key: String,
-	command: String,
-	when: String,
+/// Sent from jobs on worker threads to the UI thread.
+pub enum JobMessage {
+	Listing {
+		pane: u8,
+		generation: u64,
+		listing: Result<Listing, Error>,
+	},
+	/// Watches the directory of the pane, unless it cannot be watched.
+	Watching {
+		pane: u8,
+		generation: u64,
+		watcher: Result<Option<Watcher>, Error>,
+		receiver: Receiver<WatchEvent>,
+	},
+	/// Files of the directory of the pane, changed by other programs.
+	Changes {
+		pane: u8,
+		generation: u64,
+		changes: Option<Changes>,
+	},
+	/// A path typed in the path bar, checked to be a directory.
+	PathChecked {
+		pane: u8,
+		generation: u64,
+		path: String,
+		result: Result<String, Error>,
+	},
+	/// The directories matching the name typed in the path bar.
+	PathCompletion {
+		pane: u8,
+		generation: u64,
+		text: String,
+		names: Result<Vec<String>, Error>,
+	},
+	TransferProgress(Progress),
+	TransferConflict(Conflict, Sender<(ConflictResolution, bool)>),
+	TransferFinished(Vec<String>),
+	/// A file opened for the viewer, None for a directory.
+	ViewerOpened {
+		generation: u64,
+		path: String,
+		file: Result<Option<OpenedFile>, Error>,
+	},
+	/// Consecutive pages of the viewed file, starting at `first_page`.
+	ViewerPages {
+		generation: u64,
+		first_page: u64,
+		pages: Vec<Vec<u8>>,
+	},
+	ViewerImage {
+		generation: u64,
+		data_url: Result<String, Error>,
+	},
+	ViewerIndex {
+		generation: u64,
+		checkpoints: Vec<u64>,
+		row_count: u64,
+		finished: bool,
+	},
+	ViewerFound {
+		generation: u64,
+		offset: Option<u64>,
+	},
+	EditFailed(String),
+	SearchFailed(String),
+	DirectorySizes {
+		pane: u8,
+		generation: u64,
+		sizes: Vec<(String, u64)>,
+	},
+	DiskUsage {
+		pane: u8,
+		generation: u64,
+		directory: String,
+		children: Result<Vec<ChildUsage>, Error>,
+	},
+	/// The selected files, added up to confirm deleting them permanently.
+	DeletePrepared {
+		pane: u8,
+		children: Result<Vec<ChildUsage>, Error>,
+	},
+	/// The selected files, read to rename them after a template.
+	RenameLoaded {
+		pane: u8,
+		system: Arc<Mutex<Box<dyn System>>>,
+		batch: Result<RenameBatch, Error>,
+	},
+	Renamed {
+		pane: u8,
+		history: RenameHistory,
+		result: Result<(), Error>,
+	},
+	RenameUndone {
+		history: RenameHistory,
+		result: Result<(), Error>,
+	},
+	Compared {
+		left_dir: String,
+		right_dir: String,
+		/// Whether to synchronize the directories, or else to mark the files.
+		sync: bool,
+		comparisons: Result<Vec<Comparison>, Error>,
+	},
 }
 
 pub struct WindowState {
@@ -33,7 +170,18 @@ pub struct WindowState {
 	left_pane: Option<Pane>,
 	right_pane: Option<Pane>,
 	palette: Option<Palette>,
+	jobs_panel: Option<JobsPanel>,
+	viewer: Option<Viewer>,
+	jobs: JobQueue<JobMessage>,
 	data_sources: HashMap<String, Rc<RefCell<dyn DataSource>>>,
+	/// Run after the current command, e.g. the one chosen in the palette.
+	next_command: Option<String>,
+	themes: Rc<RefCell<Themes>>,
+	bookmarks: Vec<Bookmark>,
+	/// The last multi-rename, until it is undone.
+	rename_history: Option<RenameHistory>,
+	/// File names selected by "Save Selection".
+	saved_selection: Vec<String>,
 }
 
 impl WindowState {
@@ -50,6 +198,38 @@ impl WindowState {
 		self.get_pane(active_pane)
 	}
 
+	/// Tells, whether a context key used in `when` clauses of key bindings is
+	/// set.
+	fn is_context(&self, name: &str) -> bool {
+		let is_palette_active = self.palette.as_ref().map_or(false, |x| x.is_active());
+		let active_pane = if self.active_pane == 0 {
+			&self.left_pane
+		} else {
+			&self.right_pane
+		};
+		let is_editing_path = active_pane.as_ref().map_or(false, |x| x.is_editing_path());
+		let (is_viewer_active, is_viewer_searching) = self
+			.viewer
+			.as_ref()
+			.map_or((false, false), |x| (x.is_active(), x.is_searching()));
+		let is_pane_focus = !is_palette_active && !is_dialog_active() && !is_viewer_active;
+		match name {
+			"paneFocus" => is_pane_focus && !is_editing_path,
+			"pathBarFocus" => is_pane_focus && is_editing_path,
+			"viewerFocus" => is_viewer_active && !is_dialog_active() && !is_viewer_searching,
+			"viewerSearchFocus" => is_viewer_active && !is_dialog_active() && is_viewer_searching,
+			"paletteFocus" => is_palette_active,
+			"dialogFocus" => is_dialog_active(),
+			"quickSearchActive" => active_pane
+				.as_ref()
+				.map_or(false, |x| x.is_quick_search_active()),
+			"leftPaneFocus" => self.active_pane == 0,
+			"rightPaneFocus" => self.active_pane == 1,
+			"jobsRunning" => self.jobs.is_busy(),
+			_ => false,
+		}
+	}
+
 	fn set_active_pane(&mut self, active_pane: u8) {
 		let old_active_pane = self.active_pane;
 		if let Some(ref mut old_pane) = &mut self.get_pane(old_active_pane) {
@@ -65,31 +245,52 @@ impl WindowState {
 
 pub struct WindowEventHandler {
 	root: Option<Element>,
-	commands: HashMap<String, Callback>,
+	commands: HashMap<String, Command>,
 	key_map: HashMap<i32, i32>,
 	key_names: HashMap<String, i32>,
-	key_handlers: HashMap<i32, String>,
+	key_bindings: KeyBindings,
+	settings: Settings,
 	state: WindowState,
 }
 
-const ALT: i32 = 0x1000_0000;
-const CTRL: i32 = 0x0100_0000;
-const SHIFT: i32 = 0x0010_0000;
+/// `Event.KEY_CHAR` in TIScript.
+const KEY_CHAR: i32 = 2;
+
+const JOBS_TIMER_ID: u64 = 1;
+const JOBS_TIMER_INTERVAL: u32 = 20;
+
+/// Polls the panes for changes of their directories.
+const WATCH_TIMER_ID: u64 = 2;
+const WATCH_TIMER_INTERVAL: u32 = 250;
+
+/// How often jobs, which report progress in small steps, send it.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
 
 impl WindowEventHandler {
-	pub fn new() -> Self {
+	pub fn new(themes: Rc<RefCell<Themes>>, mut settings: Settings) -> Self {
+		let active_pane = settings.active_pane.min(1);
+		let bookmarks = std::mem::replace(&mut settings.bookmarks, Vec::new());
 		WindowEventHandler {
 			root: None,
+			settings,
 			commands: HashMap::new(),
-			key_handlers: HashMap::new(),
+			key_bindings: KeyBindings::default(),
 			key_map: HashMap::new(),   // code -> index
 			key_names: HashMap::new(), // name -> index
 			state: WindowState {
-				active_pane: 0,
+				active_pane,
 				left_pane: None,
 				right_pane: None,
 				palette: None,
+				jobs_panel: None,
+				viewer: None,
+				jobs: JobQueue::new(),
 				data_sources: HashMap::new(),
+				next_command: None,
+				themes,
+				bookmarks,
+				rename_history: None,
+				saved_selection: Vec::new(),
 			},
 		}
 	}
@@ -132,9 +333,31 @@ impl WindowEventHandler {
 		}
 	}
 
-	pub fn register_command(&mut self, command_name: &str, command_handler: Callback) {
-		self.commands
-			.insert(command_name.to_owned(), command_handler);
+	pub fn register_command(&mut self, command_name: &str, title: &str, callback: Callback) {
+		self.commands.insert(
+			command_name.to_owned(),
+			Command {
+				title: title.to_owned(),
+				callback,
+			},
+		);
+	}
+
+	fn run_command(&mut self, command_name: &str) {
+		let mut command_name = Some(command_name.to_owned());
+		while let Some(name) = command_name {
+			if let (Some(command), Some(root)) = (self.commands.get(&name), &self.root) {
+				// Commands may show dialogs, which must not be interrupted by the timers.
+				root.stop_timer(JOBS_TIMER_ID).unwrap();
+				root.stop_timer(WATCH_TIMER_ID).unwrap();
+				(command.callback)(&mut self.state, root);
+			} else {
+				log::warn!("Unknown command: {}", name);
+			}
+			command_name = self.state.next_command.take();
+		}
+		self.start_jobs_timer();
+		self.start_watch_timer();
 	}
 
 	fn on_document_ready(&mut self, root: HELEMENT) {
@@ -147,7 +370,7 @@ impl WindowEventHandler {
 			left_data_source as Rc<RefCell<dyn DataSource>>,
 		);
 
-		let right_pane = self.create_pane(&mut find_first(&root, "#right-pane"), 0);
+		let right_pane = self.create_pane(&mut find_first(&root, "#right-pane"), 1);
 		let right_data_source = Rc::clone(&right_pane.data_source);
 		self.state.data_sources.insert(
 			"right-pane".to_owned(),
@@ -157,129 +380,665 @@ impl WindowEventHandler {
 		self.state.left_pane = Some(left_pane);
 		self.state.right_pane = Some(right_pane);
 		self.state.palette = Some(Palette::new(&mut find_first(&root, "#palette")));
+		self.state.jobs_panel = Some(JobsPanel::new(&mut find_first(&root, "#jobs")));
+		let viewer = Viewer::new(
+			&mut find_first(&root, "#viewer"),
+			self.state.jobs.get_spawner(),
+		);
+		self.state.data_sources.insert(
+			"viewer".to_owned(),
+			Rc::clone(&viewer.data_source) as Rc<RefCell<dyn DataSource>>,
+		);
+		self.state.viewer = Some(viewer);
 		self.root = Some(root);
 
 		self.register_command(
 			"pane.switchPane",
+			"Switch Pane",
 			mk_callback(|state: &mut WindowState, _root: &Element| switch_pane(state)),
 		);
 		self.register_command(
 			"pane.moveUp",
+			"Move Up",
 			mk_callback(|state: &mut WindowState, _root: &Element| move_up(state)),
 		);
 		self.register_command(
 			"pane.moveDown",
+			"Move Down",
 			mk_callback(|state: &mut WindowState, _root: &Element| move_down(state)),
 		);
 		self.register_command(
 			"pane.moveHome",
+			"Move Home",
 			mk_callback(|state: &mut WindowState, _root: &Element| move_home(state)),
 		);
 		self.register_command(
 			"pane.moveEnd",
+			"Move End",
 			mk_callback(|state: &mut WindowState, _root: &Element| move_end(state)),
 		);
 		self.register_command(
 			"pane.pageUp",
+			"Move Page Up",
 			mk_callback(|state: &mut WindowState, _root: &Element| page_up(state)),
 		);
 		self.register_command(
 			"pane.pageDown",
+			"Move Page Down",
 			mk_callback(|state: &mut WindowState, _root: &Element| page_down(state)),
 		);
 		self.register_command(
 			"pane.selectUp",
+			"Select Up",
 			mk_callback(|state: &mut WindowState, _root: &Element| select_up(state)),
 		);
 		self.register_command(
 			"pane.selectDown",
+			"Select Down",
 			mk_callback(|state: &mut WindowState, _root: &Element| select_down(state)),
 		);
 		self.register_command(
 			"pane.toggleSelect",
-			mk_callback(|state: &mut WindowState, _root: &Element| toggle_select(state)),
+			"Toggle Selection",
+			mk_callback(|state: &mut WindowState, root: &Element| toggle_select(state, root)),
+		);
+		self.register_command(
+			"pane.showDiskUsage",
+			"Show Disk Usage",
+			mk_callback(|state: &mut WindowState, root: &Element| show_disk_usage(state, root)),
+		);
+		self.register_command(
+			"pane.selectPattern",
+			"Select Files by Pattern",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				select_pattern(state, root, true)
+			}),
+		);
+		self.register_command(
+			"pane.deselectPattern",
+			"Deselect Files by Pattern",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				select_pattern(state, root, false)
+			}),
+		);
+		self.register_command(
+			"pane.selectAll",
+			"Select All",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.select_where(true, |_file| true))
+			}),
+		);
+		self.register_command(
+			"pane.deselectAll",
+			"Deselect All",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.select_where(false, |_file| true))
+			}),
+		);
+		self.register_command(
+			"pane.invertSelection",
+			"Invert Selection",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, Pane::invert_selection)
+			}),
+		);
+		self.register_command(
+			"pane.selectSameExtension",
+			"Select Files with Same Extension",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, Pane::select_same_extension)
+			}),
+		);
+		self.register_command(
+			"pane.saveSelection",
+			"Save Selection",
+			mk_callback(|state: &mut WindowState, _root: &Element| save_selection(state)),
+		);
+		self.register_command(
+			"pane.restoreSelection",
+			"Restore Selection",
+			mk_callback(|state: &mut WindowState, _root: &Element| restore_selection(state)),
+		);
+		self.register_command(
+			"pane.calculateSelectionSize",
+			"Calculate Size of Selected Directories",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				calculate_selection_size(state, root)
+			}),
 		);
 		self.register_command(
 			"pane.enterItem",
-			mk_callback(|state: &mut WindowState, _root: &Element| enter_item(state)),
+			"Enter Item",
+			mk_callback(|state: &mut WindowState, root: &Element| enter_item(state, root)),
 		);
 		self.register_command(
 			"pane.exit",
+			"Exit",
 			mk_callback(|state: &mut WindowState, root: &Element| exit(state, root)),
 		);
 		self.register_command(
 			"pane.updateSelf",
+			"Update Application",
 			mk_callback(|state: &mut WindowState, root: &Element| update_self(state, root)),
 		);
 		self.register_command(
 			"pane.viewFile",
+			"View File",
 			mk_callback(|state: &mut WindowState, _root: &Element| view_file(state)),
 		);
 		self.register_command(
 			"pane.editFile",
-			mk_callback(|state: &mut WindowState, _root: &Element| edit_file(state)),
+			"Edit File",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				launch_file(state, root, Action::Edit)
+			}),
 		);
 		self.register_command(
 			"palette.show",
+			"Show Palette",
 			mk_callback(|state: &mut WindowState, _root: &Element| show_palette(state)),
 		);
 		self.register_command(
 			"palette.hide",
+			"Hide Palette",
 			mk_callback(|state: &mut WindowState, _root: &Element| hide_palette(state)),
 		);
+		self.register_command(
+			"palette.accept",
+			"Run Selected Command",
+			mk_callback(|state: &mut WindowState, _root: &Element| accept_palette(state)),
+		);
+		self.register_command(
+			"palette.deleteChar",
+			"Delete Palette Query Character",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::pop_query)
+			}),
+		);
+		self.register_command(
+			"palette.moveUp",
+			"Move Up in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::move_up)
+			}),
+		);
+		self.register_command(
+			"palette.moveDown",
+			"Move Down in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::move_down)
+			}),
+		);
+		self.register_command(
+			"palette.moveHome",
+			"Move Home in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::move_home)
+			}),
+		);
+		self.register_command(
+			"palette.moveEnd",
+			"Move End in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::move_end)
+			}),
+		);
+		self.register_command(
+			"palette.pageUp",
+			"Move Page Up in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::page_up)
+			}),
+		);
+		self.register_command(
+			"palette.pageDown",
+			"Move Page Down in Palette",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_palette(state, Palette::page_down)
+			}),
+		);
 		self.register_command(
 			"pane.copyFile",
-			mk_callback(|state: &mut WindowState, root: &Element| copy_file(state, root)),
+			"Copy File",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				transfer_files(state, root, TransferMode::Copy)
+			}),
+		);
+		self.register_command(
+			"pane.moveFile",
+			"Move File",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				transfer_files(state, root, TransferMode::Move)
+			}),
+		);
+		self.register_command(
+			"pane.packFiles",
+			"Pack Files",
+			mk_callback(|state: &mut WindowState, root: &Element| pack_files(state, root)),
+		);
+		self.register_command(
+			"pane.compareDirectories",
+			"Compare Directories",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				compare_panes(state, root, CompareMode::SizeAndTime, false)
+			}),
+		);
+		self.register_command(
+			"pane.compareContents",
+			"Compare Directories by Content",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				compare_panes(state, root, CompareMode::Content, false)
+			}),
+		);
+		self.register_command(
+			"pane.synchronizeDirectories",
+			"Synchronize Directories",
+			mk_callback(|state: &mut WindowState, root: &Element| {
+				compare_panes(state, root, CompareMode::SizeAndTime, true)
+			}),
+		);
+		self.register_command(
+			"pane.findFiles",
+			"Find Files",
+			mk_callback(|state: &mut WindowState, root: &Element| find_files(state, root)),
+		);
+		self.register_command(
+			"pane.createDirectory",
+			"Create Directory",
+			mk_callback(|state: &mut WindowState, root: &Element| create_directory(state, root)),
+		);
+		self.register_command(
+			"pane.delete",
+			"Delete",
+			mk_callback(|state: &mut WindowState, root: &Element| delete_files(state, root)),
+		);
+		self.register_command(
+			"pane.deletePermanently",
+			"Delete Permanently",
+			mk_callback(|state: &mut WindowState, root: &Element| delete_permanently(state, root)),
+		);
+		self.register_command(
+			"pane.showTrash",
+			"Show Trash",
+			mk_callback(|state: &mut WindowState, root: &Element| show_trash(state, root)),
+		);
+		self.register_command(
+			"pane.restoreFromTrash",
+			"Restore from Trash",
+			mk_callback(|state: &mut WindowState, root: &Element| restore_from_trash(state, root)),
+		);
+		self.register_command(
+			"pane.renameItem",
+			"Rename Item",
+			mk_callback(|state: &mut WindowState, root: &Element| rename_item(state, root)),
+		);
+		self.register_command(
+			"pane.multiRename",
+			"Rename Multiple Files",
+			mk_callback(|state: &mut WindowState, _root: &Element| multi_rename(state)),
+		);
+		self.register_command(
+			"pane.undoMultiRename",
+			"Undo Multiple Rename",
+			mk_callback(|state: &mut WindowState, root: &Element| undo_multi_rename(state, root)),
+		);
+		self.register_command(
+			"pane.quickSearch",
+			"Quick Search",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				start_quick_search(state, false)
+			}),
+		);
+		self.register_command(
+			"pane.quickFilter",
+			"Quick Filter",
+			mk_callback(|state: &mut WindowState, _root: &Element| start_quick_search(state, true)),
+		);
+		self.register_command(
+			"quickSearch.close",
+			"Close Quick Search",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.end_quick_search(true))
+			}),
+		);
+		self.register_command(
+			"quickSearch.deleteChar",
+			"Delete Quick Search Character",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, Pane::pop_quick_search)
+			}),
+		);
+		self.register_command(
+			"quickSearch.previous",
+			"Find Previous Match",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.find_next_quick_search(true))
+			}),
+		);
+		self.register_command(
+			"quickSearch.next",
+			"Find Next Match",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.find_next_quick_search(false))
+			}),
+		);
+		self.register_command(
+			"pane.sortByName",
+			"Sort by Name",
+			mk_callback(|state: &mut WindowState, _root: &Element| sort_by(state, "path")),
+		);
+		self.register_command(
+			"pane.sortByExtension",
+			"Sort by Extension",
+			mk_callback(|state: &mut WindowState, _root: &Element| sort_by(state, "extension")),
+		);
+		self.register_command(
+			"pane.sortBySize",
+			"Sort by Size",
+			mk_callback(|state: &mut WindowState, _root: &Element| sort_by(state, "size")),
+		);
+		self.register_command(
+			"pane.sortByDate",
+			"Sort by Date",
+			mk_callback(|state: &mut WindowState, _root: &Element| sort_by(state, "modified_on")),
+		);
+		self.register_command(
+			"pane.toggleDirectoriesFirst",
+			"Toggle Directories First",
+			mk_callback(|state: &mut WindowState, _root: &Element| toggle_directories_first(state)),
+		);
+		self.register_command(
+			"pane.goBack",
+			"Go Back",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, Pane::go_back)
+			}),
+		);
+		self.register_command(
+			"pane.goForward",
+			"Go Forward",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, Pane::go_forward)
+			}),
+		);
+		self.register_command(
+			"pane.showHistory",
+			"Show Recent Directories",
+			mk_callback(|state: &mut WindowState, root: &Element| show_history(state, root)),
+		);
+		self.register_command(
+			"bookmarks.goTo",
+			"Go to Bookmark",
+			mk_callback(|state: &mut WindowState, root: &Element| go_to_bookmark(state, root)),
+		);
+		self.register_command(
+			"bookmarks.add",
+			"Add Bookmark",
+			mk_callback(|state: &mut WindowState, root: &Element| add_bookmark(state, root)),
+		);
+		self.register_command(
+			"bookmarks.remove",
+			"Remove Bookmark",
+			mk_callback(|state: &mut WindowState, root: &Element| remove_bookmark(state, root)),
+		);
+		self.register_command(
+			"pane.newTab",
+			"New Tab",
+			mk_callback(|state: &mut WindowState, root: &Element| new_tab(state, root)),
+		);
+		self.register_command(
+			"pane.duplicateTab",
+			"Duplicate Tab",
+			mk_callback(|state: &mut WindowState, root: &Element| duplicate_tab(state, root)),
+		);
+		self.register_command(
+			"pane.closeTab",
+			"Close Tab",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| {
+					pane.close_tab();
+				})
+			}),
+		);
+		self.register_command(
+			"pane.nextTab",
+			"Next Tab",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.switch_tab(1))
+			}),
+		);
+		self.register_command(
+			"pane.previousTab",
+			"Previous Tab",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_active_pane(state, |pane| pane.switch_tab(-1))
+			}),
+		);
+		self.register_command(
+			"pane.moveTabToOtherPane",
+			"Move Tab to Other Pane",
+			mk_callback(|state: &mut WindowState, _root: &Element| move_tab_to_other_pane(state)),
+		);
+		self.register_command(
+			"view.selectTheme",
+			"Select Color Theme",
+			mk_callback(|state: &mut WindowState, root: &Element| select_theme(state, root)),
+		);
+		self.register_command(
+			"pane.openConnection",
+			"Open Connection",
+			mk_callback(|state: &mut WindowState, root: &Element| open_connection(state, root)),
+		);
+		self.register_command(
+			"pane.editPath",
+			"Edit Path",
+			mk_callback(|state: &mut WindowState, _root: &Element| edit_path(state)),
+		);
+		self.register_command(
+			"pathBar.navigate",
+			"Go to Typed Path",
+			mk_callback(|state: &mut WindowState, root: &Element| navigate_path_bar(state, root)),
+		);
+		self.register_command(
+			"pathBar.complete",
+			"Complete Typed Path",
+			mk_callback(|state: &mut WindowState, _root: &Element| complete_path(state)),
+		);
+		self.register_command(
+			"pathBar.cancel",
+			"Cancel Editing Path",
+			mk_callback(|state: &mut WindowState, root: &Element| cancel_path_edit(state, root)),
+		);
+		self.register_command(
+			"viewer.close",
+			"Close Viewer",
+			mk_callback(|state: &mut WindowState, root: &Element| close_viewer(state, root)),
+		);
+		self.register_command(
+			"viewer.lineUp",
+			"Scroll Up",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, |viewer| viewer.scroll(-1))
+			}),
+		);
+		self.register_command(
+			"viewer.lineDown",
+			"Scroll Down",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, |viewer| viewer.scroll(1))
+			}),
+		);
+		self.register_command(
+			"viewer.pageUp",
+			"Page Up",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, |viewer| viewer.scroll_pages(-1))
+			}),
+		);
+		self.register_command(
+			"viewer.pageDown",
+			"Page Down",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, |viewer| viewer.scroll_pages(1))
+			}),
+		);
+		self.register_command(
+			"viewer.home",
+			"Go to Start",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::scroll_home)
+			}),
+		);
+		self.register_command(
+			"viewer.end",
+			"Go to End",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::scroll_end)
+			}),
+		);
+		self.register_command(
+			"viewer.toggleWrap",
+			"Toggle Line Wrapping",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::toggle_wrap)
+			}),
+		);
+		self.register_command(
+			"viewer.toggleHex",
+			"Toggle Hex Mode",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::toggle_hex)
+			}),
+		);
+		self.register_command(
+			"viewer.nextEncoding",
+			"Next Encoding",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::next_encoding)
+			}),
+		);
+		self.register_command(
+			"viewer.find",
+			"Find",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::find)
+			}),
+		);
+		self.register_command(
+			"viewer.findNext",
+			"Find Next",
+			mk_callback(|state: &mut WindowState, _root: &Element| {
+				with_viewer(state, Viewer::find_next)
+			}),
+		);
+		self.register_command(
+			"viewer.cancelFind",
+			"Cancel Find",
+			mk_callback(|_state: &mut WindowState, root: &Element| focus_files(root)),
 		);
 
 		self.initialize_key_map();
 
-		let json = include_str!("../../config/keybindings.json");
-		let key_bindings = serde_json::from_str::<Vec<KeyBinding>>(json).unwrap();
-		for key_binding in &key_bindings {
-			if let Some(modified_key_index) = self.parse_key(&key_binding.key) {
-				self.key_handlers
-					.insert(modified_key_index, key_binding.command.to_owned());
-			}
+		self.load_key_bindings();
+		let command_keys = self.key_bindings.get_command_keys();
+		let palette_items = self
+			.commands
+			.iter()
+			.filter(|(command_name, _command)| {
+				!command_name.starts_with("palette.")
+					&& !command_name.starts_with("quickSearch.")
+					&& !command_name.starts_with("pathBar.")
+					&& !command_name.starts_with("viewer.")
+			})
+			.map(|(command_name, command)| PaletteItem {
+				command: command_name.to_owned(),
+				title: command.title.to_owned(),
+				key: command_keys.get(command_name).cloned(),
+			})
+			.collect();
+		if let Some(palette) = &mut self.state.palette {
+			palette.set_items(palette_items);
 		}
 
-		if let Some(ref mut pane) = &mut self.state.left_pane {
-			pane.update(None);
+		self.restore_settings();
+		self.start_jobs_timer();
+		self.start_watch_timer();
+	}
+
+	/// Restores the window geometry and the panes saved on exit.
+	fn restore_settings(&mut self) {
+		let root = match &self.root {
+			Some(root) => root,
+			None => return,
+		};
+		if let Some(window) = self.settings.window {
+			root.call_function(
+				"setWindowGeometry",
+				&sciter::make_args!(window.x, window.y, window.width, window.height),
+			)
+			.unwrap_or_else(|_err| Value::new());
 		}
-		if let Some(ref mut pane) = &mut self.state.right_pane {
-			pane.activate(false);
-			pane.update(None);
+		for index in 0..2 {
+			let active = index == self.state.active_pane;
+			let pane_settings = self.settings.get_pane(index);
+			let tabs = pane_settings
+				.map(|pane_settings| restore_tabs(root, pane_settings))
+				.unwrap_or_default();
+			if let Some(pane) = self.state.get_pane(index) {
+				pane.activate(active);
+				match pane_settings {
+					Some(pane_settings) => pane.apply_settings(pane_settings, tabs),
+					None => pane.update(None),
+				}
+			}
 		}
 	}
 
-	fn parse_key(&mut self, key: &str) -> Option<i32> {
-		let mut key = key;
-		let mut modifier = 0;
-		loop {
-			let mut found_modifier = false;
-			if key.starts_with("alt+") {
-				key = &key["alt+".len()..];
-				modifier |= ALT;
-				found_modifier = true;
-			}
-			if key.starts_with("shift+") {
-				key = &key["shift+".len()..];
-				modifier |= SHIFT;
-				found_modifier = true;
-			}
-			if key.starts_with("ctrl+") {
-				key = &key["ctrl+".len()..];
-				modifier |= CTRL;
-				found_modifier = true;
-			}
-			if !found_modifier {
-				break;
-			}
+	/// Saves the window geometry and the panes to be restored on start.
+	fn save_settings(&mut self) {
+		let root = match &self.root {
+			Some(root) => root,
+			None => return,
+		};
+		let geometry = root
+			.call_function("getWindowGeometry", &sciter::make_args!())
+			.unwrap_or_else(|_err| Value::new());
+		if geometry.len() == 4 {
+			self.settings.window = Some(WindowGeometry {
+				x: geometry.get(0).to_int().unwrap_or(0),
+				y: geometry.get(1).to_int().unwrap_or(0),
+				width: geometry.get(2).to_int().unwrap_or(0),
+				height: geometry.get(3).to_int().unwrap_or(0),
+			});
 		}
-		if let Some(key_index) = self.key_names.get(key) {
-			Some(modifier | *key_index)
-		} else {
-			None
+		self.settings.theme = Some(
+			self.state
+				.themes
+				.borrow()
+				.get_current()
+				.get_name()
+				.to_owned(),
+		);
+		self.settings.active_pane = self.state.active_pane;
+		self.settings.bookmarks = self.state.bookmarks.clone();
+		self.settings.panes = [&self.state.left_pane, &self.state.right_pane]
+			.iter()
+			.filter_map(|pane| pane.as_ref().map(Pane::get_settings))
+			.collect();
+		self.settings.save();
+	}
+
+	/// Loads the default key bindings, followed by the user's bindings from the
+	/// configuration directory, which override or remove the defaults.
+	fn load_key_bindings(&mut self) {
+		let json = include_str!("../../config/keybindings.json");
+		let key_bindings = serde_json::from_str::<Vec<KeyBinding>>(json).unwrap();
+		self.key_bindings.add(&key_bindings, &self.key_names);
+		if let Some(json) = config::read_config_file("keybindings.json") {
+			match serde_json::from_str::<Vec<KeyBinding>>(&json) {
+				Ok(key_bindings) => self.key_bindings.add(&key_bindings, &self.key_names),
+				Err(e) => log::error!("Failed to parse keybindings.json: {}", e),
+			}
 		}
 	}
 
@@ -303,20 +1062,36 @@ impl WindowEventHandler {
 			ctrl_key,
 			shift_key
 		);
-		if event_type == BEHAVIOR_EVENTS::BUTTON_CLICK as i32 {
+		if event_type == KEY_CHAR {
+			self.on_key_char(key_code, alt_key || ctrl_key)
+		} else if event_type == BEHAVIOR_EVENTS::BUTTON_CLICK as i32 {
 			if let Some(key_index) = self.key_map.get(&key_code) {
 				let key = if alt_key { ALT } else { 0 }
 					| if ctrl_key { CTRL } else { 0 }
 					| if shift_key { SHIFT } else { 0 }
 					| key_index;
-				let key_command = if let Some(key_handler) = self.key_handlers.get(&key) {
-					self.commands.get(key_handler)
-				} else {
-					None
-				};
-				let mut state = &mut self.state;
-				if let (Some(command), Some(root)) = (key_command, &self.root) {
-					command(&mut state, &root);
+				if self.key_bindings.is_pending() && self.is_modifier_key(*key_index) {
+					return true;
+				}
+				let state = &self.state;
+				let result = self
+					.key_bindings
+					.resolve(key, &|name: &str| state.is_context(name));
+				match result {
+					KeyResult::Command(command_name) => {
+						if !command_name.starts_with("quickSearch.") {
+							if let Some(pane) = self.state.get_active_pane() {
+								if pane.is_quick_search_active() {
+									pane.end_quick_search(false);
+								}
+							}
+						}
+						self.run_command(&command_name);
+					}
+					// Editing keys of the path bar are left to the input.
+					KeyResult::None if self.state.is_context("pathBarFocus") => return false,
+					KeyResult::None if self.state.is_context("viewerSearchFocus") => return false,
+					_ => {}
 				}
 				true
 			} else {
@@ -327,6 +1102,128 @@ impl WindowEventHandler {
 		}
 	}
 
+	/// Typing a character starts the quick search in the active pane.
+	fn on_key_char(&mut self, char_code: i32, modified: bool) -> bool {
+		if self.state.is_context("pathBarFocus") || self.state.is_context("viewerSearchFocus") {
+			return false;
+		}
+		if self.state.is_context("viewerFocus") {
+			return true;
+		}
+		let c = match std::char::from_u32(char_code as u32) {
+			Some(c) if !c.is_control() && !modified => c,
+			_ => return false,
+		};
+		if let Some(palette) = &mut self.state.palette {
+			if palette.is_active() {
+				palette.push_query(c);
+				return true;
+			}
+		}
+		if let Some(pane) = self.state.get_active_pane() {
+			if !pane.is_quick_search_active() {
+				if c == ' ' {
+					return false;
+				}
+				pane.start_quick_search(false);
+			}
+			pane.push_quick_search(c);
+			true
+		} else {
+			false
+		}
+	}
+
+	fn is_modifier_key(&self, key_index: i32) -> bool {
+		["shift", "ctrl", "alt"]
+			.iter()
+			.any(|name| self.key_names.get(*name) == Some(&key_index))
+	}
+
+	fn run_palette_item(&mut self, index: i32) {
+		if let Some(palette) = &mut self.state.palette {
+			palette.set_active_item(index as u32);
+		}
+		self.run_command("palette.accept");
+	}
+
+	fn on_sort_column(&mut self, name: String, column_index: i32) {
+		let pane = match name.as_str() {
+			"left-pane" => &mut self.state.left_pane,
+			"right-pane" => &mut self.state.right_pane,
+			_ => return,
+		};
+		if let Some(pane) = pane {
+			pane.sort_by(column_index as usize);
+		}
+	}
+
+	fn on_select_tab(&mut self, name: String, index: i32) {
+		let pane_index = match name.as_str() {
+			"left-pane" => 0,
+			"right-pane" => 1,
+			_ => return,
+		};
+		self.state.set_active_pane(pane_index);
+		if let Some(pane) = self.state.get_pane(pane_index) {
+			pane.select_tab(index as usize);
+		}
+		self.start_jobs_timer();
+	}
+
+	fn on_path_bar_focus(&mut self, name: String, focus: bool) {
+		let pane_index = match name.as_str() {
+			"left-pane" => 0,
+			"right-pane" => 1,
+			_ => return,
+		};
+		if focus {
+			self.state.set_active_pane(pane_index);
+		}
+		if let Some(pane) = self.state.get_pane(pane_index) {
+			pane.set_editing_path(focus);
+		}
+	}
+
+	fn on_viewer_search_focus(&mut self, focus: bool) {
+		if let Some(viewer) = &mut self.state.viewer {
+			viewer.set_searching(focus);
+		}
+	}
+
+	fn cancel_job(&mut self, id: i32) {
+		self.state.jobs.cancel(id as u64);
+	}
+
+	fn start_jobs_timer(&self) {
+		if let Some(root) = &self.root {
+			if self.state.jobs.is_busy() {
+				root.start_timer(JOBS_TIMER_INTERVAL, JOBS_TIMER_ID)
+					.unwrap();
+			}
+		}
+	}
+
+	fn start_watch_timer(&self) {
+		if let Some(root) = &self.root {
+			root.start_timer(WATCH_TIMER_INTERVAL, WATCH_TIMER_ID)
+				.unwrap();
+		}
+	}
+
+	/// Applies changes of the directories shown in the panes.
+	fn poll_changes(&mut self) {
+		let mut started_job = false;
+		for index in 0..2 {
+			if let Some(pane) = self.state.get_pane(index) {
+				started_job |= pane.poll_changes();
+			}
+		}
+		if started_job {
+			self.start_jobs_timer();
+		}
+	}
+
 	fn on_resize_files(&mut self, files_height: i32, item_height: i32) {
 		if let Some(ref mut pane) = &mut self.state.left_pane {
 			pane.set_files_height(files_height, item_height);
@@ -353,22 +1250,27 @@ impl WindowEventHandler {
 	}
 
 	fn data_source_rows_data(&mut self, name: String, row_index: i32, row_count: i32) -> Value {
-		if let Some(data_source) = self.state.data_sources.get(&name) {
+		let rows = if let Some(data_source) = self.state.data_sources.get(&name) {
 			data_source
 				.borrow()
 				.data_source_rows_data(row_index, row_count)
 		} else {
 			Value::array(row_count as usize)
-		}
+		};
+		// The viewer reads missing rows in jobs.
+		self.start_jobs_timer();
+		rows
 	}
 
 	fn create_pane(&self, element: &mut Element, index: u8) -> Pane {
-		let system: Box<dyn System> = if index < 2 {
-			Box::new(LocalSystem::default())
-		} else {
-			Box::new(SftpSystem::new())
-		};
-		Pane::new(element, index == self.state.active_pane, system)
+		let system: Box<dyn System> = Box::new(LocalSystem::default());
+		Pane::new(
+			element,
+			index,
+			index == self.state.active_pane,
+			system,
+			self.state.jobs.get_spawner(),
+		)
 	}
 }
 
@@ -390,6 +1292,23 @@ impl EventHandler for WindowEventHandler {
 		fn data_source_row_count(String);
 		fn data_source_rows_data(String, i32, i32);
 		fn on_resize_files(i32, i32);
+		fn on_sort_column(String, i32);
+		fn run_palette_item(i32);
+		fn on_select_tab(String, i32);
+		fn on_path_bar_focus(String, bool);
+		fn on_viewer_search_focus(bool);
+		fn cancel_job(i32);
+	}
+
+	fn on_timer(&mut self, root: HELEMENT, timer_id: u64) -> bool {
+		if timer_id == JOBS_TIMER_ID {
+			poll_jobs(&mut self.state, &Element::from(root))
+		} else if timer_id == WATCH_TIMER_ID {
+			self.poll_changes();
+			true
+		} else {
+			false
+		}
 	}
 
 	fn on_event(
@@ -409,6 +1328,8 @@ impl EventHandler for WindowEventHandler {
 
 		if code == BEHAVIOR_EVENTS::DOCUMENT_READY {
 			self.on_document_ready(root);
+		} else if code == BEHAVIOR_EVENTS::DOCUMENT_CLOSE_REQUEST {
+			self.save_settings();
 		}
 
 		false
@@ -419,8 +1340,67 @@ fn find_first(element: &Element, selector: &str) -> Element {
 	element.find_first(selector).unwrap().unwrap()
 }
 
-fn switch_pane(state: &mut WindowState) {
-	let active_pane = 1 - state.active_pane;
+thread_local! {
+	static DIALOG_DEPTH: Cell<u32> = Cell::new(0);
+}
+
+fn is_dialog_active() -> bool {
+	DIALOG_DEPTH.with(|x| x.get() > 0)
+}
+
+fn show_dialog(root: &Element, name: &str, parameters: Value) -> Value {
+	let url = format!("app://xcmd/{}.sciter.html", name);
+	DIALOG_DEPTH.with(|x| x.set(x.get() + 1));
+	let result = root
+		.call_function("showDialog", &sciter::make_args!(url, parameters))
+		.unwrap_or_else(|_err| Value::new());
+	DIALOG_DEPTH.with(|x| x.set(x.get() - 1));
+	result
+}
+
+/// Shows a list of items and returns the index of the chosen one.
+fn show_list_dialog(root: &Element, title: &str, label: &str, items: &[String]) -> Option<usize> {
+	if items.is_empty() {
+		show_error(root, &format!("{} is empty.", title));
+		return None;
+	}
+	let mut values = Value::array(0);
+	for item in items {
+		values.push(Value::from(item.as_str()));
+	}
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("title"), Value::from(title));
+	parameters.set_item(Value::from("label"), Value::from(label));
+	parameters.set_item(Value::from("items"), values);
+	show_dialog(root, "list", parameters)
+		.as_string()
+		.and_then(|index| index.parse::<usize>().ok())
+		.filter(|index| *index < items.len())
+}
+
+fn show_input_dialog(
+	root: &Element,
+	title: &str,
+	label: &str,
+	value: &str,
+	password: bool,
+) -> Option<String> {
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("title"), Value::from(title));
+	parameters.set_item(Value::from("label"), Value::from(label));
+	parameters.set_item(Value::from("value"), Value::from(value));
+	parameters.set_item(Value::from("password"), Value::from(password));
+	show_dialog(root, "input", parameters).as_string()
+}
+
+fn show_error(root: &Element, message: &str) {
+	log::error!("{}", message);
+	root.call_function("showError", &sciter::make_args!(message))
+		.unwrap();
+}
+
+fn switch_pane(state: &mut WindowState) {
+	let active_pane = 1 - state.active_pane;
 	state.set_active_pane(active_pane);
 }
 
@@ -460,15 +1440,50 @@ fn move_end(state: &mut WindowState) {
 	}
 }
 
-fn toggle_select(state: &mut WindowState) {
+/// Toggles the selection of the active item and calculates the size of a
+/// selected directory.
+fn toggle_select(state: &mut WindowState, root: &Element) {
+	let path = match state.get_active_pane() {
+		Some(pane) => {
+			pane.toggle_select();
+			pane.get_directory_to_size()
+		}
+		None => return,
+	};
+	if let Some(path) = path {
+		calculate_sizes(state, root, vec![path]);
+	}
+}
+
+/// Enters a directory or opens a file with its associated program.
+fn enter_item(state: &mut WindowState, root: &Element) {
 	if let Some(pane) = state.get_active_pane() {
-		pane.toggle_select();
+		if pane
+			.get_active_file_path()
+			.map_or(true, |path| archive::is_archive_path(&path))
+		{
+			pane.enter_item();
+			return;
+		}
 	}
+	launch_file(state, root, Action::Open);
 }
 
-fn enter_item(state: &mut WindowState) {
+fn start_quick_search(state: &mut WindowState, filter: bool) {
 	if let Some(pane) = state.get_active_pane() {
-		pane.enter_item();
+		pane.start_quick_search(filter);
+	}
+}
+
+fn sort_by(state: &mut WindowState, field_name: &str) {
+	if let Some(pane) = state.get_active_pane() {
+		pane.sort_by_field(field_name);
+	}
+}
+
+fn toggle_directories_first(state: &mut WindowState) {
+	if let Some(pane) = state.get_active_pane() {
+		pane.toggle_directories_first();
 	}
 }
 
@@ -486,36 +1501,2034 @@ fn select_down(state: &mut WindowState) {
 	}
 }
 
-fn view_file(state: &mut WindowState) {
+/// Selects or deselects files matching globs separated by `;`, or a regular
+/// expression starting with `/`. Patterns ending with a separator match
+/// directories, other patterns match files when selecting.
+fn select_pattern(state: &mut WindowState, root: &Element, select: bool) {
+	let (title, label) = if select {
+		("Select Files", "Select files matching:")
+	} else {
+		("Deselect Files", "Deselect files matching:")
+	};
+	let pattern = match show_input_dialog(root, title, label, "*", false) {
+		Some(pattern) if !pattern.trim().is_empty() => pattern.trim().to_owned(),
+		_ => return,
+	};
 	if let Some(pane) = state.get_active_pane() {
-		if let Some(path) = pane.get_active_path() {
-			Command::new("lister").arg(path).output().expect("lister");
+		if let Err(e) = pane.select_pattern(&pattern, select) {
+			show_error(root, &format!("Invalid pattern: {}", e));
 		}
 	}
 }
 
-fn edit_file(state: &mut WindowState) {
+fn save_selection(state: &mut WindowState) {
+	if let Some(pane) = state.get_active_pane() {
+		let names = pane.get_selected_names();
+		state.saved_selection = names;
+	}
+}
+
+/// Selects the files saved by name, also in another directory.
+fn restore_selection(state: &mut WindowState) {
+	let names: HashSet<String> = state.saved_selection.iter().cloned().collect();
 	if let Some(pane) = state.get_active_pane() {
-		if let Some(path) = pane.get_active_path() {
-			Command::new("notepad").arg(path).output().expect("notepad");
+		pane.select_where(false, |_file| true);
+		pane.select_where(true, |file| {
+			names.contains(get_path_filename(&get_path(file)))
+		});
+	}
+}
+
+fn calculate_selection_size(state: &mut WindowState, root: &Element) {
+	if let Some(pane) = state.get_active_pane() {
+		let paths = pane.get_selected_paths();
+		calculate_sizes(state, root, paths);
+	}
+}
+
+/// Adds up the sizes of the directory trees in the active pane on a worker
+/// thread. The sizes are shown in the pane, once they are known.
+fn calculate_sizes(state: &mut WindowState, root: &Element, paths: Vec<String>) {
+	let pane_index = state.active_pane;
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	let generation = pane.get_sizes_generation();
+	let mut system = match pane.duplicate_system() {
+		Ok(system) => system,
+		Err(e) => {
+			show_error(root, &format!("Failed to calculate sizes: {}", e));
+			return;
+		}
+	};
+	let title = format!("Calculate size of {}", pane.get_parent());
+	let id = state.jobs.spawn(&title, move |context| {
+		for path in paths {
+			match system.get_metadata(&path) {
+				Ok(metadata) if metadata.is_dir => {}
+				_ => continue,
+			}
+			let mut last_report = Instant::now();
+			let result = get_usage(&mut *system, &path, &mut |usage| {
+				if last_report.elapsed() >= PROGRESS_INTERVAL {
+					last_report = Instant::now();
+					context.send(JobMessage::TransferProgress(get_usage_progress(
+						&path, usage,
+					)));
+				}
+				!context.is_cancelled()
+			});
+			match result {
+				Ok(usage) => {
+					context.send(JobMessage::DirectorySizes {
+						pane: pane_index,
+						generation,
+						sizes: vec![(path, usage.bytes)],
+					});
+				}
+				Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => break,
+				Err(e) => log::warn!("Failed to calculate the size of {}: {}", path, e),
+			}
+		}
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Shows the files and bytes found so far in the jobs panel.
+fn get_usage_progress(path: &str, usage: &Usage) -> Progress {
+	Progress {
+		total_files: usage.files,
+		total_bytes: usage.bytes,
+		done_files: usage.files,
+		done_bytes: usage.bytes,
+		current_path: path.to_owned(),
+		..Progress::default()
+	}
+}
+
+fn show_disk_usage(state: &mut WindowState, root: &Element) {
+	let directory = match state.get_active_pane() {
+		Some(pane) => pane.get_parent().to_owned(),
+		None => return,
+	};
+	let pane_index = state.active_pane;
+	calculate_disk_usage(state, root, pane_index, directory);
+}
+
+/// Adds up the sizes of the children of the directory on a worker thread, to
+/// show them in the disk usage dialog.
+fn calculate_disk_usage(
+	state: &mut WindowState,
+	root: &Element,
+	pane_index: u8,
+	directory: String,
+) {
+	let pane = match state.get_pane(pane_index) {
+		Some(pane) => pane,
+		None => return,
+	};
+	let generation = pane.get_sizes_generation();
+	let mut system = match pane.duplicate_system() {
+		Ok(system) => system,
+		Err(e) => {
+			show_error(root, &format!("Failed to calculate the disk usage: {}", e));
+			return;
+		}
+	};
+	let title = format!("Disk usage of {}", directory);
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut last_report = Instant::now();
+		let children = get_children_usage(&mut *system, &directory, &mut |usage| {
+			if last_report.elapsed() >= PROGRESS_INTERVAL {
+				last_report = Instant::now();
+				context.send(JobMessage::TransferProgress(get_usage_progress(
+					&directory, usage,
+				)));
+			}
+			!context.is_cancelled()
+		});
+		context.send(JobMessage::DiskUsage {
+			pane: pane_index,
+			generation,
+			directory,
+			children,
+		});
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Lists the children of the directory by their total size. Choosing a
+/// directory opens it and shows its disk usage.
+fn show_disk_usage_dialog(
+	state: &mut WindowState,
+	root: &Element,
+	pane_index: u8,
+	generation: u64,
+	directory: &str,
+	children: Vec<ChildUsage>,
+) {
+	if let Some(pane) = state.get_pane(pane_index) {
+		let sizes = children
+			.iter()
+			.filter(|child| child.is_dir)
+			.map(|child| (child.path.clone(), child.usage.bytes))
+			.collect();
+		pane.set_directory_sizes(generation, sizes);
+	}
+	let total: u64 = children.iter().map(|child| child.usage.bytes).sum();
+	let files: u64 = children.iter().map(|child| child.usage.files).sum();
+	let mut rows = Value::array(0);
+	for child in &children {
+		let filename = get_path_filename(&child.path);
+		let name = if child.is_dir {
+			format!("[{}]", filename)
+		} else {
+			filename.to_owned()
+		};
+		let percent = if total > 0 {
+			child.usage.bytes as f64 * 100.0 / total as f64
+		} else {
+			0.0
+		};
+		let mut row = Value::map();
+		row.set_item(Value::from("name"), Value::from(name));
+		row.set_item(
+			Value::from("size"),
+			Value::from(child.usage.bytes.separated_string()),
+		);
+		row.set_item(Value::from("percent"), Value::from(percent.round() as i32));
+		row.set_item(
+			Value::from("percentText"),
+			Value::from(format!("{:.1} %", percent)),
+		);
+		row.set_item(
+			Value::from("files"),
+			Value::from(child.usage.files.separated_string()),
+		);
+		rows.push(row);
+	}
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("directory"), Value::from(directory));
+	parameters.set_item(
+		Value::from("total"),
+		Value::from(format!(
+			"{} bytes in {} files",
+			total.separated_string(),
+			files.separated_string()
+		)),
+	);
+	parameters.set_item(Value::from("rows"), rows);
+	let child = show_dialog(root, "usage", parameters)
+		.as_string()
+		.and_then(|index| index.parse::<usize>().ok())
+		.and_then(|index| children.get(index));
+	if let Some(child) = child.filter(|child| child.is_dir) {
+		if let Some(pane) = state.get_pane(pane_index) {
+			pane.navigate(&child.path);
+		}
+		calculate_disk_usage(state, root, pane_index, child.path.clone());
+	}
+}
+
+fn view_file(state: &mut WindowState) {
+	let (system, path) = match state.get_active_pane() {
+		Some(pane) => match pane.get_active_item_path() {
+			Some(path) => (pane.get_system(), path),
+			None => return,
+		},
+		None => return,
+	};
+	if let Some(viewer) = &mut state.viewer {
+		viewer.open(system, &path);
+	}
+}
+
+/// Runs the program associated with the active file. Remote files are edited
+/// in a temporary copy by a job, which uploads the changes.
+fn launch_file(state: &mut WindowState, root: &Element, action: Action) {
+	let associations = match Associations::load() {
+		Ok(associations) => associations,
+		Err(e) => {
+			show_error(root, &format!("Failed to load associations: {}", e));
+			return;
+		}
+	};
+	let (system, path, selection) = match state.get_active_pane() {
+		Some(pane) => match pane.get_active_file_path() {
+			Some(path) => (pane.get_system(), path, pane.get_selected_paths()),
+			None => return,
+		},
+		None => return,
+	};
+	let command = associations.get_command(action, get_path_filename(&path));
+	let location = lock_system(&system).get_location();
+	// Entries of archives are only readable through the system, so they are
+	// opened as copies, like remote files.
+	let may_be_entry = path
+		.char_indices()
+		.any(|(index, c)| (c == '/' || c == '\\') && archive::is_archive_path(&path[..index]));
+	if location.is_empty() && !may_be_entry {
+		let dir = PathBuf::from(&path)
+			.parent()
+			.map_or_else(String::new, |x| x.to_string_lossy().into_owned());
+		if let Err(e) = launch(&expand_command(&command, &path, &dir, &selection), &dir) {
+			show_error(root, &format!("Failed to run {}: {}", command[0], e));
+		}
+	} else {
+		let title = format!("Edit {}{}", location, path);
+		let id = state.jobs.spawn(&title, move |context| {
+			edit_remote_file(context, &system, &path, &command)
+		});
+		if let Some(jobs_panel) = &mut state.jobs_panel {
+			jobs_panel.add(id, &title);
+		}
+	}
+}
+
+fn transfer_files(state: &mut WindowState, root: &Element, mode: TransferMode) {
+	let active_pane = state.active_pane;
+	let (source_paths, description) = match state.get_pane(active_pane) {
+		Some(pane) => {
+			let source_paths = pane.get_selected_paths();
+			let description = match source_paths.as_slice() {
+				[] => return,
+				[path] => format!("\"{}\"", pane.get_filename(path)),
+				paths => format!("{} files", paths.len()),
+			};
+			(source_paths, description)
+		}
+		None => return,
+	};
+	let target_dir = match state.get_pane(1 - active_pane) {
+		Some(pane) => pane.get_parent().to_owned(),
+		None => return,
+	};
+	let title = match mode {
+		TransferMode::Copy => "Copy",
+		TransferMode::Move => "Move",
+	};
+	let label = format!("{} {} to:", title, description);
+	let target_dir = match show_input_dialog(root, title, &label, &target_dir, false) {
+		Some(ref target_dir) if !target_dir.is_empty() => target_dir.to_owned(),
+		_ => return,
+	};
+
+	let source = state
+		.get_pane(active_pane)
+		.as_mut()
+		.map(|pane| pane.duplicate_system());
+	let target = state
+		.get_pane(1 - active_pane)
+		.as_mut()
+		.map(|pane| pane.duplicate_system());
+	match (source, target) {
+		(Some(Ok(source)), Some(Ok(target))) => {
+			let transfer = Transfer::new(mode, source, target, source_paths, &target_dir);
+			let title = format!("{} {} to {}", title, description, target_dir);
+			let id = state
+				.jobs
+				.spawn(&title, move |context| run_transfer(context, transfer));
+			if let Some(jobs_panel) = &mut state.jobs_panel {
+				jobs_panel.add(id, &title);
+			}
+		}
+		(Some(Err(e)), _) | (_, Some(Err(e))) => {
+			show_error(root, &format!("{} failed: {}", title, e))
+		}
+		_ => {}
+	}
+}
+
+/// Packs the selected files into a new archive, by default in the directory of
+/// the other pane.
+fn pack_files(state: &mut WindowState, root: &Element) {
+	let active_pane = state.active_pane;
+	let (source_paths, description, name) = match state.get_pane(active_pane) {
+		Some(pane) => {
+			let source_paths = pane.get_selected_paths();
+			let (description, name) = match source_paths.as_slice() {
+				[] => return,
+				[path] => {
+					let filename = pane.get_filename(path);
+					(format!("\"{}\"", filename), filename)
+				}
+				paths => (
+					format!("{} files", paths.len()),
+					get_path_filename(pane.get_parent()).to_owned(),
+				),
+			};
+			(source_paths, description, name)
+		}
+		None => return,
+	};
+	let archive_path = match state.get_pane(1 - active_pane) {
+		Some(pane) => {
+			let target_dir = pane.get_parent().to_owned();
+			lock_system(&pane.get_system()).join_path(&target_dir, &format!("{}.zip", name))
+		}
+		None => return,
+	};
+	let label = format!("Pack {} to (.zip, .tar, .tar.gz, .tar.zst):", description);
+	let archive_path = match show_input_dialog(root, "Pack", &label, &archive_path, false) {
+		Some(ref archive_path) if !archive_path.is_empty() => archive_path.to_owned(),
+		_ => return,
+	};
+
+	let source = state
+		.get_pane(active_pane)
+		.as_mut()
+		.map(|pane| pane.duplicate_system());
+	let target = state
+		.get_pane(1 - active_pane)
+		.as_mut()
+		.map(|pane| pane.duplicate_system());
+	match (source, target) {
+		(Some(Ok(mut source)), Some(Ok(mut target))) => {
+			let title = format!("Pack {} to {}", description, archive_path);
+			let id = state.jobs.spawn(&title, move |context| {
+				let archive_path =
+					match choose_archive_path(context, &mut *target, &description, archive_path) {
+						Some(archive_path) => archive_path,
+						None => {
+							context.send(JobMessage::TransferFinished(Vec::new()));
+							return;
+						}
+					};
+				let result = archive::create_archive(
+					&mut *source,
+					&source_paths,
+					&mut *target,
+					&archive_path,
+					&mut |progress| {
+						context.send(JobMessage::TransferProgress(progress.clone()));
+						!context.is_cancelled()
+					},
+				);
+				let errors = match result {
+					Ok(()) => Vec::new(),
+					Err(_) if context.is_cancelled() => Vec::new(),
+					Err(e) => vec![format!("Failed to pack {}: {}", archive_path, e)],
+				};
+				context.send(JobMessage::TransferFinished(errors));
+			});
+			if let Some(jobs_panel) = &mut state.jobs_panel {
+				jobs_panel.add(id, &title);
+			}
+		}
+		(Some(Err(e)), _) | (_, Some(Err(e))) => show_error(root, &format!("Pack failed: {}", e)),
+		_ => {}
+	}
+}
+
+/// Asks, whether to overwrite a file existing at the archive path or to pack
+/// to another name. Returns None, if packing is cancelled.
+fn choose_archive_path(
+	context: &JobContext<JobMessage>,
+	target: &mut dyn System,
+	source_description: &str,
+	mut archive_path: String,
+) -> Option<String> {
+	loop {
+		let existing = match target.get_metadata(&archive_path) {
+			Ok(metadata) => metadata,
+			Err(_) => return Some(archive_path),
+		};
+		let conflict = Conflict {
+			source_path: source_description.to_owned(),
+			target_path: archive_path.clone(),
+			// Packed files cannot be resumed.
+			source: Metadata {
+				is_dir: true,
+				..Metadata::default()
+			},
+			target: existing,
+		};
+		let (sender, receiver) = mpsc::channel();
+		context.send(JobMessage::TransferConflict(conflict, sender));
+		match receiver.recv() {
+			Ok((ConflictResolution::Overwrite, _apply_to_all)) => return Some(archive_path),
+			Ok((ConflictResolution::Rename(name), _apply_to_all)) => {
+				let directory = get_path_directory(&archive_path).to_owned();
+				archive_path = target.join_path(&directory, &name);
+			}
+			_ => return None,
 		}
 	}
 }
 
-fn copy_file(state: &mut WindowState, root: &Element) {
+/// Runs on a worker thread and asks the UI thread about conflicts.
+fn run_transfer(context: &JobContext<JobMessage>, transfer: Transfer) {
+	let mut errors = Vec::new();
+	run_transfer_steps(context, transfer, &mut errors);
+	context.send(JobMessage::TransferFinished(errors));
+}
+
+fn run_transfer_steps(
+	context: &JobContext<JobMessage>,
+	mut transfer: Transfer,
+	errors: &mut Vec<String>,
+) {
+	loop {
+		if context.is_cancelled() {
+			transfer.cancel();
+		}
+		match transfer.step() {
+			TransferState::Running => {
+				context.send(JobMessage::TransferProgress(
+					transfer.get_progress().clone(),
+				));
+			}
+			TransferState::Conflict(conflict) => {
+				let (sender, receiver) = mpsc::channel();
+				context.send(JobMessage::TransferConflict(conflict, sender));
+				match receiver.recv() {
+					Ok((resolution, apply_to_all)) => transfer.resolve(resolution, apply_to_all),
+					Err(_) => transfer.cancel(),
+				}
+			}
+			TransferState::Finished => {
+				errors.extend_from_slice(transfer.get_errors());
+				return;
+			}
+		}
+	}
+}
+
+fn poll_jobs(state: &mut WindowState, root: &Element) -> bool {
+	let mut timers_stopped = false;
+	while let Some(event) = state.jobs.try_recv() {
+		// Dialogs run a nested message loop, so the timers must not fire meanwhile.
+		if !timers_stopped && shows_dialog(&event) {
+			root.stop_timer(JOBS_TIMER_ID).unwrap();
+			root.stop_timer(WATCH_TIMER_ID).unwrap();
+			timers_stopped = true;
+		}
+		match event {
+			JobEvent::Message(
+				_,
+				JobMessage::Listing {
+					pane,
+					generation,
+					listing,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					pane.set_listing(generation, listing);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::Watching {
+					pane,
+					generation,
+					watcher,
+					receiver,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					pane.set_watcher(generation, watcher, receiver);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::Changes {
+					pane,
+					generation,
+					changes,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					pane.set_changes(generation, changes);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::PathChecked {
+					pane,
+					generation,
+					path,
+					result,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					if pane.set_checked_path(generation, &path, result) {
+						focus_files(root);
+					}
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::PathCompletion {
+					pane,
+					generation,
+					text,
+					names,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					pane.set_path_completion(generation, &text, names);
+				}
+			}
+			JobEvent::Message(id, JobMessage::TransferProgress(progress)) => {
+				if let Some(jobs_panel) = &mut state.jobs_panel {
+					jobs_panel.update(id, &progress);
+				}
+			}
+			JobEvent::Message(_, JobMessage::TransferConflict(conflict, sender)) => {
+				let _ = sender.send(resolve_conflict(root, &conflict));
+			}
+			JobEvent::Message(_, JobMessage::TransferFinished(errors)) => {
+				finish_transfer(state, root, &errors);
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::ViewerOpened {
+					generation,
+					path,
+					file,
+				},
+			) => {
+				if let Some(viewer) = &mut state.viewer {
+					if let Err(e) = viewer.set_opened(generation, &path, file) {
+						show_error(root, &format!("Failed to view {}: {}", path, e));
+					}
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::ViewerPages {
+					generation,
+					first_page,
+					pages,
+				},
+			) => {
+				if let Some(viewer) = &mut state.viewer {
+					viewer.add_pages(generation, first_page, pages);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::ViewerImage {
+					generation,
+					data_url,
+				},
+			) => {
+				if let Some(viewer) = &mut state.viewer {
+					viewer.set_image(generation, data_url);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::ViewerIndex {
+					generation,
+					checkpoints,
+					row_count,
+					finished,
+				},
+			) => {
+				if let Some(viewer) = &mut state.viewer {
+					viewer.add_index(generation, checkpoints, row_count, finished);
+				}
+			}
+			JobEvent::Message(_, JobMessage::ViewerFound { generation, offset }) => {
+				if let Some(viewer) = &mut state.viewer {
+					viewer.set_found(generation, offset);
+				}
+			}
+			JobEvent::Message(_, JobMessage::EditFailed(message))
+			| JobEvent::Message(_, JobMessage::SearchFailed(message)) => {
+				show_error(root, &message);
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::DirectorySizes {
+					pane,
+					generation,
+					sizes,
+				},
+			) => {
+				if let Some(pane) = state.get_pane(pane) {
+					pane.set_directory_sizes(generation, sizes);
+				}
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::DiskUsage {
+					pane,
+					generation,
+					directory,
+					children,
+				},
+			) => match children {
+				Ok(children) => {
+					show_disk_usage_dialog(state, root, pane, generation, &directory, children)
+				}
+				Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => show_error(
+					root,
+					&format!("Failed to calculate the disk usage of {}: {}", directory, e),
+				),
+			},
+			JobEvent::Message(_, JobMessage::DeletePrepared { pane, children }) => match children {
+				Ok(children) => confirm_delete(state, root, pane, children),
+				Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => show_error(root, &format!("Delete failed: {}", e)),
+			},
+			JobEvent::Message(
+				_,
+				JobMessage::RenameLoaded {
+					pane,
+					system,
+					batch,
+				},
+			) => match batch {
+				Ok(batch) => confirm_multi_rename(state, root, pane, system, batch),
+				Err(e) => show_error(root, &format!("Failed to rename files: {}", e)),
+			},
+			JobEvent::Message(
+				_,
+				JobMessage::Renamed {
+					pane,
+					history,
+					result,
+				},
+			) => finish_multi_rename(state, root, pane, history, result),
+			JobEvent::Message(_, JobMessage::RenameUndone { history, result }) => {
+				finish_undo_multi_rename(state, root, history, result)
+			}
+			JobEvent::Message(
+				_,
+				JobMessage::Compared {
+					left_dir,
+					right_dir,
+					sync,
+					comparisons,
+				},
+			) => match comparisons {
+				Ok(comparisons) if sync => {
+					synchronize_panes(state, root, &left_dir, &right_dir, &comparisons)
+				}
+				Ok(comparisons) => mark_differences(state, &left_dir, &right_dir, &comparisons),
+				Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => show_error(root, &format!("Compare failed: {}", e)),
+			},
+			JobEvent::Finished(id) => {
+				if let Some(jobs_panel) = &mut state.jobs_panel {
+					jobs_panel.remove(id);
+				}
+			}
+		}
+	}
+	let has_jobs = state.jobs.is_busy();
+	if timers_stopped {
+		restart_timers(root, has_jobs);
+	} else if !has_jobs {
+		root.stop_timer(JOBS_TIMER_ID).unwrap();
+	}
+	has_jobs
+}
+
+/// Whether handling the event may show a dialog.
+fn shows_dialog(event: &JobEvent<JobMessage>) -> bool {
+	matches!(
+		event,
+		JobEvent::Message(
+			_,
+			JobMessage::TransferConflict(..)
+				| JobMessage::TransferFinished(_)
+				| JobMessage::ViewerOpened { .. }
+				| JobMessage::EditFailed(_)
+				| JobMessage::SearchFailed(_)
+				| JobMessage::DiskUsage { .. }
+				| JobMessage::DeletePrepared { .. }
+				| JobMessage::RenameLoaded { .. }
+				| JobMessage::Renamed { .. }
+				| JobMessage::RenameUndone { .. }
+				| JobMessage::Compared { .. }
+		)
+	)
+}
+
+/// Starts the timers stopped while a dialog was shown. The jobs timer is
+/// only needed while jobs run.
+fn restart_timers(root: &Element, has_jobs: bool) {
+	if has_jobs {
+		root.start_timer(JOBS_TIMER_INTERVAL, JOBS_TIMER_ID)
+			.unwrap();
+	}
+	root.start_timer(WATCH_TIMER_INTERVAL, WATCH_TIMER_ID)
+		.unwrap();
+}
+
+/// Asks for search criteria and searches the directory tree on a worker
+/// thread. The results are listed in a new tab, while they are found.
+fn find_files(state: &mut WindowState, root: &Element) {
+	let directory = match state.get_active_pane() {
+		Some(pane) => pane.get_parent().to_owned(),
+		None => return,
+	};
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("directory"), Value::from(directory.as_str()));
+	parameters.set_item(Value::from("name"), Value::from("*"));
+	let result = show_dialog(root, "search", parameters);
+	let directory = match result.get_item("directory").as_string() {
+		Some(directory) if !directory.is_empty() => directory,
+		_ => return,
+	};
+	let query = match get_search_query(&result) {
+		Ok(query) => query,
+		Err(message) => {
+			show_error(root, &message);
+			return;
+		}
+	};
+
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	let connection = pane.get_connection().map(str::to_owned);
+	let systems = pane
+		.duplicate_system()
+		.and_then(|system| Ok((system, pane.duplicate_system()?)));
+	let (results_system, mut system) = match systems {
+		Ok(systems) => systems,
+		Err(e) => {
+			show_error(root, &format!("Find failed: {}", e));
+			return;
+		}
+	};
+	let results = Arc::new(Mutex::new(SearchResults::new(&directory)));
+	let results_system = SearchResultsSystem::new(results_system, Arc::clone(&results));
+	if let Err(e) = pane.open_system_tab(Box::new(results_system), connection) {
+		show_error(root, &format!("Find failed: {}", e));
+		return;
+	}
+	let title = format!("Find in {}", directory);
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut last_report = Instant::now();
+		let result = search(
+			&mut *system,
+			&directory,
+			&query,
+			&mut |path| lock_results(&results).add(path),
+			&mut |progress| {
+				if last_report.elapsed() >= PROGRESS_INTERVAL {
+					last_report = Instant::now();
+					context.send(JobMessage::TransferProgress(progress.clone()));
+				}
+				!context.is_cancelled()
+			},
+		);
+		lock_results(&results).notify();
+		match result {
+			Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => {
+				let message = format!("Failed to search {}: {}", directory, e);
+				context.send(JobMessage::SearchFailed(message));
+			}
+			Ok(()) => {}
+		}
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Builds the query from the values of the search dialog.
+fn get_search_query(values: &Value) -> Result<SearchQuery, String> {
+	let get_string = |name: &str| {
+		values
+			.get_item(name)
+			.as_string()
+			.map(|x| x.trim().to_owned())
+			.unwrap_or_default()
+	};
+	let get_bool = |name: &str| values.get_item(name).to_bool().unwrap_or(false);
+	let match_case = get_bool("matchCase");
+	let mut query = SearchQuery::default();
+
+	let name = get_string("name");
+	if !name.is_empty() && name != "*" {
+		let pattern = if get_bool("nameRegex") {
+			name
+		} else {
+			glob_to_regex(&name)
+		};
+		let regex = RegexBuilder::new(&pattern)
+			.case_insensitive(!match_case)
+			.build()
+			.map_err(|e| format!("Invalid file name pattern: {}", e))?;
+		query.name = Some(regex);
+	}
+	let content = get_string("content");
+	if !content.is_empty() {
+		let pattern = if get_bool("contentRegex") {
+			content
+		} else {
+			regex::escape(&content)
+		};
+		let regex = regex::bytes::RegexBuilder::new(&pattern)
+			.case_insensitive(!match_case)
+			.build()
+			.map_err(|e| format!("Invalid content pattern: {}", e))?;
+		query.content = Some(regex);
+	}
+	query.min_size = parse_size(&get_string("minSize"))?;
+	query.max_size = parse_size(&get_string("maxSize"))?;
+	query.modified_after = parse_date(&get_string("after"), 0)?;
+	// The end date is included.
+	query.modified_before = parse_date(&get_string("before"), 1)?;
+	Ok(query)
+}
+
+/// Parses a size in bytes with an optional unit like `k`, `M` or `G`.
+fn parse_size(text: &str) -> Result<Option<u64>, String> {
+	if text.is_empty() {
+		return Ok(None);
+	}
+	let lowercase = text.to_lowercase();
+	let number = lowercase.trim_end_matches('b');
+	let (number, factor) = match number.chars().last() {
+		Some('k') => (&number[..number.len() - 1], 1 << 10),
+		Some('m') => (&number[..number.len() - 1], 1 << 20),
+		Some('g') => (&number[..number.len() - 1], 1 << 30),
+		_ => (number, 1),
+	};
+	number
+		.trim()
+		.parse::<f64>()
+		.ok()
+		.filter(|x| *x >= 0.0)
+		.map(|x| Some((x * factor as f64) as u64))
+		.ok_or_else(|| format!("Invalid size: {}", text))
+}
+
+/// Parses a date as `YYYY-MM-DD` and returns the local midnight, the given
+/// number of days later.
+fn parse_date(text: &str, days: i64) -> Result<Option<SystemTime>, String> {
+	if text.is_empty() {
+		return Ok(None);
+	}
+	NaiveDate::parse_from_str(text, "%Y-%m-%d")
+		.ok()
+		.and_then(|date| date.checked_add_signed(chrono::Duration::days(days)))
+		.and_then(|date| date.and_hms_opt(0, 0, 0))
+		.and_then(|time| Local.from_local_datetime(&time).earliest())
+		.map(|time| Some(SystemTime::from(time)))
+		.ok_or_else(|| format!("Invalid date: {}", text))
+}
+
+/// Compares the directories of the left and right pane on a worker thread.
+fn compare_panes(state: &mut WindowState, root: &Element, mode: CompareMode, sync: bool) {
+	let (left, left_dir) = match &mut state.left_pane {
+		Some(pane) => (pane.duplicate_system(), pane.get_parent().to_owned()),
+		None => return,
+	};
+	let (right, right_dir) = match &mut state.right_pane {
+		Some(pane) => (pane.duplicate_system(), pane.get_parent().to_owned()),
+		None => return,
+	};
+	let (mut left, mut right) = match (left, right) {
+		(Ok(left), Ok(right)) => (left, right),
+		(Err(e), _) | (_, Err(e)) => {
+			show_error(root, &format!("Compare failed: {}", e));
+			return;
+		}
+	};
+	let title = format!("Compare {} and {}", left_dir, right_dir);
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut last_report = Instant::now();
+		let comparisons = compare_directories(
+			&mut *left,
+			&left_dir,
+			&mut *right,
+			&right_dir,
+			mode,
+			&mut |progress| {
+				if last_report.elapsed() >= PROGRESS_INTERVAL {
+					last_report = Instant::now();
+					context.send(JobMessage::TransferProgress(progress.clone()));
+				}
+				!context.is_cancelled()
+			},
+		);
+		context.send(JobMessage::Compared {
+			left_dir,
+			right_dir,
+			sync,
+			comparisons,
+		});
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Returns the class of files in the left or right pane with the difference.
+fn get_mark(difference: Difference, left: bool) -> &'static str {
+	match (difference, left) {
+		// Files existing on one side only are listed in that pane only.
+		(Difference::OnlyLeft, _) | (Difference::OnlyRight, _) => "unique",
+		(Difference::LeftNewer, true) | (Difference::RightNewer, false) => "newer",
+		(Difference::LeftNewer, false) | (Difference::RightNewer, true) => "older",
+		(Difference::LeftLarger, true) | (Difference::RightLarger, false) => "larger",
+		(Difference::LeftLarger, false) | (Difference::RightLarger, true) => "smaller",
+		(Difference::Different, _) | (Difference::Mismatch, _) => "different",
+		(Difference::Identical, _) => "identical",
+	}
+}
+
+/// Marks the files in both panes and selects the differing ones, unless the
+/// panes have moved on to other directories.
+fn mark_differences(
+	state: &mut WindowState,
+	left_dir: &str,
+	right_dir: &str,
+	comparisons: &[Comparison],
+) {
+	for (index, directory) in [left_dir, right_dir].iter().enumerate() {
+		let left = index == 0;
+		if let Some(pane) = state.get_pane(index as u8) {
+			if pane.get_parent() == *directory {
+				let marks: HashMap<String, &'static str> = comparisons
+					.iter()
+					.filter(|comparison| comparison.is_top_level())
+					.map(|comparison| {
+						(
+							comparison.path.clone(),
+							get_mark(comparison.difference, left),
+						)
+					})
+					.collect();
+				pane.set_marks(marks);
+			}
+		}
+	}
+}
+
+/// Shows the copies needed to synchronize the directories for each direction
+/// and runs the chosen ones.
+fn synchronize_panes(
+	state: &mut WindowState,
+	root: &Element,
+	left_dir: &str,
+	right_dir: &str,
+	comparisons: &[Comparison],
+) {
+	let directions = [
+		("leftToRight", SyncDirection::LeftToRight),
+		("rightToLeft", SyncDirection::RightToLeft),
+		("both", SyncDirection::Both),
+	];
+	let mut plans = Value::map();
+	for (name, direction) in &directions {
+		let (to_right, to_left) = get_sync_copies(comparisons, *direction);
+		let mut plan = Value::array(0);
+		for path in &to_right {
+			plan.push(Value::from(format!("\u{2192} {}", path)));
+		}
+		for path in &to_left {
+			plan.push(Value::from(format!("\u{2190} {}", path)));
+		}
+		plans.set_item(Value::from(*name), plan);
+	}
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("left"), Value::from(left_dir));
+	parameters.set_item(Value::from("right"), Value::from(right_dir));
+	parameters.set_item(Value::from("plans"), plans);
+	let mut conflicts = Value::array(0);
+	for path in get_sync_conflicts(comparisons) {
+		conflicts.push(Value::from(path));
+	}
+	parameters.set_item(Value::from("conflicts"), conflicts);
+	let result = show_dialog(root, "sync", parameters).as_string();
+	let direction = match directions
+		.iter()
+		.find(|(name, _)| Some(*name) == result.as_deref())
+	{
+		Some((_, direction)) => *direction,
+		None => return,
+	};
+	let (to_right, to_left) = get_sync_copies(comparisons, direction);
+
+	let mut transfers = Vec::new();
+	for (source_pane, source_dir, target_dir, paths) in [
+		(0, left_dir, right_dir, to_right),
+		(1, right_dir, left_dir, to_left),
+	] {
+		if paths.is_empty() {
+			continue;
+		}
+		let source = state
+			.get_pane(source_pane)
+			.as_mut()
+			.map(|pane| pane.duplicate_system());
+		let target = state
+			.get_pane(1 - source_pane)
+			.as_mut()
+			.map(|pane| pane.duplicate_system());
+		let (mut source, mut target) = match (source, target) {
+			(Some(Ok(source)), Some(Ok(target))) => (source, target),
+			(Some(Err(e)), _) | (_, Some(Err(e))) => {
+				show_error(root, &format!("Synchronize failed: {}", e));
+				return;
+			}
+			_ => return,
+		};
+		let roots: Vec<(String, String)> = paths
+			.iter()
+			.map(|path| {
+				let source_path = join_relative_path(&mut *source, source_dir, path);
+				let target_parent = match path.rfind('/') {
+					Some(index) => join_relative_path(&mut *target, target_dir, &path[..index]),
+					None => target_dir.to_owned(),
+				};
+				(source_path, target_parent)
+			})
+			.collect();
+		let mut transfer = Transfer::new(TransferMode::Copy, source, target, Vec::new(), "");
+		transfer.set_default_resolution(ConflictResolution::Overwrite);
+		for (source_path, target_parent) in roots {
+			transfer.add(&source_path, &target_parent);
+		}
+		transfers.push(transfer);
+	}
+	let title = format!("Synchronize {} and {}", left_dir, right_dir);
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut errors = Vec::new();
+		for transfer in transfers {
+			run_transfer_steps(context, transfer, &mut errors);
+		}
+		context.send(JobMessage::TransferFinished(errors));
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+fn resolve_conflict(root: &Element, conflict: &Conflict) -> (ConflictResolution, bool) {
+	let mut parameters = Value::map();
+	parameters.set_item(
+		Value::from("source"),
+		Value::from(conflict.source_path.as_str()),
+	);
+	parameters.set_item(
+		Value::from("target"),
+		Value::from(conflict.target_path.as_str()),
+	);
+	parameters.set_item(
+		Value::from("sourceSize"),
+		Value::from(conflict.source.size.separated_string()),
+	);
+	parameters.set_item(
+		Value::from("targetSize"),
+		Value::from(conflict.target.size.separated_string()),
+	);
+	parameters.set_item(
+		Value::from("canResume"),
+		Value::from(!conflict.source.is_dir && conflict.target.size < conflict.source.size),
+	);
+	let result = show_dialog(root, "conflict", parameters);
+	let apply_to_all = result.get_item("all").to_bool().unwrap_or(false);
+	let resolution = match result.get_item("action").as_string() {
+		Some(ref action) if action == "overwrite" => ConflictResolution::Overwrite,
+		Some(ref action) if action == "skip" => ConflictResolution::Skip,
+		Some(ref action) if action == "resume" => ConflictResolution::Resume,
+		Some(ref action) if action == "rename" => match result.get_item("name").as_string() {
+			Some(name) => ConflictResolution::Rename(name),
+			None => ConflictResolution::Skip,
+		},
+		_ => ConflictResolution::Cancel,
+	};
+	(resolution, apply_to_all)
+}
+
+fn finish_transfer(state: &mut WindowState, root: &Element, errors: &[String]) {
+	if let Some(pane) = &mut state.left_pane {
+		pane.refresh();
+	}
+	if let Some(pane) = &mut state.right_pane {
+		pane.refresh();
+	}
+	if !errors.is_empty() {
+		let mut message = format!("{} item(s) failed:\n", errors.len());
+		for error in errors.iter().take(10) {
+			message.push_str(&format!("\n{}", error));
+		}
+		show_error(root, &message);
+	}
+}
+
+fn create_directory(state: &mut WindowState, root: &Element) {
+	let name = match show_input_dialog(root, "Create Directory", "New directory:", "", false) {
+		Some(ref name) if !name.is_empty() => name.to_owned(),
+		_ => return,
+	};
 	if let Some(pane) = state.get_active_pane() {
-		if let Some(path) = pane.get_active_path() {
-			// root.eval_script("view.dialog({ url: \"app://xcmd/copy.sciter.html\", width: this.toPixels(100dip), height: this.toPixels(100dip) })").unwrap();
-			root.eval_script("view.dialog(\"app://xcmd/copy.sciter.html\")")
-				.unwrap();
+		if let Err(e) = pane.create_directory(&name) {
+			show_error(root, &format!("Failed to create directory {}: {}", name, e));
+		}
+	}
+}
+
+/// Moves the selected files to the trash. Files of systems without a trash,
+/// e.g. SFTP connections, and files in the trash are deleted permanently
+/// after a confirmation instead.
+fn delete_files(state: &mut WindowState, root: &Element) {
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	if !lock_system(&pane.get_system()).has_trash() {
+		delete_permanently(state, root);
+		return;
+	}
+	let paths = pane.get_selected_paths();
+	if paths.is_empty() {
+		return;
+	}
+	let mut system = match pane.duplicate_system() {
+		Ok(system) => system,
+		Err(e) => {
+			show_error(root, &format!("Delete failed: {}", e));
+			return;
 		}
+	};
+	let title = format!("Move to trash from {}", pane.get_parent());
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut errors = Vec::new();
+		for (index, path) in paths.iter().enumerate() {
+			if context.is_cancelled() {
+				break;
+			}
+			context.send(JobMessage::TransferProgress(Progress {
+				total_files: paths.len() as u64,
+				done_files: index as u64,
+				current_path: path.clone(),
+				..Progress::default()
+			}));
+			if let Err(e) = system.move_to_trash(path) {
+				errors.push(format!("{}: {}", path, e));
+			}
+		}
+		context.send(JobMessage::TransferFinished(errors));
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
 	}
 }
 
+/// Adds up the selected files on a worker thread, to confirm deleting them
+/// permanently.
+fn delete_permanently(state: &mut WindowState, root: &Element) {
+	let pane_index = state.active_pane;
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	let paths = pane.get_selected_paths();
+	if paths.is_empty() {
+		return;
+	}
+	let mut system = match pane.duplicate_system() {
+		Ok(system) => system,
+		Err(e) => {
+			show_error(root, &format!("Delete failed: {}", e));
+			return;
+		}
+	};
+	let title = format!("Prepare deleting in {}", pane.get_parent());
+	let id = state.jobs.spawn(&title, move |context| {
+		let children = get_paths_usage(&mut *system, paths, context);
+		context.send(JobMessage::DeletePrepared {
+			pane: pane_index,
+			children,
+		});
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Adds up the files below each path.
+fn get_paths_usage(
+	system: &mut dyn System,
+	paths: Vec<String>,
+	context: &JobContext<JobMessage>,
+) -> Result<Vec<ChildUsage>, Error> {
+	let mut children = Vec::with_capacity(paths.len());
+	let mut last_report = Instant::now();
+	for path in paths {
+		let metadata = system.get_metadata(&path)?;
+		// Links are counted as files, without their target.
+		let usage = get_usage(system, &path, &mut |usage| {
+			if last_report.elapsed() >= PROGRESS_INTERVAL {
+				last_report = Instant::now();
+				context.send(JobMessage::TransferProgress(get_usage_progress(
+					&path, usage,
+				)));
+			}
+			!context.is_cancelled()
+		})?;
+		children.push(ChildUsage {
+			path,
+			is_dir: metadata.is_dir && !metadata.is_link,
+			usage,
+		});
+	}
+	Ok(children)
+}
+
+/// Lists the files to delete with their sizes and deletes them on a worker
+/// thread, once confirmed.
+fn confirm_delete(
+	state: &mut WindowState,
+	root: &Element,
+	pane_index: u8,
+	children: Vec<ChildUsage>,
+) {
+	let mut total = Usage::default();
+	let mut rows = Value::array(0);
+	for child in &children {
+		total.bytes += child.usage.bytes;
+		total.files += child.usage.files;
+		total.directories += child.usage.directories + child.is_dir as u64;
+		let filename = get_path_filename(&child.path);
+		let (name, files) = if child.is_dir {
+			(
+				format!("[{}]", filename),
+				format!("{} files", child.usage.files.separated_string()),
+			)
+		} else {
+			(filename.to_owned(), String::new())
+		};
+		let mut row = Value::map();
+		row.set_item(Value::from("name"), Value::from(name));
+		row.set_item(
+			Value::from("size"),
+			Value::from(child.usage.bytes.separated_string()),
+		);
+		row.set_item(Value::from("files"), Value::from(files));
+		rows.push(row);
+	}
+	let description = match children.as_slice() {
+		[child] => format!("\"{}\"", get_path_filename(&child.path)),
+		children => format!("{} items", children.len()),
+	};
+	let mut parameters = Value::map();
+	parameters.set_item(
+		Value::from("message"),
+		Value::from(format!(
+			"Delete {} permanently? This cannot be undone.",
+			description
+		)),
+	);
+	parameters.set_item(
+		Value::from("total"),
+		Value::from(format!(
+			"{} bytes in {} files and {} directories",
+			total.bytes.separated_string(),
+			total.files.separated_string(),
+			total.directories.separated_string()
+		)),
+	);
+	parameters.set_item(Value::from("rows"), rows);
+	if !show_dialog(root, "delete", parameters)
+		.to_bool()
+		.unwrap_or(false)
+	{
+		return;
+	}
+
+	let mut system = match state.get_pane(pane_index) {
+		Some(pane) => match pane.duplicate_system() {
+			Ok(system) => system,
+			Err(e) => {
+				show_error(root, &format!("Delete failed: {}", e));
+				return;
+			}
+		},
+		None => return,
+	};
+	let title = format!("Delete {}", description);
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut errors = Vec::new();
+		let mut deleted = Usage::default();
+		let mut last_report = Instant::now();
+		for child in children {
+			let result = delete_tree(&mut *system, &child.path, &mut |usage| {
+				if last_report.elapsed() >= PROGRESS_INTERVAL {
+					last_report = Instant::now();
+					context.send(JobMessage::TransferProgress(Progress {
+						total_files: total.files,
+						total_bytes: total.bytes,
+						done_files: deleted.files + usage.files,
+						done_bytes: deleted.bytes + usage.bytes,
+						current_path: child.path.clone(),
+						..Progress::default()
+					}));
+				}
+				!context.is_cancelled()
+			});
+			match result {
+				Ok(usage) => {
+					deleted.files += usage.files;
+					deleted.bytes += usage.bytes;
+				}
+				Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => break,
+				Err(e) => errors.push(format!("{}: {}", child.path, e)),
+			}
+		}
+		context.send(JobMessage::TransferFinished(errors));
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Lists the trashed files in a new tab, from where they can be restored.
+fn show_trash(state: &mut WindowState, root: &Element) {
+	if !trash::is_supported() {
+		show_error(root, "There is no trash on this system.");
+		return;
+	}
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	// The parent item of the trash leads back to a local directory.
+	let parent = pane.get_parent();
+	let origin = if pane.get_connection().is_none() && Path::new(parent).is_dir() {
+		parent.to_owned()
+	} else {
+		expand_home("~").unwrap_or_default()
+	};
+	let system = TrashSystem::new(Box::new(LocalSystem::default()), &origin);
+	if let Err(e) = pane.open_system_tab(Box::new(system), None) {
+		show_error(root, &format!("Failed to show the trash: {}", e));
+	}
+}
+
+/// Moves the selected items of the trash back to where they were deleted
+/// from.
+fn restore_from_trash(state: &mut WindowState, root: &Element) {
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	if pane.get_parent() != TRASH_PATH {
+		show_error(
+			root,
+			"Files are restored from the trash shown by \"Show Trash\".",
+		);
+		return;
+	}
+	let mut errors = Vec::new();
+	for path in pane.get_selected_paths() {
+		if let Err(e) = trash::restore(&path) {
+			errors.push(format!("{}: {}", pane.get_filename(&path), e));
+		}
+	}
+	finish_transfer(state, root, &errors);
+}
+
+fn rename_item(state: &mut WindowState, root: &Element) {
+	if let Some(pane) = state.get_active_pane() {
+		if let Some(path) = pane.get_active_item_path() {
+			let filename = pane.get_filename(&path);
+			let new_name = match show_input_dialog(root, "Rename", "New name:", &filename, false) {
+				Some(ref new_name) if !new_name.is_empty() && new_name != &filename => {
+					new_name.to_owned()
+				}
+				_ => return,
+			};
+			if let Err(e) = pane.rename_item(&path, &new_name) {
+				show_error(root, &format!("Failed to rename {}: {}", filename, e));
+			}
+		}
+	}
+}
+
+/// Reads the selected files on a worker thread, to rename them after a
+/// template.
+fn multi_rename(state: &mut WindowState) {
+	let pane_index = state.active_pane;
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	let paths = pane.get_selected_paths();
+	if paths.is_empty() {
+		return;
+	}
+	let system = pane.get_system();
+	let title = format!("Prepare renaming in {}", pane.get_parent());
+	let id = state.jobs.spawn(&title, move |context| {
+		let batch = RenameBatch::load(&mut **lock_system(&system), &paths);
+		context.send(JobMessage::RenameLoaded {
+			pane: pane_index,
+			system,
+			batch,
+		});
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Asks for the template, previewing the new names while the dialog is
+/// edited, and renames the files on a worker thread.
+fn confirm_multi_rename(
+	state: &mut WindowState,
+	root: &Element,
+	pane_index: u8,
+	system: Arc<Mutex<Box<dyn System>>>,
+	batch: RenameBatch,
+) {
+	let batch = Arc::new(batch);
+	let preview_batch = Arc::clone(&batch);
+	let preview = move |args: &[Value]| -> Value {
+		let mut result = Value::map();
+		let options = get_rename_options(args.first().unwrap_or(&Value::new()));
+		let renamer = match Renamer::new(&options) {
+			Ok(renamer) => renamer,
+			Err(message) => {
+				result.set_item(Value::from("error"), Value::from(message));
+				return result;
+			}
+		};
+		let mut rows = Value::array(0);
+		for preview in preview_batch.preview(&renamer) {
+			let mut row = Value::array(0);
+			row.push(Value::from(preview.name));
+			row.push(Value::from(preview.new_name));
+			row.push(Value::from(preview.problem.unwrap_or("")));
+			rows.push(row);
+		}
+		result.set_item(Value::from("rows"), rows);
+		result
+	};
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("template"), Value::from("[N].[E]"));
+	parameters.set_item(Value::from("preview"), Value::from(preview));
+	let result = show_dialog(root, "multi-rename", parameters);
+	if result.get_item("template").as_string().is_none() {
+		return;
+	}
+
+	let renamer = match Renamer::new(&get_rename_options(&result)) {
+		Ok(renamer) => renamer,
+		Err(message) => {
+			show_error(root, &message);
+			return;
+		}
+	};
+	let previews = batch.preview(&renamer);
+	if let Some(preview) = previews.iter().find(|preview| preview.problem.is_some()) {
+		let message = format!(
+			"Cannot rename {} to {}: {}.",
+			preview.name,
+			preview.new_name,
+			preview.problem.unwrap_or_default()
+		);
+		show_error(root, &message);
+		return;
+	}
+	let title = "Rename files".to_owned();
+	let id = state.jobs.spawn(&title, move |context| {
+		let mut locked_system = lock_system(&system);
+		let renames = batch.get_renames(&mut **locked_system, &previews);
+		let result = apply_renames(&mut **locked_system, &renames);
+		drop(locked_system);
+		context.send(JobMessage::Renamed {
+			pane: pane_index,
+			history: RenameHistory { system, renames },
+			result,
+		});
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Keeps the renamed files active and remembers the renames to undo them.
+fn finish_multi_rename(
+	state: &mut WindowState,
+	root: &Element,
+	pane_index: u8,
+	history: RenameHistory,
+	result: Result<(), Error>,
+) {
+	if let Err(e) = result {
+		show_error(root, &format!("Failed to rename files: {}", e));
+		return;
+	}
+	if history.renames.is_empty() {
+		return;
+	}
+	if let Some(pane) = state.get_pane(pane_index) {
+		let active_path = pane.get_active_item_path().map(|active_path| {
+			history
+				.renames
+				.iter()
+				.find(|(path, _new_path)| *path == active_path)
+				.map_or(active_path, |(_path, new_path)| new_path.clone())
+		});
+		pane.update(active_path.as_deref());
+	}
+	state.rename_history = Some(history);
+}
+
+/// Reads the values of the multi-rename dialog.
+fn get_rename_options(values: &Value) -> RenameOptions {
+	let get_string = |name: &str| values.get_item(name).as_string().unwrap_or_default();
+	let get_bool = |name: &str| values.get_item(name).to_bool().unwrap_or(false);
+	let get_number =
+		|name: &str, default: i64| get_string(name).trim().parse::<i64>().unwrap_or(default);
+	let case = match get_string("case").as_str() {
+		"lower" => Case::Lower,
+		"upper" => Case::Upper,
+		"title" => Case::Title,
+		_ => Case::Unchanged,
+	};
+	RenameOptions {
+		template: get_string("template"),
+		search: get_string("search"),
+		replace: get_string("replace"),
+		regex: get_bool("regex"),
+		match_case: get_bool("matchCase"),
+		case,
+		counter_start: get_number("counterStart", 1),
+		counter_step: get_number("counterStep", 1),
+	}
+}
+
+/// Renames the files of the last multi-rename back on a worker thread.
+fn undo_multi_rename(state: &mut WindowState, root: &Element) {
+	let history = match state.rename_history.take() {
+		Some(history) => history,
+		None => {
+			show_error(root, "There is no multiple rename to undo.");
+			return;
+		}
+	};
+	let title = "Undo multiple rename".to_owned();
+	let id = state.jobs.spawn(&title, move |context| {
+		let renames: Vec<(String, String)> = history
+			.renames
+			.iter()
+			.map(|(path, new_path)| (new_path.clone(), path.clone()))
+			.collect();
+		let result = apply_renames(&mut **lock_system(&history.system), &renames);
+		context.send(JobMessage::RenameUndone { history, result });
+	});
+	if let Some(jobs_panel) = &mut state.jobs_panel {
+		jobs_panel.add(id, &title);
+	}
+}
+
+/// Keeps the history to try again, if undoing failed.
+fn finish_undo_multi_rename(
+	state: &mut WindowState,
+	root: &Element,
+	history: RenameHistory,
+	result: Result<(), Error>,
+) {
+	if let Err(e) = result {
+		show_error(root, &format!("Failed to undo the rename: {}", e));
+		state.rename_history = Some(history);
+		return;
+	}
+	for index in 0..2 {
+		if let Some(pane) = state.get_pane(index) {
+			pane.refresh();
+		}
+	}
+}
+
+fn load_connection_profiles() -> Result<Vec<ConnectionProfile>, Error> {
+	let toml = config::read_config_file("connections.toml")
+		.unwrap_or_else(|| include_str!("../../config/connections.toml").to_owned());
+	ConnectionProfile::parse_toml(&toml)
+}
+
+fn open_connection(state: &mut WindowState, root: &Element) {
+	let profiles = match load_connection_profiles() {
+		Ok(profiles) => profiles,
+		Err(e) => {
+			show_error(root, &format!("Failed to load connections: {}", e));
+			return;
+		}
+	};
+
+	let mut items = Value::array(0);
+	for profile in &profiles {
+		let mut item = Value::map();
+		item.set_item(Value::from("name"), Value::from(profile.name.as_str()));
+		item.set_item(Value::from("description"), Value::from(profile.to_string()));
+		items.push(item);
+	}
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("profiles"), items);
+	let name = match show_dialog(root, "connect", parameters).as_string() {
+		Some(name) => name,
+		None => return,
+	};
+	let profile = match profiles.into_iter().find(|x| x.name == name) {
+		Some(profile) => profile,
+		None => return,
+	};
+
+	if let Some(system) = connect(root, profile) {
+		if let Some(pane) = state.get_active_pane() {
+			if let Err(e) = pane.set_system(Box::new(system), Some(name.to_owned())) {
+				show_error(root, &format!("Failed to open {}: {}", name, e));
+			}
+		}
+	}
+}
+
+/// Opens the systems of the tabs saved on exit. Tabs, whose connection fails,
+/// are left out.
+fn restore_tabs<'a>(
+	root: &Element,
+	pane_settings: &'a PaneSettings,
+) -> Vec<(Box<dyn System>, &'a TabSettings)> {
+	let mut tabs: Vec<(Box<dyn System>, &TabSettings)> = Vec::new();
+	for tab_settings in &pane_settings.tabs {
+		let system: Option<Box<dyn System>> = match &tab_settings.connection {
+			Some(name) => connect_by_name(root, name).map(|x| Box::new(x) as Box<dyn System>),
+			None => Some(Box::new(LocalSystem::default())),
+		};
+		if let Some(system) = system {
+			tabs.push((system, tab_settings));
+		}
+	}
+	tabs
+}
+
+/// Connects to a profile saved by name with a tab or a bookmark. Connections
+/// opened from the path bar without a profile are named by their URL.
+fn connect_by_name(root: &Element, name: &str) -> Option<SftpSystem> {
+	let profiles = load_connection_profiles().unwrap_or_else(|e| {
+		log::error!("Failed to load connections: {}", e);
+		Vec::new()
+	});
+	match profiles.into_iter().find(|x| x.name == name) {
+		Some(profile) => connect(root, profile),
+		None => match ConnectionProfile::parse_url(name) {
+			Some((profile, _path)) => connect(root, profile),
+			None => {
+				log::warn!("Connection {} no longer exists.", name);
+				None
+			}
+		},
+	}
+}
+
+/// Returns the saved profile of the account in the URL, or else a profile
+/// named by the URL, which authenticates with the SSH agent.
+fn get_url_profile(url_profile: ConnectionProfile) -> ConnectionProfile {
+	load_connection_profiles()
+		.ok()
+		.and_then(|profiles| {
+			profiles
+				.into_iter()
+				.find(|x| x.is_same_account(&url_profile))
+		})
+		.unwrap_or(url_profile)
+}
+
+/// Connects to the profile, asking for the password and confirmation of an
+/// unknown host key.
+fn connect(root: &Element, mut profile: ConnectionProfile) -> Option<SftpSystem> {
+	if profile.needs_password() {
+		let label = format!("Password for {}:", profile);
+		match show_input_dialog(root, "Password", &label, "", true) {
+			Some(password) => profile.set_password(password),
+			None => return None,
+		}
+	}
+
+	let known_hosts = KnownHostsFiles::new(config::config_file("known_hosts"));
+	let mut confirm = |host_key: &HostKey| confirm_host_key(root, host_key);
+	match SftpSystem::connect(&profile, &known_hosts, &mut confirm) {
+		Ok(system) => Some(system),
+		Err(e) => {
+			show_error(root, &format!("Failed to connect to {}: {}", profile, e));
+			None
+		}
+	}
+}
+
+fn confirm_host_key(root: &Element, host_key: &HostKey) -> HostKeyDecision {
+	let mut parameters = Value::map();
+	parameters.set_item(Value::from("host"), Value::from(host_key.host.as_str()));
+	parameters.set_item(
+		Value::from("keyType"),
+		Value::from(host_key.key_type.as_str()),
+	);
+	parameters.set_item(
+		Value::from("fingerprint"),
+		Value::from(host_key.fingerprint.as_str()),
+	);
+	parameters.set_item(
+		Value::from("changed"),
+		Value::from(host_key.status == HostKeyStatus::Changed),
+	);
+	match show_dialog(root, "host-key", parameters).as_string() {
+		Some(ref decision) if decision == "save" => HostKeyDecision::AcceptAndSave,
+		Some(ref decision) if decision == "once" => HostKeyDecision::AcceptOnce,
+		_ => HostKeyDecision::Reject,
+	}
+}
+
+fn edit_path(state: &mut WindowState) {
+	with_active_pane(state, Pane::edit_path);
+}
+
+/// Opens the path typed in the path bar: an `sftp://` URL, a local path
+/// starting with `~` or a path of the current tab's system.
+fn navigate_path_bar(state: &mut WindowState, root: &Element) {
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	let text = pane.get_path_text().trim().to_owned();
+	let path = if text.starts_with("sftp://") {
+		let (url_profile, path) = match ConnectionProfile::parse_url(&text) {
+			Some(url) => url,
+			None => {
+				pane.show_path_error(&format!("Invalid URL: {}", text));
+				return;
+			}
+		};
+		let profile = get_url_profile(url_profile);
+		if pane.get_connection() != Some(profile.name.as_str()) {
+			let name = profile.name.clone();
+			let system = match connect(root, profile) {
+				Some(system) => system,
+				None => return,
+			};
+			if let Err(e) = pane.set_system(Box::new(system), Some(name.clone())) {
+				pane.show_path_error(&format!("Failed to open {}: {}", name, e));
+				return;
+			}
+		}
+		match path {
+			Some(path) => path,
+			None => {
+				focus_files(root);
+				return;
+			}
+		}
+	} else if let Some(home_path) = expand_home(&text) {
+		if pane.get_connection().is_some() {
+			if let Err(e) = pane.set_system(Box::new(LocalSystem::default()), None) {
+				pane.show_path_error(&format!("Failed to open {}: {}", home_path, e));
+				return;
+			}
+		}
+		home_path
+	} else {
+		text
+	};
+	pane.open_path(&path);
+}
+
+fn complete_path(state: &mut WindowState) {
+	with_active_pane(state, Pane::complete_path);
+}
+
+fn cancel_path_edit(state: &mut WindowState, root: &Element) {
+	with_active_pane(state, Pane::cancel_path_edit);
+	focus_files(root);
+}
+
+/// Moves the focus from the path bar or the search input of the viewer back
+/// to the window, which handles the keys.
+fn focus_files(root: &Element) {
+	Element::from(root.as_ptr())
+		.set_state(ELEMENT_STATE_BITS::STATE_FOCUS, None, true)
+		.unwrap();
+}
+
+fn close_viewer(state: &mut WindowState, root: &Element) {
+	with_viewer(state, Viewer::close);
+	focus_files(root);
+}
+
 fn exit(_state: &mut WindowState, root: &Element) {
 	root.eval_script("view.close()").unwrap();
 }
 
+fn show_history(state: &mut WindowState, root: &Element) {
+	let history = match state.get_active_pane() {
+		Some(pane) => pane.get_history(),
+		None => return,
+	};
+	if let Some(index) = show_list_dialog(root, "Recent Directories", "Go to", &history) {
+		with_active_pane(state, |pane| pane.navigate(&history[index]));
+	}
+}
+
+fn get_bookmark_titles(bookmarks: &[Bookmark]) -> Vec<String> {
+	bookmarks
+		.iter()
+		.map(|bookmark| match &bookmark.connection {
+			Some(connection) => format!("{} ({}: {})", bookmark.name, connection, bookmark.path),
+			None => format!("{} ({})", bookmark.name, bookmark.path),
+		})
+		.collect()
+}
+
+fn go_to_bookmark(state: &mut WindowState, root: &Element) {
+	let titles = get_bookmark_titles(&state.bookmarks);
+	let bookmark = match show_list_dialog(root, "Bookmarks", "Go to bookmark", &titles) {
+		Some(index) => state.bookmarks[index].clone(),
+		None => return,
+	};
+	let pane = match state.get_active_pane() {
+		Some(pane) => pane,
+		None => return,
+	};
+	if pane.get_connection() != bookmark.connection.as_deref() {
+		let system: Box<dyn System> = match &bookmark.connection {
+			Some(name) => match connect_by_name(root, name) {
+				Some(system) => Box::new(system),
+				None => return,
+			},
+			None => Box::new(LocalSystem::default()),
+		};
+		if let Err(e) = pane.set_system(system, bookmark.connection.clone()) {
+			show_error(root, &format!("Failed to open {}: {}", bookmark.name, e));
+			return;
+		}
+	}
+	pane.navigate(&bookmark.path);
+}
+
+fn add_bookmark(state: &mut WindowState, root: &Element) {
+	let (path, connection) = match state.get_active_pane() {
+		Some(pane) => (
+			pane.get_parent().to_owned(),
+			pane.get_connection().map(str::to_owned),
+		),
+		None => return,
+	};
+	let default_name = get_path_filename(&path).to_owned();
+	if let Some(name) = show_input_dialog(root, "Add Bookmark", "Name:", &default_name, false) {
+		state.bookmarks.push(Bookmark {
+			name,
+			path,
+			connection,
+		});
+	}
+}
+
+fn remove_bookmark(state: &mut WindowState, root: &Element) {
+	let titles = get_bookmark_titles(&state.bookmarks);
+	if let Some(index) = show_list_dialog(root, "Bookmarks", "Remove bookmark", &titles) {
+		state.bookmarks.remove(index);
+	}
+}
+
+fn new_tab(state: &mut WindowState, root: &Element) {
+	if let Some(pane) = state.get_active_pane() {
+		if let Err(e) = pane.open_system_tab(Box::new(LocalSystem::default()), None) {
+			show_error(root, &format!("Failed to open a tab: {}", e));
+		}
+	}
+}
+
+fn duplicate_tab(state: &mut WindowState, root: &Element) {
+	if let Some(pane) = state.get_active_pane() {
+		if let Err(e) = pane.duplicate_tab() {
+			show_error(root, &format!("Failed to duplicate the tab: {}", e));
+		}
+	}
+}
+
+fn move_tab_to_other_pane(state: &mut WindowState) {
+	let tab = match state.get_active_pane() {
+		Some(pane) => pane.close_tab(),
+		None => None,
+	};
+	if let Some(tab) = tab {
+		let other_pane = 1 - state.active_pane;
+		state.set_active_pane(other_pane);
+		if let Some(pane) = state.get_active_pane() {
+			pane.open_tab(tab);
+		}
+	}
+}
+
+fn select_theme(state: &mut WindowState, root: &Element) {
+	let mut items = Value::array(0);
+	let mut parameters = Value::map();
+	{
+		let themes = state.themes.borrow();
+		for name in themes.get_names() {
+			items.push(Value::from(name));
+		}
+		parameters.set_item(
+			Value::from("current"),
+			Value::from(themes.get_current().get_name()),
+		);
+	}
+	parameters.set_item(Value::from("themes"), items);
+	let name = match show_dialog(root, "theme", parameters).as_string() {
+		Some(name) => name,
+		None => return,
+	};
+
+	let (selected, problems) = {
+		let mut themes = state.themes.borrow_mut();
+		let problems = match themes.get(&name) {
+			Some(theme) => theme.get_problems(),
+			None => return,
+		};
+		(themes.select(&name), problems)
+	};
+	if !selected {
+		show_error(
+			root,
+			&format!("Color theme {} is invalid. {}", name, problems.join(" ")),
+		);
+		return;
+	}
+	if !problems.is_empty() {
+		log::warn!("Color theme {}: {}", name, problems.join(" "));
+	}
+	apply_theme(root, &state.themes.borrow());
+}
+
+/// Re-renders the style sheet of the window with the current theme.
+fn apply_theme(root: &Element, themes: &Themes) {
+	let bytes = match get_app_data("xcmd/style.sciter.css") {
+		Some(bytes) => bytes,
+		None => return,
+	};
+	let template = Template::new(&String::from_utf8_lossy(bytes));
+	let css = themes.get_current().render(&template);
+	let (base_url, _) = sciter::s2w!("app://xcmd/style.sciter.css");
+	let (media_type, _) = sciter::s2w!("screen");
+	let scapi = sciter::SciterAPI();
+	(scapi.SciterSetCSS)(
+		root.get_hwnd(true),
+		css.as_ptr(),
+		css.len() as u32,
+		base_url.as_ptr(),
+		media_type.as_ptr(),
+	);
+}
+
 fn show_palette(state: &mut WindowState) {
 	if let Some(palette) = &mut state.palette {
 		palette.activate(true);
@@ -528,6 +3541,37 @@ fn hide_palette(state: &mut WindowState) {
 	}
 }
 
+fn accept_palette(state: &mut WindowState) {
+	if let Some(palette) = &mut state.palette {
+		state.next_command = palette.get_active_command().map(str::to_owned);
+		palette.activate(false);
+	}
+}
+
+fn with_palette(state: &mut WindowState, f: fn(&mut Palette)) {
+	if let Some(palette) = &mut state.palette {
+		f(palette);
+	}
+}
+
+fn with_active_pane<F>(state: &mut WindowState, f: F)
+where
+	F: FnOnce(&mut Pane),
+{
+	if let Some(pane) = state.get_active_pane() {
+		f(pane);
+	}
+}
+
+fn with_viewer<F>(state: &mut WindowState, f: F)
+where
+	F: FnOnce(&mut Viewer),
+{
+	if let Some(viewer) = &mut state.viewer {
+		f(viewer);
+	}
+}
+
 // fn resolve_link(path: &Path) -> PathBuf {
 // 	match std::fs::read_link(path) {
 // 		Ok(path_buf) => path_buf,
@@ -1,48 +1,71 @@
-use crate::ui::Template;
+use crate::ui::{Template, Themes};
 use sciter::host::{HostHandler, LOAD_RESULT, SCN_LOAD_DATA};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
 
 const APP_DATA: &[(&str, &[u8])] = &include!(concat!(env!("OUT_DIR"), "/$app_data.rs"));
 
-pub struct WindowSciterHandler {}
+pub struct WindowSciterHandler {
+	themes: Rc<RefCell<Themes>>,
+}
 
 impl WindowSciterHandler {
-	pub fn new() -> Self {
-		WindowSciterHandler {}
+	pub fn new(themes: Rc<RefCell<Themes>>) -> Self {
+		WindowSciterHandler { themes }
 	}
 }
 
+/// Returns a file of the application, e.g. `xcmd/style.sciter.css`.
+pub fn get_app_data(requested_path: &str) -> Option<&'static [u8]> {
+	APP_DATA
+		.iter()
+		.find(|(path, _bytes)| *path == requested_path)
+		.map(|(_path, bytes)| *bytes)
+}
+
+/// Returns the names of the color theme variables used by the templates.
+pub fn get_theme_variable_names() -> HashSet<String> {
+	APP_DATA
+		.iter()
+		.filter(|(path, _bytes)| is_template(path))
+		.flat_map(|(_path, bytes)| {
+			Template::new(&String::from_utf8_lossy(bytes)).get_variable_names()
+		})
+		.collect()
+}
+
+fn is_template(path: &str) -> bool {
+	path.ends_with(".html") || path.ends_with(".css")
+}
+
 impl HostHandler for WindowSciterHandler {
 	fn on_data_load(&mut self, data: &mut SCN_LOAD_DATA) -> Option<LOAD_RESULT> {
 		let requested_uri = sciter::w2s!(data.uri);
 		log::info!("Loading: {:?}", &requested_uri);
 		if requested_uri.starts_with("app://") {
 			let requested_path = &requested_uri["app://".len()..];
-			for (path, bytes) in APP_DATA {
-				if &requested_path == path {
-					if requested_path.ends_with(".html") || requested_path.ends_with(".css") {
-						let template = Template::new(&String::from_utf8_lossy(bytes));
-						let color_theme = Template::parse_toml(include_str!(
-							"../../config/dark.color-theme.toml"
-						));
-						let rendered_template = template.render(&color_theme);
-						let mut html_with_bom = vec![0xef, 0xbb, 0xbf];
-						html_with_bom.extend_from_slice(rendered_template.as_bytes());
-						self.data_ready(
-							data.hwnd,
-							&requested_uri,
-							&html_with_bom,
-							None, //Some(data.request_id),
-						);
-						return Some(LOAD_RESULT::LOAD_DEFAULT);
-					} else {
-						self.data_ready(
-							data.hwnd,
-							&requested_uri,
-							bytes,
-							None, //Some(data.request_id),
-						);
-						return Some(LOAD_RESULT::LOAD_DEFAULT);
-					}
+			if let Some(bytes) = get_app_data(requested_path) {
+				if is_template(requested_path) {
+					let template = Template::new(&String::from_utf8_lossy(bytes));
+					let rendered_template = self.themes.borrow().get_current().render(&template);
+					let mut html_with_bom = vec![0xef, 0xbb, 0xbf];
+					html_with_bom.extend_from_slice(rendered_template.as_bytes());
+					self.data_ready(
+						data.hwnd,
+						&requested_uri,
+						&html_with_bom,
+						None, //Some(data.request_id),
+					);
+					return Some(LOAD_RESULT::LOAD_DEFAULT);
+				} else {
+					self.data_ready(
+						data.hwnd,
+						&requested_uri,
+						bytes,
+						None, //Some(data.request_id),
+					);
+					return Some(LOAD_RESULT::LOAD_DEFAULT);
 				}
 			}
 		}
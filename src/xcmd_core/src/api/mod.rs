@@ -1,11 +1,21 @@
+use chrono::{DateTime, Local};
 use std::error;
 use std::fmt;
-use std::io;
-use std::rc::Rc;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 #[derive(Debug)]
 pub enum Error {
 	IoError(io::Error),
+	SshError(ssh2::Error),
+	WatchError(notify::Error),
+	ZipError(zip::result::ZipError),
+	ConfigError(String),
+	AuthenticationError(String),
+	HostKeyMismatch { host: String, fingerprint: String },
+	HostKeyRejected { host: String, fingerprint: String },
 }
 
 impl error::Error for Error {
@@ -22,10 +32,25 @@ impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
 			Error::IoError(ref e) => e.fmt(f),
-			// _ => {
-			// 	use std::error::Error;
-			// 	f.write_str(self.description())
-			// }
+			Error::SshError(ref e) => e.fmt(f),
+			Error::WatchError(ref e) => e.fmt(f),
+			Error::ZipError(ref e) => e.fmt(f),
+			Error::ConfigError(ref message) => write!(f, "Configuration error: {}", message),
+			Error::AuthenticationError(ref message) => {
+				write!(f, "Authentication failed: {}", message)
+			}
+			Error::HostKeyMismatch {
+				ref host,
+				ref fingerprint,
+			} => write!(
+				f,
+				"Host key of {} has changed to {} and was not accepted.",
+				host, fingerprint
+			),
+			Error::HostKeyRejected {
+				ref host,
+				ref fingerprint,
+			} => write!(f, "Host key {} of {} was not accepted.", fingerprint, host),
 		}
 	}
 }
@@ -36,17 +61,45 @@ impl From<io::Error> for Error {
 	}
 }
 
+impl From<ssh2::Error> for Error {
+	fn from(err: ssh2::Error) -> Error {
+		Error::SshError(err)
+	}
+}
+
+impl From<notify::Error> for Error {
+	fn from(err: notify::Error) -> Error {
+		Error::WatchError(err)
+	}
+}
+
+impl From<zip::result::ZipError> for Error {
+	fn from(err: zip::result::ZipError) -> Error {
+		Error::ZipError(err)
+	}
+}
+
+impl From<toml::de::Error> for Error {
+	fn from(err: toml::de::Error) -> Error {
+		Error::ConfigError(err.to_string())
+	}
+}
+
+#[derive(Clone)]
 pub struct File {
 	pub selected: bool,
-	pub field_names: Rc<Vec<String>>,
+	pub is_dir: bool,
+	pub field_names: Arc<Vec<String>>,
 	pub fields: Vec<Value>,
 }
 
+#[derive(Clone)]
 pub enum Icon {
 	Local(String),
 	Shell(String),
 }
 
+#[derive(Clone)]
 pub enum Value {
 	String {
 		string: String,
@@ -59,6 +112,26 @@ pub enum Value {
 	Size {
 		bytes: u64,
 	},
+	DateTime {
+		time: SystemTime,
+	},
+	/// Unix mode bits, including the file type.
+	Permissions {
+		mode: u32,
+	},
+	/// Windows file attributes.
+	Attributes {
+		attributes: u32,
+	},
+	Owner {
+		user: String,
+		group: String,
+	},
+	Link {
+		target: String,
+	},
+	/// A value the system does not know, e.g. the link target of a regular file.
+	Empty,
 }
 
 impl fmt::Display for Value {
@@ -67,14 +140,67 @@ impl fmt::Display for Value {
 			Value::String { string } => write!(f, "{}", string),
 			Value::Path { name, .. } => write!(f, "{}", name),
 			Value::Size { bytes } => write!(f, "{}", &bytes.to_string()),
+			Value::DateTime { time } => write!(
+				f,
+				"{}",
+				DateTime::<Local>::from(*time).format("%Y-%m-%d %H:%M")
+			),
+			Value::Permissions { mode } => write!(f, "{}", format_permissions(*mode)),
+			Value::Attributes { attributes } => write!(f, "{}", format_attributes(*attributes)),
+			Value::Owner { user, group } => write!(f, "{}:{}", user, group),
+			Value::Link { target } => write!(f, "{}", target),
+			Value::Empty => Ok(()),
 		}
 	}
 }
 
+/// Formats mode bits the way `ls -l` does, e.g. `drwxr-xr-x`.
+pub fn format_permissions(mode: u32) -> String {
+	let file_type = match mode & 0o170_000 {
+		0o040_000 => 'd',
+		0o120_000 => 'l',
+		0o020_000 => 'c',
+		0o060_000 => 'b',
+		0o010_000 => 'p',
+		0o140_000 => 's',
+		_ => '-',
+	};
+	let mut permissions = String::with_capacity(10);
+	permissions.push(file_type);
+	for (shift, special, special_char) in &[(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+		let bits = (mode >> shift) & 0o7;
+		permissions.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+		permissions.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+		permissions.push(match (bits & 0o1 != 0, mode & special != 0) {
+			(true, true) => *special_char,
+			(false, true) => special_char.to_ascii_uppercase(),
+			(true, false) => 'x',
+			(false, false) => '-',
+		});
+	}
+	permissions
+}
+
+/// Formats Windows file attributes as read-only, archive, hidden and system
+/// flags, e.g. `-a--`.
+pub fn format_attributes(attributes: u32) -> String {
+	[(0x01, 'r'), (0x20, 'a'), (0x02, 'h'), (0x04, 's')]
+		.iter()
+		.map(|(flag, flag_char)| {
+			if attributes & flag != 0 {
+				*flag_char
+			} else {
+				'-'
+			}
+		})
+		.collect()
+}
+
 impl File {
-	pub fn new(field_names: &Rc<Vec<String>>, fields: Vec<Value>) -> Self {
+	pub fn new(field_names: &Arc<Vec<String>>, is_dir: bool, fields: Vec<Value>) -> Self {
 		File {
 			selected: false,
+			is_dir,
 			field_names: field_names.clone(),
 			fields,
 		}
@@ -85,17 +211,82 @@ impl File {
 	}
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+	pub is_dir: bool,
+	pub size: u64,
+	pub modified: Option<SystemTime>,
+	pub permissions: Option<u32>,
+	/// A symbolic link. The other fields describe its target, unless the
+	/// link is broken.
+	pub is_link: bool,
+}
+
+/// A change of a watched directory, made by any program.
+pub enum WatchEvent {
+	/// A file was created or modified.
+	Changed(String),
+	Removed(String),
+	/// Changes were missed, the directory has to be listed again.
+	Rescan,
+}
+
+/// Watches a directory until it is dropped.
+pub struct Watcher {
+	_watcher: Box<dyn Send>,
+}
+
+impl Watcher {
+	pub fn new(watcher: Box<dyn Send>) -> Self {
+		Watcher { _watcher: watcher }
+	}
+}
+
 pub trait Cursor<TFile, TError> {
 	fn next() -> Result<TFile, TError>;
 }
 
-pub trait System {
-	fn get_root(&mut self, field_names: &Rc<Vec<String>>) -> Result<File, Error>;
-	fn get_file(&mut self, path: &str, field_names: &Rc<Vec<String>>) -> Result<File, Error>;
+pub trait System: Send {
+	/// Identifies the backend, e.g. `sftp://user@host:22`. Empty for local files.
+	fn get_location(&mut self) -> String;
+	/// Opens another handle to the same backend, e.g. for a transfer.
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error>;
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error>;
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error>;
 	fn get_filename(&mut self, path: &str) -> String;
 	fn list_files(
 		&mut self,
 		parent_directory: &File,
-		field_names: &Rc<Vec<String>>,
+		field_names: &Arc<Vec<String>>,
 	) -> Result<Vec<File>, Error>;
+	fn join_path(&mut self, directory: &str, name: &str) -> String;
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error>;
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error>;
+	/// Lists paths and metadata of directory children, without the parent entry.
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error>;
+	fn create_directory(&mut self, path: &str) -> Result<(), Error>;
+	/// Deletes a file or an empty directory.
+	fn delete(&mut self, path: &str) -> Result<(), Error>;
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error>;
+	/// Returns the target of a symbolic link.
+	fn read_link(&mut self, path: &str) -> Result<String, Error>;
+	/// Creates a symbolic link at the path, pointing to the target.
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error>;
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error>;
+	/// Opens a file for writing, truncating it unless `append` is set.
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error>;
+	/// Sends changes of the children of a directory until the watcher is
+	/// dropped. Returns `None`, if the system cannot watch and must be polled.
+	fn watch(&mut self, path: &str, sender: Sender<WatchEvent>) -> Result<Option<Watcher>, Error>;
+	/// Tells, whether deleted files can be moved to a trash to be restored.
+	fn has_trash(&mut self) -> bool {
+		false
+	}
+	/// Moves a file or a directory tree to the trash.
+	fn move_to_trash(&mut self, path: &str) -> Result<(), Error> {
+		Err(Error::IoError(io::Error::new(
+			io::ErrorKind::Other,
+			format!("{} cannot be moved to the trash.", path),
+		)))
+	}
 }
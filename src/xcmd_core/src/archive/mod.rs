@@ -0,0 +1,1214 @@
+use crate::api::{Error, File, Icon, Metadata, System, Value, WatchEvent, Watcher};
+use crate::transfer::Progress;
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const PAGE_SIZE: u64 = 64 * 1024;
+const CHUNK_SIZE: usize = 64 * 1024;
+const CACHED_ARCHIVES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ArchiveKind {
+	Zip,
+	Tar,
+	TarGz,
+	TarZst,
+}
+
+impl ArchiveKind {
+	fn from_path(path: &str) -> Option<ArchiveKind> {
+		let name = path
+			.rsplit(['/', '\\'])
+			.next()
+			.unwrap_or(path)
+			.to_lowercase();
+		if name.ends_with(".zip") {
+			Some(ArchiveKind::Zip)
+		} else if name.ends_with(".tar") {
+			Some(ArchiveKind::Tar)
+		} else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+			Some(ArchiveKind::TarGz)
+		} else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+			Some(ArchiveKind::TarZst)
+		} else {
+			None
+		}
+	}
+}
+
+/// Tells, whether the file name has the extension of a supported archive.
+pub fn is_archive_path(path: &str) -> bool {
+	ArchiveKind::from_path(path).is_some()
+}
+
+/// Tells, whether the path is an entry within an archive file of the system.
+pub fn is_entry_path(system: &mut dyn System, path: &str) -> bool {
+	match split_archive_path(system, path) {
+		Some((_archive_path, entry_path)) => !entry_path.is_empty(),
+		None => false,
+	}
+}
+
+/// Splits a path into the path of an archive file and the path of an entry in
+/// the archive, which is empty for the archive itself.
+fn split_archive_path(system: &mut dyn System, path: &str) -> Option<(String, String)> {
+	let ends = path
+		.char_indices()
+		.filter(|(_index, c)| *c == '/' || *c == '\\')
+		.map(|(index, _c)| index)
+		.chain(Some(path.len()));
+	for end in ends {
+		let archive_path = &path[..end];
+		if is_archive_path(archive_path)
+			&& system
+				.get_metadata(archive_path)
+				.map_or(false, |metadata| !metadata.is_dir)
+		{
+			let entry_path = normalize_entry_path(&path[end..]);
+			return Some((archive_path.to_owned(), entry_path));
+		}
+	}
+	None
+}
+
+/// The entries of an archive, read once and kept until the archive changes.
+struct Archive {
+	path: String,
+	kind: ArchiveKind,
+	/// Metadata of the archive file, to notice changes.
+	size: u64,
+	modified: Option<SystemTime>,
+	/// Entries by their path in the archive, separated by `/`. Directories
+	/// missing in the archive are added for the files within.
+	entries: BTreeMap<String, Metadata>,
+}
+
+impl Archive {
+	/// Returns the paths and metadata of the children of a directory in the
+	/// archive. The root directory is the empty path.
+	fn get_children(&self, directory: &str) -> Vec<(&str, &Metadata)> {
+		let prefix = if directory.is_empty() {
+			String::new()
+		} else {
+			format!("{}/", directory)
+		};
+		self.entries
+			.range(prefix.clone()..)
+			.take_while(|(path, _metadata)| path.starts_with(&prefix))
+			.filter(|(path, _metadata)| !path[prefix.len()..].contains('/'))
+			.map(|(path, metadata)| (path.as_str(), metadata))
+			.collect()
+	}
+
+	fn add_entry(&mut self, path: &str, metadata: Metadata) {
+		if is_outside_entry_path(path) {
+			log::warn!("Skipping entry {} outside of archive {}", path, self.path);
+			return;
+		}
+		let path = normalize_entry_path(path);
+		if path.is_empty() {
+			return;
+		}
+		let mut parent = path.as_str();
+		while let Some(index) = parent.rfind('/') {
+			parent = &parent[..index];
+			self.entries
+				.entry(parent.to_owned())
+				.or_insert_with(|| Metadata {
+					is_dir: true,
+					..Metadata::default()
+				});
+		}
+		self.entries.insert(path, metadata);
+	}
+}
+
+/// Shows zip and tar archives of another system as directories. Paths in an
+/// archive continue the path of the archive file, e.g.
+/// `/tmp/build.zip/bin/app`. Other paths are passed to the other system.
+/// Archives are read-only; use `create_archive` to pack files.
+pub struct ArchiveSystem {
+	system: Box<dyn System>,
+	/// The most recently used archive last.
+	archives: Vec<Archive>,
+}
+
+impl ArchiveSystem {
+	pub fn new(system: Box<dyn System>) -> Self {
+		ArchiveSystem {
+			system,
+			archives: Vec::new(),
+		}
+	}
+
+	fn find_archive(&mut self, path: &str) -> Option<(String, String)> {
+		split_archive_path(&mut *self.system, path)
+	}
+
+	/// Returns the archive, reading its entries, unless they are cached.
+	fn get_archive(&mut self, path: &str) -> Result<&Archive, Error> {
+		let metadata = self.system.get_metadata(path)?;
+		let cached = self.archives.iter().position(|archive| {
+			archive.path == path
+				&& archive.size == metadata.size
+				&& archive.modified == metadata.modified
+		});
+		let archive = match cached {
+			Some(index) => self.archives.remove(index),
+			None => {
+				self.archives.retain(|archive| archive.path != path);
+				if self.archives.len() == CACHED_ARCHIVES {
+					self.archives.remove(0);
+				}
+				read_archive(&mut *self.system, path, &metadata)?
+			}
+		};
+		self.archives.push(archive);
+		Ok(self.archives.last().unwrap())
+	}
+
+	fn get_entry_metadata(
+		&mut self,
+		archive_path: &str,
+		entry_path: &str,
+	) -> Result<Metadata, Error> {
+		self.get_archive(archive_path)?
+			.entries
+			.get(entry_path)
+			.cloned()
+			.ok_or_else(|| not_found_error(entry_path))
+	}
+
+	/// Joins the path of an entry to the path of its archive.
+	fn get_entry_full_path(&mut self, archive_path: &str, entry_path: &str) -> String {
+		entry_path
+			.split('/')
+			.filter(|name| !name.is_empty())
+			.fold(archive_path.to_owned(), |path, name| {
+				self.system.join_path(&path, name)
+			})
+	}
+
+	fn get_entry_file(
+		&mut self,
+		archive_path: &str,
+		entry_path: &str,
+		metadata: &Metadata,
+		name: Option<String>,
+		field_names: &Arc<Vec<String>>,
+	) -> File {
+		let full_path = self.get_entry_full_path(archive_path, entry_path);
+		let filename = entry_path.rsplit('/').next().unwrap_or(entry_path);
+		let (name, extension) = if let Some(name) = name {
+			(name, "".to_owned())
+		} else if metadata.is_dir {
+			(format!("[{}]", filename), "".to_owned())
+		} else {
+			match filename.rfind('.') {
+				Some(index) if index > 0 => (
+					filename[..index].to_owned(),
+					filename[index + 1..].to_owned(),
+				),
+				_ => (filename.to_owned(), "".to_owned()),
+			}
+		};
+		let icon = Icon::Shell(if metadata.is_dir {
+			"C:\\.".to_owned()
+		} else {
+			format!("C:\\*.{}", extension)
+		});
+		let mut icon = Some(icon);
+		let fields = field_names
+			.iter()
+			.map(|field_name| match field_name.as_str() {
+				"path" => Value::Path {
+					path: full_path.clone(),
+					name: name.clone(),
+					icon: icon.take().unwrap_or(Icon::Shell(String::new())),
+				},
+				"extension" => Value::String {
+					string: extension.clone(),
+				},
+				"size" if !metadata.is_dir => Value::Size {
+					bytes: metadata.size,
+				},
+				"modified_on" => metadata
+					.modified
+					.map(|time| Value::DateTime { time })
+					.unwrap_or(Value::Empty),
+				"attributes" => metadata
+					.permissions
+					.map(|mode| Value::Permissions { mode })
+					.unwrap_or(Value::Empty),
+				_ => Value::Empty,
+			})
+			.collect();
+		File::new(field_names, metadata.is_dir, fields)
+	}
+
+	fn list_entries(
+		&mut self,
+		archive_path: &str,
+		entry_path: &str,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<Vec<File>, Error> {
+		let mut files = Vec::new();
+		let parent_item = match entry_path.rfind('/') {
+			Some(index) => {
+				let parent_path = &entry_path[..index];
+				let metadata = self.get_entry_metadata(archive_path, parent_path)?;
+				self.get_entry_file(
+					archive_path,
+					parent_path,
+					&metadata,
+					Some("[..]".to_owned()),
+					field_names,
+				)
+			}
+			None if !entry_path.is_empty() => self.get_entry_file(
+				archive_path,
+				"",
+				&Metadata {
+					is_dir: true,
+					..Metadata::default()
+				},
+				Some("[..]".to_owned()),
+				field_names,
+			),
+			// The parent of the archive is a directory of the other system.
+			None => {
+				let mut parent = self.get_parent_directory(archive_path, field_names)?;
+				if let Some(path_index) = parent.get_field_index("path") {
+					if let Value::Path { name, .. } = &mut parent.fields[path_index] {
+						*name = "[..]".to_owned();
+					}
+				}
+				parent
+			}
+		};
+		files.push(parent_item);
+		let children: Vec<(String, Metadata)> = self
+			.get_archive(archive_path)?
+			.get_children(entry_path)
+			.into_iter()
+			.map(|(path, metadata)| (path.to_owned(), metadata.clone()))
+			.collect();
+		for (path, metadata) in children {
+			files.push(self.get_entry_file(archive_path, &path, &metadata, None, field_names));
+		}
+		Ok(files)
+	}
+
+	fn get_parent_directory(
+		&mut self,
+		archive_path: &str,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<File, Error> {
+		let filename = self.system.get_filename(archive_path);
+		let parent_path =
+			archive_path[..archive_path.len() - filename.len()].trim_end_matches(['/', '\\']);
+		let parent_path = if parent_path.is_empty() {
+			&archive_path[..1]
+		} else {
+			parent_path
+		};
+		self.system.get_file(parent_path, field_names)
+	}
+}
+
+impl System for ArchiveSystem {
+	fn get_location(&mut self) -> String {
+		self.system.get_location()
+	}
+
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+		Ok(Box::new(ArchiveSystem::new(self.system.duplicate()?)))
+	}
+
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		self.system.get_root(field_names)
+	}
+
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		match self.find_archive(path) {
+			Some((archive_path, entry_path)) if !entry_path.is_empty() => {
+				let metadata = self.get_entry_metadata(&archive_path, &entry_path)?;
+				Ok(self.get_entry_file(&archive_path, &entry_path, &metadata, None, field_names))
+			}
+			_ => self.system.get_file(path, field_names),
+		}
+	}
+
+	fn get_filename(&mut self, path: &str) -> String {
+		self.system.get_filename(path)
+	}
+
+	fn list_files(
+		&mut self,
+		parent_directory: &File,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<Vec<File>, Error> {
+		let path = match parent_directory.get_field_index("path") {
+			Some(path_index) => match &parent_directory.fields[path_index] {
+				Value::Path { path, .. } => path.clone(),
+				_ => String::new(),
+			},
+			None => String::new(),
+		};
+		match self.find_archive(&path) {
+			Some((archive_path, entry_path)) => {
+				self.list_entries(&archive_path, &entry_path, field_names)
+			}
+			None => self.system.list_files(parent_directory, field_names),
+		}
+	}
+
+	fn join_path(&mut self, directory: &str, name: &str) -> String {
+		self.system.join_path(directory, name)
+	}
+
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+		match self.find_archive(path) {
+			Some((archive_path, entry_path)) if !entry_path.is_empty() => {
+				self.get_entry_metadata(&archive_path, &entry_path)
+			}
+			_ => self.system.get_metadata(path),
+		}
+	}
+
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => Err(read_only_error()),
+			_ => self.system.set_metadata(path, metadata),
+		}
+	}
+
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+		let (archive_path, entry_path) = match self.find_archive(path) {
+			Some(archive) => archive,
+			None => return self.system.read_dir(path),
+		};
+		let children: Vec<(String, Metadata)> = self
+			.get_archive(&archive_path)?
+			.get_children(&entry_path)
+			.into_iter()
+			.map(|(path, metadata)| (path.to_owned(), metadata.clone()))
+			.collect();
+		Ok(children
+			.into_iter()
+			.map(|(path, metadata)| (self.get_entry_full_path(&archive_path, &path), metadata))
+			.collect())
+	}
+
+	fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+		match self.find_archive(path) {
+			Some(_archive) => Err(read_only_error()),
+			None => self.system.create_directory(path),
+		}
+	}
+
+	fn delete(&mut self, path: &str) -> Result<(), Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => Err(read_only_error()),
+			_ => self.system.delete(path),
+		}
+	}
+
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error> {
+		match (self.find_archive(path), self.find_archive(new_path)) {
+			(Some((_, entry_path)), _) | (_, Some((_, entry_path))) if !entry_path.is_empty() => {
+				Err(read_only_error())
+			}
+			_ => self.system.rename(path, new_path),
+		}
+	}
+
+	fn read_link(&mut self, path: &str) -> Result<String, Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => {
+				Err(Error::IoError(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("{} is not a link.", entry_path),
+				)))
+			}
+			_ => self.system.read_link(path),
+		}
+	}
+
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => Err(read_only_error()),
+			_ => self.system.create_link(target, path),
+		}
+	}
+
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+		let (archive_path, entry_path) = match self.find_archive(path) {
+			Some((archive_path, entry_path)) if !entry_path.is_empty() => {
+				(archive_path, entry_path)
+			}
+			_ => return self.system.open_read(path, offset),
+		};
+		let (kind, size) = {
+			let archive = self.get_archive(&archive_path)?;
+			(archive.kind, archive.size)
+		};
+		// The entry is decompressed on another thread with its own handle of
+		// the system, as the reader may outlive this borrow.
+		let mut system = self.system.duplicate()?;
+		let (sender, receiver) = mpsc::sync_channel(4);
+		thread::Builder::new()
+			.name("archive-reader".to_owned())
+			.spawn(move || {
+				let mut writer = ChannelWriter {
+					sender: sender.clone(),
+				};
+				let result = copy_entry(
+					&mut *system,
+					kind,
+					&archive_path,
+					size,
+					&entry_path,
+					offset,
+					&mut writer,
+				);
+				if let Err(e) = result {
+					let _ = sender.send(Err(into_io_error(e)));
+				}
+			})?;
+		Ok(Box::new(ChannelReader {
+			receiver,
+			chunk: Vec::new(),
+			position: 0,
+		}))
+	}
+
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => Err(read_only_error()),
+			_ => self.system.open_write(path, append),
+		}
+	}
+
+	fn watch(&mut self, path: &str, sender: Sender<WatchEvent>) -> Result<Option<Watcher>, Error> {
+		match self.find_archive(path) {
+			// Archives are polled; their entries are cached until they change.
+			Some(_archive) => Ok(None),
+			None => self.system.watch(path, sender),
+		}
+	}
+
+	fn has_trash(&mut self) -> bool {
+		self.system.has_trash()
+	}
+
+	fn move_to_trash(&mut self, path: &str) -> Result<(), Error> {
+		match self.find_archive(path) {
+			Some((_archive_path, entry_path)) if !entry_path.is_empty() => Err(read_only_error()),
+			_ => self.system.move_to_trash(path),
+		}
+	}
+}
+
+/// Removes `.` and empty names and leading and trailing separators.
+fn normalize_entry_path(path: &str) -> String {
+	path.split(['/', '\\'])
+		.filter(|name| !name.is_empty() && *name != ".")
+		.collect::<Vec<&str>>()
+		.join("/")
+}
+
+/// Tells, whether an entry would be extracted outside of the target
+/// directory, as it is absolute, has a drive prefix or contains `..`.
+fn is_outside_entry_path(path: &str) -> bool {
+	let bytes = path.as_bytes();
+	path.starts_with(['/', '\\'])
+		|| (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+		|| path.split(['/', '\\']).any(|name| name == "..")
+}
+
+/// Tells, whether the name of an entry in the archive is the entry path.
+fn is_entry_named(name: &str, entry_path: &str) -> bool {
+	!is_outside_entry_path(name) && normalize_entry_path(name) == entry_path
+}
+
+fn read_archive(
+	system: &mut dyn System,
+	path: &str,
+	metadata: &Metadata,
+) -> Result<Archive, Error> {
+	let kind = ArchiveKind::from_path(path).ok_or_else(|| not_found_error(path))?;
+	log::info!("Reading archive {}", path);
+	let mut archive = Archive {
+		path: path.to_owned(),
+		kind,
+		size: metadata.size,
+		modified: metadata.modified,
+		entries: BTreeMap::new(),
+	};
+	if kind == ArchiveKind::Zip {
+		let mut zip = ZipArchive::new(SystemReader::new(system, path, metadata.size))?;
+		for index in 0..zip.len() {
+			let file = zip.by_index_raw(index)?;
+			let modified = file
+				.last_modified()
+				.and_then(|time| {
+					Local
+						.with_ymd_and_hms(
+							i32::from(time.year()),
+							u32::from(time.month()),
+							u32::from(time.day()),
+							u32::from(time.hour()),
+							u32::from(time.minute()),
+							u32::from(time.second()),
+						)
+						.single()
+				})
+				.map(SystemTime::from);
+			let entry_metadata = Metadata {
+				is_dir: file.is_dir(),
+				size: file.size(),
+				modified,
+				permissions: file.unix_mode(),
+				is_link: false,
+			};
+			let name = file.name().to_owned();
+			archive.add_entry(&name, entry_metadata);
+		}
+	} else {
+		let mut tar = open_tar(system, kind, path)?;
+		for entry in tar.entries()? {
+			let entry = entry?;
+			let header = entry.header();
+			let entry_metadata = Metadata {
+				is_dir: header.entry_type().is_dir(),
+				size: header.size().unwrap_or(0),
+				modified: header
+					.mtime()
+					.ok()
+					.map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+				permissions: header.mode().ok(),
+				is_link: false,
+			};
+			let name = entry.path()?.to_string_lossy().into_owned();
+			archive.add_entry(&name, entry_metadata);
+		}
+	}
+	Ok(archive)
+}
+
+fn open_tar(
+	system: &mut dyn System,
+	kind: ArchiveKind,
+	path: &str,
+) -> Result<tar::Archive<Box<dyn Read + Send>>, Error> {
+	let reader = system.open_read(path, 0)?;
+	let reader: Box<dyn Read + Send> = match kind {
+		ArchiveKind::TarGz => Box::new(MultiGzDecoder::new(reader)),
+		ArchiveKind::TarZst => Box::new(zstd::Decoder::new(reader)?),
+		ArchiveKind::Tar | ArchiveKind::Zip => reader,
+	};
+	Ok(tar::Archive::new(reader))
+}
+
+/// Writes the content of an entry from the offset on.
+fn copy_entry(
+	system: &mut dyn System,
+	kind: ArchiveKind,
+	archive_path: &str,
+	size: u64,
+	entry_path: &str,
+	offset: u64,
+	writer: &mut dyn Write,
+) -> Result<(), Error> {
+	if kind == ArchiveKind::Zip {
+		let mut zip = ZipArchive::new(SystemReader::new(system, archive_path, size))?;
+		let index = (0..zip.len())
+			.find(|index| {
+				zip.name_for_index(*index)
+					.map_or(false, |name| is_entry_named(name, entry_path))
+			})
+			.ok_or_else(|| not_found_error(entry_path))?;
+		let mut file = zip.by_index(index)?;
+		io::copy(&mut (&mut file).take(offset), &mut io::sink())?;
+		io::copy(&mut file, writer)?;
+		return Ok(());
+	}
+	let mut tar = open_tar(system, kind, archive_path)?;
+	for entry in tar.entries()? {
+		let mut entry = entry?;
+		if is_entry_named(&entry.path()?.to_string_lossy(), entry_path) {
+			io::copy(&mut (&mut entry).take(offset), &mut io::sink())?;
+			io::copy(&mut entry, writer)?;
+			return Ok(());
+		}
+	}
+	Err(not_found_error(entry_path))
+}
+
+/// Reads a file of a system with seeking. A page is kept, so that the small
+/// reads of the zip directory do not reopen the file.
+struct SystemReader<'a> {
+	system: &'a mut dyn System,
+	path: String,
+	size: u64,
+	position: u64,
+	/// The open stream with its position.
+	stream: Option<(u64, Box<dyn Read + Send>)>,
+	page_start: u64,
+	page: Vec<u8>,
+}
+
+impl<'a> SystemReader<'a> {
+	fn new(system: &'a mut dyn System, path: &str, size: u64) -> Self {
+		SystemReader {
+			system,
+			path: path.to_owned(),
+			size,
+			position: 0,
+			stream: None,
+			page_start: 0,
+			page: Vec::new(),
+		}
+	}
+
+	fn load_page(&mut self, page_start: u64) -> io::Result<()> {
+		let mut stream = match self.stream.take() {
+			Some((position, stream)) if position == page_start => stream,
+			_ => self
+				.system
+				.open_read(&self.path, page_start)
+				.map_err(into_io_error)?,
+		};
+		self.page.clear();
+		(&mut stream).take(PAGE_SIZE).read_to_end(&mut self.page)?;
+		self.page_start = page_start;
+		self.stream = Some((page_start + self.page.len() as u64, stream));
+		Ok(())
+	}
+}
+
+impl Read for SystemReader<'_> {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		if self.position >= self.size || buffer.is_empty() {
+			return Ok(0);
+		}
+		let page_end = self.page_start + self.page.len() as u64;
+		if self.position < self.page_start || self.position >= page_end {
+			self.load_page(self.position / PAGE_SIZE * PAGE_SIZE)?;
+		}
+		let start = (self.position - self.page_start) as usize;
+		let length = buffer.len().min(self.page.len().saturating_sub(start));
+		buffer[..length].copy_from_slice(&self.page[start..start + length]);
+		self.position += length as u64;
+		Ok(length)
+	}
+}
+
+impl Seek for SystemReader<'_> {
+	fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+		let position = match position {
+			SeekFrom::Start(offset) => i128::from(offset),
+			SeekFrom::End(offset) => i128::from(self.size) + i128::from(offset),
+			SeekFrom::Current(offset) => i128::from(self.position) + i128::from(offset),
+		};
+		if position < 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Seek before the start of the file.",
+			));
+		}
+		self.position = position as u64;
+		Ok(self.position)
+	}
+}
+
+/// Passes chunks written on one thread to a `ChannelReader` on another.
+struct ChannelWriter {
+	sender: SyncSender<io::Result<Vec<u8>>>,
+}
+
+impl Write for ChannelWriter {
+	fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+		let length = buffer.len().min(CHUNK_SIZE);
+		self.sender
+			.send(Ok(buffer[..length].to_vec()))
+			.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The reader was closed."))?;
+		Ok(length)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+struct ChannelReader {
+	receiver: Receiver<io::Result<Vec<u8>>>,
+	chunk: Vec<u8>,
+	position: usize,
+}
+
+impl Read for ChannelReader {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		while self.position == self.chunk.len() {
+			match self.receiver.recv() {
+				Ok(Ok(chunk)) => {
+					self.chunk = chunk;
+					self.position = 0;
+				}
+				Ok(Err(e)) => return Err(e),
+				// The writer is done.
+				Err(_) => return Ok(0),
+			}
+		}
+		let length = buffer.len().min(self.chunk.len() - self.position);
+		buffer[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+		self.position += length;
+		Ok(length)
+	}
+}
+
+/// Adds a file or directory to an archive being created, with its name,
+/// metadata, the target of a link and the content.
+type AddEntry<'a> =
+	dyn FnMut(&str, &Metadata, Option<&str>, &mut dyn Read) -> Result<(), Error> + 'a;
+
+/// Packs files and directories into a new archive. The format is chosen by
+/// the extension of the archive path. `report` is called with the progress
+/// and stops packing, if it returns false. An existing file at the archive
+/// path is overwritten. The archive is deleted again, if packing fails after
+/// it was created.
+pub fn create_archive(
+	source: &mut dyn System,
+	paths: &[String],
+	target: &mut dyn System,
+	archive_path: &str,
+	report: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<(), Error> {
+	let kind = ArchiveKind::from_path(archive_path).ok_or_else(|| {
+		Error::IoError(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"Archives must end in .zip, .tar, .tar.gz, .tgz, .tar.zst or .tzst.",
+		))
+	})?;
+	let mut entries = Vec::new();
+	for path in paths {
+		let name = source.get_filename(path);
+		let metadata = source.get_metadata(path)?;
+		collect_entries(source, path, &name, metadata, &mut entries)?;
+	}
+	let progress = Progress {
+		total_files: entries.len() as u64,
+		total_bytes: entries
+			.iter()
+			.map(|(_path, _name, metadata)| metadata.size)
+			.sum(),
+		..Progress::default()
+	};
+	let writer = target.open_write(archive_path, false)?;
+	let result = write_archive(source, kind, &entries, progress, writer, report);
+	if result.is_err() {
+		// A partial archive is of no use.
+		if let Err(e) = target.delete(archive_path) {
+			log::warn!("Failed to delete {}: {}", archive_path, e);
+		}
+	}
+	result
+}
+
+fn write_archive(
+	source: &mut dyn System,
+	kind: ArchiveKind,
+	entries: &[(String, String, Metadata)],
+	mut progress: Progress,
+	writer: Box<dyn Write + Send>,
+	report: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<(), Error> {
+	let mut add_entries = |add: &mut AddEntry| {
+		for (path, name, metadata) in entries {
+			progress.current_path = path.clone();
+			progress.current_size = metadata.size;
+			if !report(&progress) {
+				return Err(Error::IoError(io::Error::new(
+					io::ErrorKind::Interrupted,
+					"Cancelled.",
+				)));
+			}
+			if metadata.is_link {
+				let target = source.read_link(path)?;
+				add(name, metadata, Some(&target), &mut io::empty())?;
+			} else if metadata.is_dir {
+				add(name, metadata, None, &mut io::empty())?;
+			} else {
+				add(name, metadata, None, &mut source.open_read(path, 0)?)?;
+			}
+			progress.done_files += 1;
+			progress.done_bytes += metadata.size;
+		}
+		Ok(())
+	};
+	match kind {
+		ArchiveKind::Zip => {
+			let mut zip = ZipWriter::new_stream(writer);
+			add_entries(&mut |name, metadata, link, reader| {
+				let mut options = SimpleFileOptions::default()
+					.large_file(metadata.size >= u64::from(u32::MAX))
+					.unix_permissions(metadata.permissions.unwrap_or(0o644) & 0o777);
+				if let Some(time) = metadata.modified.and_then(to_zip_time) {
+					options = options.last_modified_time(time);
+				}
+				if let Some(target) = link {
+					zip.add_symlink(name, target, options.unix_permissions(0o777))?;
+				} else if metadata.is_dir {
+					zip.add_directory(name, options)?;
+				} else {
+					zip.start_file(name, options)?;
+					io::copy(reader, &mut zip)?;
+				}
+				Ok(())
+			})?;
+			zip.finish()?.into_inner().flush()?;
+		}
+		ArchiveKind::Tar => {
+			let mut tar = tar::Builder::new(writer);
+			add_entries(&mut |name, metadata, link, reader| {
+				append_tar_entry(&mut tar, name, metadata, link, reader)
+			})?;
+			tar.into_inner()?.flush()?;
+		}
+		ArchiveKind::TarGz => {
+			let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+			add_entries(&mut |name, metadata, link, reader| {
+				append_tar_entry(&mut tar, name, metadata, link, reader)
+			})?;
+			tar.into_inner()?.finish()?.flush()?;
+		}
+		ArchiveKind::TarZst => {
+			let mut tar = tar::Builder::new(zstd::Encoder::new(writer, 0)?);
+			add_entries(&mut |name, metadata, link, reader| {
+				append_tar_entry(&mut tar, name, metadata, link, reader)
+			})?;
+			tar.into_inner()?.finish()?.flush()?;
+		}
+	}
+	Ok(())
+}
+
+/// Adds the file or directory and its descendants as (path, name, metadata),
+/// where the name is the path in the archive. Links are added as links,
+/// without the content of their target.
+fn collect_entries(
+	source: &mut dyn System,
+	path: &str,
+	name: &str,
+	mut metadata: Metadata,
+	entries: &mut Vec<(String, String, Metadata)>,
+) -> Result<(), Error> {
+	if metadata.is_link {
+		metadata.size = 0;
+	}
+	let is_dir = metadata.is_dir && !metadata.is_link;
+	entries.push((path.to_owned(), name.to_owned(), metadata));
+	if is_dir {
+		for (child_path, child_metadata) in source.read_dir(path)? {
+			let child_name = format!("{}/{}", name, source.get_filename(&child_path));
+			collect_entries(source, &child_path, &child_name, child_metadata, entries)?;
+		}
+	}
+	Ok(())
+}
+
+fn append_tar_entry<W: Write>(
+	tar: &mut tar::Builder<W>,
+	name: &str,
+	metadata: &Metadata,
+	link: Option<&str>,
+	reader: &mut dyn Read,
+) -> Result<(), Error> {
+	let mut header = tar::Header::new_gnu();
+	if link.is_some() {
+		header.set_entry_type(tar::EntryType::Symlink);
+		header.set_size(0);
+		header.set_mode(0o777);
+	} else if metadata.is_dir {
+		header.set_entry_type(tar::EntryType::Directory);
+		header.set_size(0);
+		header.set_mode(metadata.permissions.unwrap_or(0o755) & 0o7777);
+	} else {
+		header.set_entry_type(tar::EntryType::Regular);
+		header.set_size(metadata.size);
+		header.set_mode(metadata.permissions.unwrap_or(0o644) & 0o7777);
+	}
+	if let Some(modified) = metadata.modified {
+		let mtime = modified
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |x| x.as_secs());
+		header.set_mtime(mtime);
+	}
+	match link {
+		Some(target) => tar.append_link(&mut header, name, target)?,
+		None => tar.append_data(&mut header, name, reader)?,
+	}
+	Ok(())
+}
+
+fn to_zip_time(time: SystemTime) -> Option<zip::DateTime> {
+	let time = DateTime::<Local>::from(time);
+	zip::DateTime::from_date_and_time(
+		time.year() as u16,
+		time.month() as u8,
+		time.day() as u8,
+		time.hour() as u8,
+		time.minute() as u8,
+		time.second() as u8,
+	)
+	.ok()
+}
+
+fn read_only_error() -> Error {
+	Error::IoError(io::Error::new(
+		io::ErrorKind::PermissionDenied,
+		"Archives are read-only.",
+	))
+}
+
+fn not_found_error(path: &str) -> Error {
+	Error::IoError(io::Error::new(
+		io::ErrorKind::NotFound,
+		format!("{} not found in the archive.", path),
+	))
+}
+
+fn into_io_error(e: Error) -> io::Error {
+	match e {
+		Error::IoError(e) => e,
+		e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use crate::transfer::{Transfer, TransferMode, TransferState};
+	use std::fs;
+
+	fn pack(source: &TestDir, names: &[&str], archive_path: &str) {
+		let paths: Vec<String> = names.iter().map(|name| source.join(name)).collect();
+		create_archive(
+			&mut LocalSystem::default(),
+			&paths,
+			&mut LocalSystem::default(),
+			archive_path,
+			&mut |_progress| true,
+		)
+		.unwrap();
+	}
+
+	#[test]
+	fn archive_kind_is_chosen_by_extension() {
+		assert!(ArchiveKind::from_path("/tmp/a.ZIP") == Some(ArchiveKind::Zip));
+		assert!(ArchiveKind::from_path("/tmp/a.tgz") == Some(ArchiveKind::TarGz));
+		assert!(ArchiveKind::from_path("/tmp/a.tar.zst") == Some(ArchiveKind::TarZst));
+		assert!(ArchiveKind::from_path("/tmp/a.zip/b.txt").is_none());
+	}
+
+	#[test]
+	fn packed_files_can_be_browsed() {
+		let source = TestDir::new();
+		fs::create_dir(source.path().join("directory")).unwrap();
+		fs::write(source.path().join("directory").join("file.txt"), "content").unwrap();
+		for name in &[
+			"packed.zip",
+			"packed.tar",
+			"packed.tar.gz",
+			"packed.tar.zst",
+		] {
+			let archive_path = source.join(name);
+			pack(&source, &["directory"], &archive_path);
+
+			let mut system = ArchiveSystem::new(Box::new(LocalSystem::default()));
+			let directory = format!("{}/directory", archive_path);
+			let children = system.read_dir(&directory).unwrap();
+			assert_eq!(children.len(), 1, "{}", name);
+			let (path, metadata) = &children[0];
+			assert_eq!(*path, format!("{}/file.txt", directory));
+			assert!(!metadata.is_dir);
+			assert_eq!(metadata.size, 7);
+			let mut content = String::new();
+			system
+				.open_read(path, 0)
+				.unwrap()
+				.read_to_string(&mut content)
+				.unwrap();
+			assert_eq!(content, "content");
+			assert!(is_entry_path(&mut system, path));
+			assert!(!is_entry_path(&mut system, &archive_path));
+			assert!(!is_entry_path(&mut system, &source.join("directory")));
+		}
+	}
+
+	#[test]
+	fn failing_before_packing_keeps_an_existing_file() {
+		let source = TestDir::new();
+		for name in &["existing.txt", "existing.zip"] {
+			fs::write(source.path().join(name), "content").unwrap();
+			let result = create_archive(
+				&mut LocalSystem::default(),
+				&[source.join("missing")],
+				&mut LocalSystem::default(),
+				&source.join(name),
+				&mut |_progress| true,
+			);
+			assert!(result.is_err(), "{}", name);
+			assert_eq!(
+				fs::read_to_string(source.path().join(name)).unwrap(),
+				"content"
+			);
+		}
+	}
+
+	#[test]
+	fn cancelled_archive_is_deleted() {
+		let source = TestDir::new();
+		fs::write(source.path().join("file.txt"), "content").unwrap();
+		let archive_path = source.join("packed.tar");
+		let result = create_archive(
+			&mut LocalSystem::default(),
+			&[source.join("file.txt")],
+			&mut LocalSystem::default(),
+			&archive_path,
+			&mut |_progress| false,
+		);
+		assert!(result.is_err());
+		assert!(!source.path().join("packed.tar").exists());
+	}
+
+	/// Names of entries, which must not be extracted outside of the target.
+	const OUTSIDE_NAMES: &[&str] = &[
+		"../outside.txt",
+		"directory/../../outside.txt",
+		"/absolute.txt",
+		"\\absolute.txt",
+		"C:/drive.txt",
+	];
+
+	fn craft_zip(archive_path: &str) {
+		let mut zip = ZipWriter::new(fs::File::create(archive_path).unwrap());
+		for name in OUTSIDE_NAMES.iter().chain(&["inside.txt"]) {
+			zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+			zip.write_all(b"content").unwrap();
+		}
+		zip.finish().unwrap();
+	}
+
+	/// Writes the names into the headers, as `tar::Builder` rejects them.
+	fn craft_tar(archive_path: &str) {
+		let mut tar = tar::Builder::new(fs::File::create(archive_path).unwrap());
+		for name in OUTSIDE_NAMES.iter().chain(&["inside.txt"]) {
+			let mut header = tar::Header::new_old();
+			header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+			header.set_entry_type(tar::EntryType::Regular);
+			header.set_size(7);
+			header.set_mode(0o644);
+			header.set_cksum();
+			tar.append(&header, &b"content"[..]).unwrap();
+		}
+		tar.finish().unwrap();
+	}
+
+	#[test]
+	fn entries_outside_of_the_archive_are_skipped() {
+		assert!(is_outside_entry_path("a/../../b"));
+		assert!(is_outside_entry_path("d:\\b"));
+		assert!(!is_outside_entry_path("a/..b/c"));
+
+		for name in &["crafted.zip", "crafted.tar"] {
+			let directory = TestDir::new();
+			let archive_path = directory.join(name);
+			if name.ends_with(".zip") {
+				craft_zip(&archive_path);
+			} else {
+				craft_tar(&archive_path);
+			}
+			let target = directory.path().join("target");
+			fs::create_dir(&target).unwrap();
+
+			let mut system = ArchiveSystem::new(Box::new(LocalSystem::default()));
+			let source_paths: Vec<String> = system
+				.read_dir(&archive_path)
+				.unwrap()
+				.into_iter()
+				.map(|(path, _metadata)| path)
+				.collect();
+			assert_eq!(source_paths, [format!("{}/inside.txt", archive_path)]);
+			for path in OUTSIDE_NAMES {
+				let result = system
+					.open_read(&format!("{}/{}", archive_path, path), 0)
+					.map(|mut reader| reader.read_to_end(&mut Vec::new()));
+				assert!(!matches!(result, Ok(Ok(_))), "{}", path);
+			}
+
+			let mut transfer = Transfer::new(
+				TransferMode::Copy,
+				Box::new(system),
+				Box::new(LocalSystem::default()),
+				source_paths,
+				target.to_str().unwrap(),
+			);
+			while !matches!(transfer.step(), TransferState::Finished) {}
+			assert!(transfer.get_errors().is_empty(), "{}", name);
+			let mut names: Vec<String> = fs::read_dir(directory.path())
+				.unwrap()
+				.chain(fs::read_dir(&target).unwrap())
+				.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+				.collect();
+			names.sort();
+			assert_eq!(names, [*name, "inside.txt", "target"]);
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn links_are_packed_as_links() {
+		let source = TestDir::new();
+		fs::create_dir(source.path().join("directory")).unwrap();
+		// A link to the parent directory would be packed forever, if followed.
+		std::os::unix::fs::symlink("..", source.path().join("directory").join("loop")).unwrap();
+		let archive_path = source.join("packed.tar");
+		pack(&source, &["directory"], &archive_path);
+
+		let mut tar = tar::Archive::new(fs::File::open(&archive_path).unwrap());
+		let links: Vec<(String, String)> = tar
+			.entries()
+			.unwrap()
+			.map(|entry| entry.unwrap())
+			.filter(|entry| entry.header().entry_type().is_symlink())
+			.map(|entry| {
+				(
+					entry.path().unwrap().to_string_lossy().into_owned(),
+					entry
+						.link_name()
+						.unwrap()
+						.unwrap()
+						.to_string_lossy()
+						.into_owned(),
+				)
+			})
+			.collect();
+		assert_eq!(links, [("directory/loop".to_owned(), "..".to_owned())]);
+
+		pack(&source, &["directory"], &source.join("packed.zip"));
+	}
+}
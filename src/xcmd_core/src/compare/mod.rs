@@ -0,0 +1,483 @@
+use crate::api::{Error, Metadata, System};
+use crate::transfer::Progress;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::time::{Duration, SystemTime};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+/// Modification times closer than this are equal, as some file systems and
+/// archives store them with two seconds precision.
+const TIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareMode {
+	/// Files of the same size and modification time are identical.
+	SizeAndTime,
+	/// Files of the same size are identical, if their contents are.
+	Content,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difference {
+	OnlyLeft,
+	OnlyRight,
+	LeftNewer,
+	RightNewer,
+	/// Both were modified at the same time, but the left one is larger.
+	LeftLarger,
+	RightLarger,
+	/// The contents differ, but neither is newer nor larger. Directories
+	/// containing differences are also different.
+	Different,
+	/// A directory, file or link on one side and another kind on the other.
+	/// These are not synchronized, as one would replace the other.
+	Mismatch,
+	Identical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncDirection {
+	LeftToRight,
+	RightToLeft,
+	Both,
+}
+
+#[derive(Clone, Debug)]
+pub struct Comparison {
+	/// The path relative to the compared directories, separated by `/`.
+	pub path: String,
+	pub is_dir: bool,
+	pub difference: Difference,
+}
+
+impl Comparison {
+	/// Tells, whether the item is directly in the compared directories.
+	pub fn is_top_level(&self) -> bool {
+		!self.path.contains('/')
+	}
+}
+
+/// Compares two directories of any systems recursively. Directories existing
+/// on one side only and links are not descended into. `report` is called with
+/// the progress and stops comparing, if it returns false.
+pub fn compare_directories(
+	left: &mut dyn System,
+	left_dir: &str,
+	right: &mut dyn System,
+	right_dir: &str,
+	mode: CompareMode,
+	report: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<Vec<Comparison>, Error> {
+	let mut comparer = Comparer {
+		left,
+		right,
+		mode,
+		report,
+		progress: Progress::default(),
+		comparisons: Vec::new(),
+	};
+	comparer.compare_directory(left_dir, right_dir, "")?;
+	Ok(comparer.comparisons)
+}
+
+/// The path and metadata of a file in the left and in the right directory.
+type Sides = (Option<(String, Metadata)>, Option<(String, Metadata)>);
+
+struct Comparer<'a> {
+	left: &'a mut dyn System,
+	right: &'a mut dyn System,
+	mode: CompareMode,
+	report: &'a mut dyn FnMut(&Progress) -> bool,
+	progress: Progress,
+	comparisons: Vec<Comparison>,
+}
+
+impl Comparer<'_> {
+	/// Adds the comparisons of the children and returns, whether they are
+	/// all identical.
+	fn compare_directory(
+		&mut self,
+		left_dir: &str,
+		right_dir: &str,
+		relative_dir: &str,
+	) -> Result<bool, Error> {
+		let mut children: BTreeMap<String, Sides> = BTreeMap::new();
+		for (path, metadata) in self.left.read_dir(left_dir)? {
+			let filename = self.left.get_filename(&path);
+			children.entry(filename).or_default().0 = Some((path, metadata));
+		}
+		for (path, metadata) in self.right.read_dir(right_dir)? {
+			let filename = self.right.get_filename(&path);
+			children.entry(filename).or_default().1 = Some((path, metadata));
+		}
+		let mut identical = true;
+		for (filename, sides) in children {
+			let relative_path = if relative_dir.is_empty() {
+				filename
+			} else {
+				format!("{}/{}", relative_dir, filename)
+			};
+			let (is_dir, difference) = match sides {
+				(Some((_, left)), None) => (is_directory(&left), Difference::OnlyLeft),
+				(None, Some((_, right))) => (is_directory(&right), Difference::OnlyRight),
+				(Some((left_path, left)), Some((right_path, right))) => {
+					if is_directory(&left) && is_directory(&right) {
+						let difference =
+							if self.compare_directory(&left_path, &right_path, &relative_path)? {
+								Difference::Identical
+							} else {
+								Difference::Different
+							};
+						(true, difference)
+					} else if left.is_link && right.is_link {
+						let difference = self.compare_links(&left_path, &right_path)?;
+						(false, difference)
+					} else if is_directory(&left)
+						|| is_directory(&right)
+						|| left.is_link || right.is_link
+					{
+						(is_directory(&left), Difference::Mismatch)
+					} else {
+						let difference =
+							self.compare_files(&left_path, &left, &right_path, &right)?;
+						(false, difference)
+					}
+				}
+				(None, None) => continue,
+			};
+			identical &= difference == Difference::Identical;
+			self.comparisons.push(Comparison {
+				path: relative_path,
+				is_dir,
+				difference,
+			});
+		}
+		Ok(identical)
+	}
+
+	fn compare_files(
+		&mut self,
+		left_path: &str,
+		left: &Metadata,
+		right_path: &str,
+		right: &Metadata,
+	) -> Result<Difference, Error> {
+		self.progress.current_path = left_path.to_owned();
+		self.progress.current_bytes = 0;
+		self.progress.current_size = 0;
+		if !(self.report)(&self.progress) {
+			return Err(cancelled_error());
+		}
+		self.progress.done_files += 1;
+		let newer = compare_times(left.modified, right.modified);
+		if self.mode == CompareMode::Content && left.size == right.size {
+			if self.has_same_content(left_path, right_path, left.size)? {
+				return Ok(Difference::Identical);
+			}
+			return Ok(newer.unwrap_or(Difference::Different));
+		}
+		if let Some(newer) = newer {
+			return Ok(newer);
+		}
+		Ok(if left.size > right.size {
+			Difference::LeftLarger
+		} else if left.size < right.size {
+			Difference::RightLarger
+		} else if self.mode == CompareMode::Content {
+			Difference::Different
+		} else {
+			Difference::Identical
+		})
+	}
+
+	/// Links are identical, if they point to the same target.
+	fn compare_links(&mut self, left_path: &str, right_path: &str) -> Result<Difference, Error> {
+		let left_target = self.left.read_link(left_path)?;
+		let right_target = self.right.read_link(right_path)?;
+		self.progress.done_files += 1;
+		Ok(if left_target == right_target {
+			Difference::Identical
+		} else {
+			Difference::Different
+		})
+	}
+
+	fn has_same_content(
+		&mut self,
+		left_path: &str,
+		right_path: &str,
+		size: u64,
+	) -> Result<bool, Error> {
+		self.progress.current_size = size * 2;
+		let left_hash = self.hash_file(true, left_path)?;
+		let right_hash = self.hash_file(false, right_path)?;
+		Ok(left_hash == right_hash)
+	}
+
+	fn hash_file(&mut self, left: bool, path: &str) -> Result<Vec<u8>, Error> {
+		let system = if left {
+			&mut *self.left
+		} else {
+			&mut *self.right
+		};
+		let mut reader = system.open_read(path, 0)?;
+		// The file is hashed in chunks to report the progress and cancel in
+		// between.
+		let mut hasher = Sha256::new();
+		let mut buffer = vec![0; BUFFER_SIZE];
+		loop {
+			let length = reader.read(&mut buffer)?;
+			if length == 0 {
+				break;
+			}
+			hasher.input(&buffer[..length]);
+			self.progress.current_bytes += length as u64;
+			self.progress.done_bytes += length as u64;
+			if !(self.report)(&self.progress) {
+				return Err(cancelled_error());
+			}
+		}
+		Ok(hasher.result().to_vec())
+	}
+}
+
+/// Tells, whether the item is a directory to descend into.
+fn is_directory(metadata: &Metadata) -> bool {
+	metadata.is_dir && !metadata.is_link
+}
+
+/// Returns which side is newer, if the times differ enough to tell.
+fn compare_times(left: Option<SystemTime>, right: Option<SystemTime>) -> Option<Difference> {
+	match (left, right) {
+		(Some(left), Some(right)) => {
+			if left
+				.duration_since(right)
+				.map_or(false, |x| x > TIME_TOLERANCE)
+			{
+				Some(Difference::LeftNewer)
+			} else if right
+				.duration_since(left)
+				.map_or(false, |x| x > TIME_TOLERANCE)
+			{
+				Some(Difference::RightNewer)
+			} else {
+				None
+			}
+		}
+		_ => None,
+	}
+}
+
+/// Returns the relative paths to copy from left to right and from right to
+/// left. In one direction, the source replaces all differing files. In both
+/// directions, the newer file wins and files, which differ otherwise, are
+/// left alone.
+pub fn get_sync_copies(
+	comparisons: &[Comparison],
+	direction: SyncDirection,
+) -> (Vec<String>, Vec<String>) {
+	let mut to_right = Vec::new();
+	let mut to_left = Vec::new();
+	for comparison in comparisons {
+		// Directories on both sides are synchronized by their children.
+		if comparison.is_dir && comparison.difference == Difference::Different {
+			continue;
+		}
+		let copy_to_right = match (direction, comparison.difference) {
+			(_, Difference::Identical) => None,
+			// Replacing a directory by a file or the other way round could lose
+			// data, so these are left to the user, see `get_sync_conflicts`.
+			(_, Difference::Mismatch) => None,
+			(SyncDirection::LeftToRight, Difference::OnlyRight) => None,
+			(SyncDirection::LeftToRight, _) => Some(true),
+			(SyncDirection::RightToLeft, Difference::OnlyLeft) => None,
+			(SyncDirection::RightToLeft, _) => Some(false),
+			(SyncDirection::Both, Difference::OnlyLeft) => Some(true),
+			(SyncDirection::Both, Difference::LeftNewer) => Some(true),
+			(SyncDirection::Both, Difference::OnlyRight) => Some(false),
+			(SyncDirection::Both, Difference::RightNewer) => Some(false),
+			(SyncDirection::Both, _) => None,
+		};
+		match copy_to_right {
+			Some(true) => to_right.push(comparison.path.clone()),
+			Some(false) => to_left.push(comparison.path.clone()),
+			None => {}
+		}
+	}
+	(to_right, to_left)
+}
+
+/// Returns the relative paths, which are not synchronized, because they are
+/// of a different kind on each side.
+pub fn get_sync_conflicts(comparisons: &[Comparison]) -> Vec<String> {
+	comparisons
+		.iter()
+		.filter(|comparison| comparison.difference == Difference::Mismatch)
+		.map(|comparison| comparison.path.clone())
+		.collect()
+}
+
+/// Joins a path relative to the compared directories to one of them.
+pub fn join_relative_path(system: &mut dyn System, directory: &str, relative_path: &str) -> String {
+	relative_path
+		.split('/')
+		.fold(directory.to_owned(), |path, name| {
+			system.join_path(&path, name)
+		})
+}
+
+fn cancelled_error() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::Interrupted, "Cancelled."))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use filetime::FileTime;
+	use std::fs;
+	use std::path::Path;
+
+	fn comparison(path: &str, is_dir: bool, difference: Difference) -> Comparison {
+		Comparison {
+			path: path.to_owned(),
+			is_dir,
+			difference,
+		}
+	}
+
+	fn comparisons() -> Vec<Comparison> {
+		vec![
+			comparison("left", false, Difference::OnlyLeft),
+			comparison("right", false, Difference::OnlyRight),
+			comparison("dir", true, Difference::Different),
+			comparison("dir/left-newer", false, Difference::LeftNewer),
+			comparison("dir/right-newer", false, Difference::RightNewer),
+			comparison("dir/larger", false, Difference::LeftLarger),
+			comparison("same", false, Difference::Identical),
+			comparison("kind", true, Difference::Mismatch),
+		]
+	}
+
+	#[test]
+	fn one_direction_copies_all_differences_from_the_source() {
+		let (to_right, to_left) = get_sync_copies(&comparisons(), SyncDirection::LeftToRight);
+		assert_eq!(
+			to_right,
+			["left", "dir/left-newer", "dir/right-newer", "dir/larger"]
+		);
+		assert!(to_left.is_empty());
+
+		let (to_right, to_left) = get_sync_copies(&comparisons(), SyncDirection::RightToLeft);
+		assert!(to_right.is_empty());
+		assert_eq!(
+			to_left,
+			["right", "dir/left-newer", "dir/right-newer", "dir/larger"]
+		);
+	}
+
+	#[test]
+	fn both_directions_copy_the_newer_files() {
+		let (to_right, to_left) = get_sync_copies(&comparisons(), SyncDirection::Both);
+		assert_eq!(to_right, ["left", "dir/left-newer"]);
+		assert_eq!(to_left, ["right", "dir/right-newer"]);
+	}
+
+	#[test]
+	fn mismatches_are_reported_as_conflicts() {
+		assert_eq!(get_sync_conflicts(&comparisons()), ["kind"]);
+	}
+
+	fn write_file(path: &Path, content: &str, seconds: i64) {
+		fs::write(path, content).unwrap();
+		filetime::set_file_mtime(path, FileTime::from_unix_time(seconds, 0)).unwrap();
+	}
+
+	#[test]
+	fn directories_are_compared_recursively() {
+		let left = TestDir::new();
+		let right = TestDir::new();
+		for dir in &[&left, &right] {
+			fs::create_dir(dir.path().join("dir")).unwrap();
+			write_file(&dir.path().join("dir").join("same"), "same", 1_000_000);
+		}
+		write_file(&left.path().join("dir").join("newer"), "left", 2_000_000);
+		write_file(&right.path().join("dir").join("newer"), "right", 1_000_000);
+		write_file(&left.path().join("content"), "left", 1_000_000);
+		write_file(&right.path().join("content"), "rght", 1_000_000);
+		write_file(&left.path().join("only"), "", 1_000_000);
+		fs::create_dir(left.path().join("kind")).unwrap();
+		write_file(&right.path().join("kind"), "", 1_000_000);
+
+		let compare = |mode| {
+			compare_directories(
+				&mut LocalSystem::default(),
+				left.path().to_str().unwrap(),
+				&mut LocalSystem::default(),
+				right.path().to_str().unwrap(),
+				mode,
+				&mut |_progress| true,
+			)
+			.unwrap()
+			.into_iter()
+			.map(|comparison| (comparison.path, comparison.difference))
+			.collect::<Vec<_>>()
+		};
+		let expected = |content| {
+			vec![
+				("content".to_owned(), content),
+				("dir/newer".to_owned(), Difference::LeftNewer),
+				("dir/same".to_owned(), Difference::Identical),
+				("dir".to_owned(), Difference::Different),
+				("kind".to_owned(), Difference::Mismatch),
+				("only".to_owned(), Difference::OnlyLeft),
+			]
+		};
+		assert_eq!(
+			compare(CompareMode::SizeAndTime),
+			expected(Difference::Identical)
+		);
+		assert_eq!(
+			compare(CompareMode::Content),
+			expected(Difference::Different)
+		);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn links_are_compared_by_target() {
+		use std::os::unix::fs::symlink;
+		let left = TestDir::new();
+		let right = TestDir::new();
+		for dir in &[&left, &right] {
+			symlink(".", dir.path().join("loop")).unwrap();
+		}
+		symlink("a", left.path().join("target")).unwrap();
+		symlink("b", right.path().join("target")).unwrap();
+		symlink(".", left.path().join("kind")).unwrap();
+		fs::create_dir(right.path().join("kind")).unwrap();
+
+		let comparisons: Vec<_> = compare_directories(
+			&mut LocalSystem::default(),
+			left.path().to_str().unwrap(),
+			&mut LocalSystem::default(),
+			right.path().to_str().unwrap(),
+			CompareMode::SizeAndTime,
+			&mut |_progress| true,
+		)
+		.unwrap()
+		.into_iter()
+		.map(|comparison| (comparison.path, comparison.difference))
+		.collect();
+		assert_eq!(
+			comparisons,
+			[
+				("kind".to_owned(), Difference::Mismatch),
+				("loop".to_owned(), Difference::Identical),
+				("target".to_owned(), Difference::Different),
+			]
+		);
+	}
+}
@@ -0,0 +1,104 @@
+use crate::api::{Error, Metadata, System};
+use crate::usage::Usage;
+use std::io;
+
+/// Deletes a file or a directory tree. Links to directories are deleted
+/// without their contents. `report` is called with the usage deleted so far
+/// and stops, if it returns false.
+pub fn delete_tree(
+	system: &mut dyn System,
+	path: &str,
+	report: &mut dyn FnMut(&Usage) -> bool,
+) -> Result<Usage, Error> {
+	let metadata = system.get_metadata(path)?;
+	let mut deleted = Usage::default();
+	delete_path(system, path, &metadata, &mut deleted, report)?;
+	Ok(deleted)
+}
+
+fn delete_path(
+	system: &mut dyn System,
+	path: &str,
+	metadata: &Metadata,
+	deleted: &mut Usage,
+	report: &mut dyn FnMut(&Usage) -> bool,
+) -> Result<(), Error> {
+	if metadata.is_link {
+		system.delete(path)?;
+		deleted.files += 1;
+	} else if metadata.is_dir {
+		for (child_path, child_metadata) in system.read_dir(path)? {
+			delete_path(system, &child_path, &child_metadata, deleted, report)?;
+		}
+		system.delete(path)?;
+		deleted.directories += 1;
+	} else {
+		system.delete(path)?;
+		deleted.files += 1;
+		deleted.bytes += metadata.size;
+	}
+	if report(deleted) {
+		Ok(())
+	} else {
+		Err(cancelled_error())
+	}
+}
+
+fn cancelled_error() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::Interrupted, "Cancelled."))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use std::fs;
+
+	#[test]
+	fn trees_are_deleted_with_their_contents() {
+		let dir = TestDir::new();
+		fs::create_dir_all(dir.path().join("tree").join("sub")).unwrap();
+		fs::write(dir.path().join("tree").join("one"), "1").unwrap();
+		fs::write(dir.path().join("tree").join("sub").join("two"), "22").unwrap();
+		let deleted = delete_tree(
+			&mut LocalSystem::default(),
+			&dir.join("tree"),
+			&mut |_usage| true,
+		)
+		.unwrap();
+		assert_eq!(
+			(deleted.bytes, deleted.files, deleted.directories),
+			(3, 2, 2)
+		);
+		assert!(!dir.path().join("tree").exists());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn links_are_deleted_without_their_targets() {
+		use std::os::unix::fs::symlink;
+		let dir = TestDir::new();
+		fs::create_dir_all(dir.path().join("kept")).unwrap();
+		fs::write(dir.path().join("kept").join("file"), "content").unwrap();
+		fs::create_dir(dir.path().join("tree")).unwrap();
+		symlink(
+			dir.path().join("kept"),
+			dir.path().join("tree").join("link"),
+		)
+		.unwrap();
+		symlink("missing", dir.path().join("tree").join("broken")).unwrap();
+		let deleted = delete_tree(
+			&mut LocalSystem::default(),
+			&dir.join("tree"),
+			&mut |_usage| true,
+		)
+		.unwrap();
+		assert_eq!(
+			(deleted.bytes, deleted.files, deleted.directories),
+			(0, 2, 1)
+		);
+		assert!(!dir.path().join("tree").exists());
+		assert!(dir.path().join("kept").join("file").is_file());
+	}
+}
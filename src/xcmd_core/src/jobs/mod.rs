@@ -0,0 +1,170 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+pub type JobId = u64;
+
+pub enum JobEvent<T> {
+	Message(JobId, T),
+	/// Sent after the last message of a job.
+	Finished(JobId),
+}
+
+struct Job {
+	id: JobId,
+	cancelled: Arc<AtomicBool>,
+}
+
+/// Passed to a job running on a worker thread.
+pub struct JobContext<T> {
+	id: JobId,
+	sender: Sender<JobEvent<T>>,
+	cancelled: Arc<AtomicBool>,
+}
+
+impl<T> JobContext<T> {
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::SeqCst)
+	}
+
+	/// Sends a message to the thread polling the queue. Returns false, if the
+	/// queue no longer exists.
+	pub fn send(&self, message: T) -> bool {
+		self.sender
+			.send(JobEvent::Message(self.id, message))
+			.is_ok()
+	}
+}
+
+#[derive(Default)]
+struct Jobs {
+	last_id: JobId,
+	running: Vec<Job>,
+}
+
+/// Starts jobs of a queue. Can be cloned and handed to the parts of the
+/// application that start jobs.
+pub struct JobSpawner<T> {
+	sender: Sender<JobEvent<T>>,
+	jobs: Arc<Mutex<Jobs>>,
+}
+
+impl<T> Clone for JobSpawner<T> {
+	fn clone(&self) -> Self {
+		JobSpawner {
+			sender: self.sender.clone(),
+			jobs: Arc::clone(&self.jobs),
+		}
+	}
+}
+
+impl<T: Send + 'static> JobSpawner<T> {
+	/// Runs the job on a new worker thread.
+	pub fn spawn<F>(&self, title: &str, job: F) -> JobId
+	where
+		F: FnOnce(&JobContext<T>) + Send + 'static,
+	{
+		let cancelled = Arc::new(AtomicBool::new(false));
+		let id = {
+			let mut jobs = self.lock_jobs();
+			jobs.last_id += 1;
+			let id = jobs.last_id;
+			jobs.running.push(Job {
+				id,
+				cancelled: Arc::clone(&cancelled),
+			});
+			id
+		};
+		log::info!("Starting job {}: {}", id, title);
+		let context = JobContext {
+			id,
+			sender: self.sender.clone(),
+			cancelled,
+		};
+		let sender = self.sender.clone();
+		let result = thread::Builder::new()
+			.name(format!("job-{}", id))
+			.spawn(move || {
+				if panic::catch_unwind(AssertUnwindSafe(|| job(&context))).is_err() {
+					log::error!("Job {} panicked.", id);
+				}
+				let _ = context.sender.send(JobEvent::Finished(id));
+			});
+		if let Err(e) = result {
+			log::error!("Failed to start job {}: {}", id, e);
+			let _ = sender.send(JobEvent::Finished(id));
+		}
+		id
+	}
+}
+
+impl<T> JobSpawner<T> {
+	/// Asks a job to stop. Jobs check this themselves, so they may still send
+	/// messages for a while.
+	pub fn cancel(&self, id: JobId) {
+		if let Some(job) = self.lock_jobs().running.iter().find(|job| job.id == id) {
+			job.cancelled.store(true, Ordering::SeqCst);
+		}
+	}
+
+	fn lock_jobs(&self) -> MutexGuard<'_, Jobs> {
+		self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+/// Runs jobs on worker threads and collects their messages, to be polled on
+/// the UI thread.
+pub struct JobQueue<T> {
+	spawner: JobSpawner<T>,
+	receiver: Receiver<JobEvent<T>>,
+}
+
+impl<T: Send + 'static> JobQueue<T> {
+	pub fn new() -> Self {
+		let (sender, receiver) = mpsc::channel();
+		JobQueue {
+			spawner: JobSpawner {
+				sender,
+				jobs: Arc::new(Mutex::new(Jobs::default())),
+			},
+			receiver,
+		}
+	}
+
+	pub fn get_spawner(&self) -> JobSpawner<T> {
+		self.spawner.clone()
+	}
+
+	pub fn spawn<F>(&self, title: &str, job: F) -> JobId
+	where
+		F: FnOnce(&JobContext<T>) + Send + 'static,
+	{
+		self.spawner.spawn(title, job)
+	}
+
+	pub fn cancel(&self, id: JobId) {
+		self.spawner.cancel(id);
+	}
+
+	/// Returns false, when all jobs finished and their messages were received.
+	pub fn is_busy(&self) -> bool {
+		!self.spawner.lock_jobs().running.is_empty()
+	}
+
+	/// Returns the next message without waiting.
+	pub fn try_recv(&self) -> Option<JobEvent<T>> {
+		let event = self.receiver.try_recv().ok()?;
+		if let JobEvent::Finished(id) = event {
+			self.spawner.lock_jobs().running.retain(|job| job.id != id);
+		}
+		Some(event)
+	}
+}
+
+impl<T: Send + 'static> Default for JobQueue<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -1,4 +1,14 @@
 pub mod api;
+pub mod archive;
+pub mod compare;
+pub mod delete;
 pub mod errors;
+pub mod jobs;
 pub mod local;
+pub mod search;
 pub mod sftp;
+#[cfg(test)]
+mod test_dir;
+pub mod transfer;
+pub mod trash;
+pub mod usage;
@@ -1,27 +1,44 @@
-use crate::api::{Error, File, Icon, System, Value};
-use std::fs;
+use crate::api::{Error, File, Icon, Metadata, System, Value, WatchEvent, Watcher};
+use crate::trash;
+use filetime::FileTime;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::RecursiveMode;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct LocalSystem;
 
+/// Names of the users and groups owning files, looked up once per listing.
+#[derive(Default)]
+struct OwnerNames {
+	#[cfg(unix)]
+	users: HashMap<u32, String>,
+	#[cfg(unix)]
+	groups: HashMap<u32, String>,
+}
+
 impl LocalSystem {
 	fn get_local_file(
 		&self,
 		path: &Path,
 		name: Option<String>,
-		field_names: &Rc<Vec<String>>,
+		field_names: &Arc<Vec<String>>,
+		owner_names: &mut OwnerNames,
 	) -> Result<File, Error> {
 		let path = Path::new(path);
 		let full_path = trim_long_path_prefix(&path.to_string_lossy().into_owned()).to_owned();
-		let metadata = path.metadata();
-		let mut size = 0;
-		let mut is_dir = false;
-		if let Ok(metadata) = metadata {
-			size = metadata.len();
-			is_dir = metadata.file_type().is_dir();
+		let link_metadata = path.symlink_metadata().ok();
+		let metadata = match &link_metadata {
+			Some(link_metadata) if link_metadata.file_type().is_symlink() => path.metadata().ok(),
+			_ => link_metadata.clone(),
 		};
+		let is_dir = metadata.as_ref().map_or(false, |x| x.is_dir());
 		let (name, extension) = if let Some(name) = name {
 			(name, "".to_owned())
 		} else if is_dir {
@@ -40,30 +57,69 @@ impl LocalSystem {
 					.unwrap_or_else(|| String::from("")),
 			)
 		};
-		Ok(File::new(
-			field_names,
-			vec![
-				Value::Path {
+		let mut fields = Vec::with_capacity(field_names.len());
+		for field_name in field_names.iter() {
+			let value = match (field_name.as_str(), &metadata) {
+				("path", _) => Value::Path {
 					path: full_path.clone(),
-					name: name,
-					icon: Icon::Local(full_path),
+					name: name.clone(),
+					icon: Icon::Local(full_path.clone()),
+				},
+				("extension", _) => Value::String {
+					string: extension.clone(),
+				},
+				// Directories are sized on demand, by adding up their files.
+				("size", Some(metadata)) if !is_dir => Value::Size {
+					bytes: metadata.len(),
 				},
-				Value::String { string: extension },
-				Value::Size { bytes: size },
-			],
-		))
+				("modified_on", Some(metadata)) => metadata
+					.modified()
+					.map(|time| Value::DateTime { time })
+					.unwrap_or(Value::Empty),
+				("created_on", Some(metadata)) => metadata
+					.created()
+					.map(|time| Value::DateTime { time })
+					.unwrap_or(Value::Empty),
+				("attributes", _) => link_metadata
+					.as_ref()
+					.map(get_attributes)
+					.unwrap_or(Value::Empty),
+				("owner", Some(metadata)) => get_owner(metadata, owner_names),
+				("link_target", _) => match &link_metadata {
+					Some(link_metadata) if link_metadata.file_type().is_symlink() => {
+						fs::read_link(path)
+							.map(|target| Value::Link {
+								target: target.to_string_lossy().into_owned(),
+							})
+							.unwrap_or(Value::Empty)
+					}
+					_ => Value::Empty,
+				},
+				_ => Value::Empty,
+			};
+			fields.push(value);
+		}
+		Ok(File::new(field_names, is_dir, fields))
 	}
 }
 
 impl System for LocalSystem {
-	fn get_root(&mut self, field_names: &Rc<Vec<String>>) -> Result<File, Error> {
+	fn get_location(&mut self) -> String {
+		String::new()
+	}
+
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+		Ok(Box::new(LocalSystem::default()))
+	}
+
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
 		let root = fs::canonicalize(".").unwrap_or_else(|_err| PathBuf::from("."));
-		self.get_local_file(&root, None, field_names)
+		self.get_local_file(&root, None, field_names, &mut OwnerNames::default())
 	}
 
-	fn get_file(&mut self, path: &str, field_names: &Rc<Vec<String>>) -> Result<File, Error> {
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
 		let path = fs::canonicalize(path).unwrap_or_else(|_err| PathBuf::from(path));
-		self.get_local_file(&path, None, field_names)
+		self.get_local_file(&path, None, field_names, &mut OwnerNames::default())
 	}
 
 	fn get_filename(&mut self, path: &str) -> String {
@@ -76,9 +132,10 @@ impl System for LocalSystem {
 	fn list_files(
 		&mut self,
 		parent_directory: &File,
-		field_names: &Rc<Vec<String>>,
+		field_names: &Arc<Vec<String>>,
 	) -> Result<Vec<File>, Error> {
 		let mut files: Vec<File> = Vec::new();
+		let mut owner_names = OwnerNames::default();
 		if let Some(path_index) = parent_directory.get_field_index("path") {
 			if let Value::Path { path, .. } = &parent_directory.fields[path_index] {
 				let path = Path::new(path);
@@ -87,6 +144,7 @@ impl System for LocalSystem {
 						&parent_path,
 						Some("[..]".to_owned()),
 						field_names,
+						&mut owner_names,
 					)?);
 				}
 				match fs::read_dir(&path) {
@@ -96,6 +154,7 @@ impl System for LocalSystem {
 								&child_path?.path(),
 								None,
 								field_names,
+								&mut owner_names,
 							)?);
 						}
 					}
@@ -105,6 +164,232 @@ impl System for LocalSystem {
 		}
 		Ok(files)
 	}
+
+	fn join_path(&mut self, directory: &str, name: &str) -> String {
+		Path::new(directory)
+			.join(name)
+			.to_string_lossy()
+			.into_owned()
+	}
+
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+		Ok(read_local_metadata(Path::new(path))?)
+	}
+
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+		if let Some(modified) = metadata.modified {
+			filetime::set_file_mtime(path, FileTime::from_system_time(modified))?;
+		}
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			if let Some(permissions) = metadata.permissions {
+				fs::set_permissions(path, fs::Permissions::from_mode(permissions))?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+		let mut children = Vec::new();
+		for child in fs::read_dir(path)? {
+			let child_path = child?.path();
+			let metadata = read_local_metadata(&child_path)?;
+			children.push((child_path.to_string_lossy().into_owned(), metadata));
+		}
+		Ok(children)
+	}
+
+	fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+		Ok(fs::create_dir(path)?)
+	}
+
+	fn delete(&mut self, path: &str) -> Result<(), Error> {
+		if fs::symlink_metadata(path)?.is_dir() {
+			Ok(fs::remove_dir(path)?)
+		} else {
+			Ok(fs::remove_file(path)?)
+		}
+	}
+
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error> {
+		Ok(fs::rename(path, new_path)?)
+	}
+
+	fn read_link(&mut self, path: &str) -> Result<String, Error> {
+		Ok(fs::read_link(path)?.to_string_lossy().into_owned())
+	}
+
+	#[cfg(unix)]
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		Ok(std::os::unix::fs::symlink(target, path)?)
+	}
+
+	#[cfg(windows)]
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		// Windows links to directories differ from links to files.
+		let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+		if directory.join(target).is_dir() {
+			Ok(std::os::windows::fs::symlink_dir(target, path)?)
+		} else {
+			Ok(std::os::windows::fs::symlink_file(target, path)?)
+		}
+	}
+
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+		let mut file = fs::File::open(path)?;
+		if offset > 0 {
+			file.seek(SeekFrom::Start(offset))?;
+		}
+		Ok(Box::new(file))
+	}
+
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+		let file = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.append(append)
+			.truncate(!append)
+			.open(path)?;
+		Ok(Box::new(file))
+	}
+
+	fn watch(&mut self, path: &str, sender: Sender<WatchEvent>) -> Result<Option<Watcher>, Error> {
+		let mut watcher =
+			notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+				for event in get_watch_events(result) {
+					if sender.send(event).is_err() {
+						break;
+					}
+				}
+			})?;
+		notify::Watcher::watch(&mut watcher, Path::new(path), RecursiveMode::NonRecursive)?;
+		Ok(Some(Watcher::new(Box::new(watcher))))
+	}
+
+	fn has_trash(&mut self) -> bool {
+		trash::is_supported()
+	}
+
+	fn move_to_trash(&mut self, path: &str) -> Result<(), Error> {
+		trash::move_to_trash(path)
+	}
+}
+
+fn get_watch_events(result: notify::Result<notify::Event>) -> Vec<WatchEvent> {
+	let event = match result {
+		Ok(event) => event,
+		Err(e) => {
+			log::warn!("Failed to watch: {}", e);
+			return vec![WatchEvent::Rescan];
+		}
+	};
+	if event.need_rescan() {
+		return vec![WatchEvent::Rescan];
+	}
+	let paths: Vec<String> = event
+		.paths
+		.iter()
+		.map(|path| trim_long_path_prefix(&path.to_string_lossy()).to_owned())
+		.collect();
+	match event.kind {
+		EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+			paths.into_iter().map(WatchEvent::Changed).collect()
+		}
+		EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+			paths.into_iter().map(WatchEvent::Removed).collect()
+		}
+		EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => vec![
+			WatchEvent::Removed(paths[0].clone()),
+			WatchEvent::Changed(paths[1].clone()),
+		],
+		// The direction of other renames is not known.
+		EventKind::Modify(ModifyKind::Name(_)) => paths
+			.into_iter()
+			.map(|path| {
+				if Path::new(&path).exists() {
+					WatchEvent::Changed(path)
+				} else {
+					WatchEvent::Removed(path)
+				}
+			})
+			.collect(),
+		EventKind::Modify(_) => paths.into_iter().map(WatchEvent::Changed).collect(),
+		EventKind::Access(_) => Vec::new(),
+		EventKind::Any | EventKind::Other => vec![WatchEvent::Rescan],
+	}
+}
+
+/// Follows links and describes broken ones by the link itself.
+fn read_local_metadata(path: &Path) -> io::Result<Metadata> {
+	let link_metadata = fs::symlink_metadata(path)?;
+	if !link_metadata.file_type().is_symlink() {
+		return Ok(get_local_metadata(&link_metadata));
+	}
+	let mut metadata = match fs::metadata(path) {
+		Ok(metadata) => get_local_metadata(&metadata),
+		Err(_) => get_local_metadata(&link_metadata),
+	};
+	metadata.is_link = true;
+	Ok(metadata)
+}
+
+fn get_local_metadata(metadata: &fs::Metadata) -> Metadata {
+	#[cfg(unix)]
+	let permissions = {
+		use std::os::unix::fs::PermissionsExt;
+		Some(metadata.permissions().mode())
+	};
+	#[cfg(not(unix))]
+	let permissions = None;
+	Metadata {
+		is_dir: metadata.is_dir(),
+		size: metadata.len(),
+		modified: metadata.modified().ok(),
+		permissions,
+		is_link: false,
+	}
+}
+
+#[cfg(unix)]
+fn get_attributes(metadata: &fs::Metadata) -> Value {
+	use std::os::unix::fs::PermissionsExt;
+	Value::Permissions {
+		mode: metadata.permissions().mode(),
+	}
+}
+
+#[cfg(windows)]
+fn get_attributes(metadata: &fs::Metadata) -> Value {
+	use std::os::windows::fs::MetadataExt;
+	Value::Attributes {
+		attributes: metadata.file_attributes(),
+	}
+}
+
+#[cfg(unix)]
+fn get_owner(metadata: &fs::Metadata, owner_names: &mut OwnerNames) -> Value {
+	use std::os::unix::fs::MetadataExt;
+	let (uid, gid) = (metadata.uid(), metadata.gid());
+	let user = owner_names.users.entry(uid).or_insert_with(|| {
+		users::get_user_by_uid(uid)
+			.map(|user| user.name().to_string_lossy().into_owned())
+			.unwrap_or_else(|| uid.to_string())
+	});
+	let group = owner_names.groups.entry(gid).or_insert_with(|| {
+		users::get_group_by_gid(gid)
+			.map(|group| group.name().to_string_lossy().into_owned())
+			.unwrap_or_else(|| gid.to_string())
+	});
+	Value::Owner {
+		user: user.clone(),
+		group: group.clone(),
+	}
+}
+
+#[cfg(not(unix))]
+fn get_owner(_metadata: &fs::Metadata, _owner_names: &mut OwnerNames) -> Value {
+	Value::Empty
 }
 
 fn trim_long_path_prefix(path: &str) -> &str {
@@ -0,0 +1,547 @@
+use crate::api::{Error, File, Icon, Metadata, System, Value, WatchEvent, Watcher};
+use crate::transfer::Progress;
+use regex::bytes;
+use regex::Regex;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The path of the directory listing the results.
+pub const RESULTS_PATH: &str = "search:";
+
+const BUFFER_SIZE: usize = 64 * 1024;
+/// Bytes kept from the previous chunk, so that matches spanning two chunks
+/// are found, unless they are longer.
+const CONTENT_OVERLAP: usize = 4 * 1024;
+/// Files with a zero byte in the first chunk are binary.
+const BINARY_CHECK_SIZE: usize = 8 * 1024;
+/// How often panes showing the results are told to list them again.
+const NOTIFY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Criteria for files to find. Empty criteria match all files.
+#[derive(Default)]
+pub struct SearchQuery {
+	/// Matched against the file name.
+	pub name: Option<Regex>,
+	/// Matched against the contents of files, except binary ones.
+	pub content: Option<bytes::Regex>,
+	pub min_size: Option<u64>,
+	pub max_size: Option<u64>,
+	pub modified_after: Option<SystemTime>,
+	pub modified_before: Option<SystemTime>,
+}
+
+impl SearchQuery {
+	/// Tells, whether only files can match, as directories have no size or
+	/// content.
+	fn is_for_files(&self) -> bool {
+		self.content.is_some() || self.min_size.is_some() || self.max_size.is_some()
+	}
+
+	fn is_metadata_match(&self, metadata: &Metadata) -> bool {
+		if metadata.is_dir && self.is_for_files() {
+			return false;
+		}
+		let modified = metadata.modified.unwrap_or(SystemTime::UNIX_EPOCH);
+		self.min_size.map_or(true, |x| metadata.size >= x)
+			&& self.max_size.map_or(true, |x| metadata.size <= x)
+			&& self.modified_after.map_or(true, |x| modified >= x)
+			&& self.modified_before.map_or(true, |x| modified < x)
+	}
+}
+
+/// Files found by a search. They are added by the search job and listed by
+/// the `SearchResultsSystem`s sharing them.
+pub struct SearchResults {
+	/// The searched directory.
+	directory: String,
+	paths: Vec<String>,
+	senders: Vec<Sender<WatchEvent>>,
+	last_notified: Option<Instant>,
+}
+
+impl SearchResults {
+	pub fn new(directory: &str) -> Self {
+		SearchResults {
+			directory: directory.to_owned(),
+			paths: Vec::new(),
+			senders: Vec::new(),
+			last_notified: None,
+		}
+	}
+
+	pub fn add(&mut self, path: &str) {
+		self.paths.push(path.to_owned());
+		if self
+			.last_notified
+			.map_or(true, |x| x.elapsed() >= NOTIFY_INTERVAL)
+		{
+			self.notify();
+		}
+	}
+
+	/// Tells the watching panes to list the results again.
+	pub fn notify(&mut self) {
+		self.last_notified = Some(Instant::now());
+		self.senders
+			.retain(|sender| sender.send(WatchEvent::Rescan).is_ok());
+	}
+}
+
+pub fn lock_results(results: &Mutex<SearchResults>) -> MutexGuard<'_, SearchResults> {
+	results.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Walks the directory tree and calls `found` with the paths of matching
+/// files. Directories, which cannot be read, are skipped. `report` is called
+/// with the progress and stops searching, if it returns false.
+pub fn search(
+	system: &mut dyn System,
+	directory: &str,
+	query: &SearchQuery,
+	found: &mut dyn FnMut(&str),
+	report: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<(), Error> {
+	let mut progress = Progress::default();
+	let mut directories = vec![directory.to_owned()];
+	while let Some(directory) = directories.pop() {
+		let children = match system.read_dir(&directory) {
+			Ok(children) => children,
+			Err(e) => {
+				log::warn!("Failed to search {}: {}", directory, e);
+				continue;
+			}
+		};
+		for (path, metadata) in children {
+			progress.current_path = path.clone();
+			if !report(&progress) {
+				return Err(cancelled_error());
+			}
+			// Links to directories are not followed, as they may form loops.
+			if metadata.is_dir && !metadata.is_link {
+				directories.push(path.clone());
+			} else {
+				progress.total_files += 1;
+			}
+			let is_name_match = query
+				.name
+				.as_ref()
+				.map_or(true, |name| name.is_match(&system.get_filename(&path)));
+			if !is_name_match || !query.is_metadata_match(&metadata) {
+				continue;
+			}
+			if let Some(content) = &query.content {
+				match has_content(system, &path, content, &mut progress, report) {
+					Ok(true) => {}
+					Ok(false) => continue,
+					Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+						return Err(cancelled_error());
+					}
+					Err(e) => {
+						log::warn!("Failed to search {}: {}", path, e);
+						continue;
+					}
+				}
+			}
+			progress.done_files += 1;
+			found(&path);
+		}
+	}
+	Ok(())
+}
+
+fn has_content(
+	system: &mut dyn System,
+	path: &str,
+	content: &bytes::Regex,
+	progress: &mut Progress,
+	report: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<bool, Error> {
+	let mut reader = system.open_read(path, 0)?;
+	let mut buffer = vec![0; BUFFER_SIZE];
+	let mut text: Vec<u8> = Vec::new();
+	let mut is_first_chunk = true;
+	loop {
+		let length = reader.read(&mut buffer)?;
+		if length == 0 {
+			return Ok(false);
+		}
+		let chunk = &buffer[..length];
+		if is_first_chunk && chunk.iter().take(BINARY_CHECK_SIZE).any(|x| *x == 0) {
+			return Ok(false);
+		}
+		is_first_chunk = false;
+		let overlap = text.len().min(CONTENT_OVERLAP);
+		text.drain(..text.len() - overlap);
+		text.extend_from_slice(chunk);
+		if content.is_match(&text) {
+			return Ok(true);
+		}
+		progress.done_bytes += length as u64;
+		if !report(progress) {
+			return Err(cancelled_error());
+		}
+	}
+}
+
+/// Lists the results of a search as the directory `RESULTS_PATH`, which is
+/// also the root. The results are named by their path relative to the
+/// searched directory. Other paths are passed to the searched system, so
+/// that the results can be copied, renamed and entered as usual.
+pub struct SearchResultsSystem {
+	system: Box<dyn System>,
+	results: Arc<Mutex<SearchResults>>,
+}
+
+impl SearchResultsSystem {
+	pub fn new(system: Box<dyn System>, results: Arc<Mutex<SearchResults>>) -> Self {
+		SearchResultsSystem { system, results }
+	}
+
+	fn get_results_directory(&self, field_names: &Arc<Vec<String>>) -> File {
+		let fields = field_names
+			.iter()
+			.map(|field_name| match field_name.as_str() {
+				"path" => Value::Path {
+					path: RESULTS_PATH.to_owned(),
+					name: "[Search results]".to_owned(),
+					icon: Icon::Shell("C:\\.".to_owned()),
+				},
+				_ => Value::Empty,
+			})
+			.collect();
+		File::new(field_names, true, fields)
+	}
+
+	/// Returns the file of a result, named by its relative path.
+	fn get_result_file(
+		&mut self,
+		path: &str,
+		directory: &str,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<File, Error> {
+		let mut file = self.system.get_file(path, field_names)?;
+		let filename = self.system.get_filename(path);
+		let relative_path = path
+			.strip_prefix(directory)
+			.unwrap_or(path)
+			.trim_start_matches(['/', '\\']);
+		let relative_dir = &relative_path[..relative_path.len().saturating_sub(filename.len())];
+		if let Some(path_index) = file.get_field_index("path") {
+			if let Value::Path { name, .. } = &mut file.fields[path_index] {
+				*name = if file.is_dir {
+					format!("[{}]", relative_path)
+				} else {
+					format!("{}{}", relative_dir, name)
+				};
+			}
+		}
+		Ok(file)
+	}
+}
+
+impl System for SearchResultsSystem {
+	fn get_location(&mut self) -> String {
+		self.system.get_location()
+	}
+
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+		Ok(Box::new(SearchResultsSystem::new(
+			self.system.duplicate()?,
+			Arc::clone(&self.results),
+		)))
+	}
+
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		Ok(self.get_results_directory(field_names))
+	}
+
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		if path == RESULTS_PATH {
+			Ok(self.get_results_directory(field_names))
+		} else {
+			self.system.get_file(path, field_names)
+		}
+	}
+
+	fn get_filename(&mut self, path: &str) -> String {
+		if path == RESULTS_PATH {
+			"Search results".to_owned()
+		} else {
+			self.system.get_filename(path)
+		}
+	}
+
+	fn list_files(
+		&mut self,
+		parent_directory: &File,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<Vec<File>, Error> {
+		let path_index = parent_directory.get_field_index("path");
+		let is_results = match path_index.map(|x| &parent_directory.fields[x]) {
+			Some(Value::Path { path, .. }) => path == RESULTS_PATH,
+			_ => false,
+		};
+		if !is_results {
+			return self.system.list_files(parent_directory, field_names);
+		}
+		let (directory, paths) = {
+			let results = lock_results(&self.results);
+			(results.directory.clone(), results.paths.clone())
+		};
+		let mut files = Vec::with_capacity(paths.len() + 1);
+		// The parent item leaves the results for the searched directory.
+		let mut parent = self.system.get_file(&directory, field_names)?;
+		if let Some(path_index) = parent.get_field_index("path") {
+			if let Value::Path { name, .. } = &mut parent.fields[path_index] {
+				*name = "[..]".to_owned();
+			}
+		}
+		files.push(parent);
+		for path in paths {
+			match self.get_result_file(&path, &directory, field_names) {
+				Ok(file) => files.push(file),
+				// The file may have been removed since.
+				Err(e) => log::info!("Failed to list {}: {}", path, e),
+			}
+		}
+		Ok(files)
+	}
+
+	fn join_path(&mut self, directory: &str, name: &str) -> String {
+		self.system.join_path(directory, name)
+	}
+
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+		if path == RESULTS_PATH {
+			Ok(Metadata {
+				is_dir: true,
+				..Metadata::default()
+			})
+		} else {
+			check_path(path)?;
+			self.system.get_metadata(path)
+		}
+	}
+
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.set_metadata(path, metadata)
+	}
+
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+		if path != RESULTS_PATH {
+			check_path(path)?;
+			return self.system.read_dir(path);
+		}
+		let paths = lock_results(&self.results).paths.clone();
+		Ok(paths
+			.into_iter()
+			.filter_map(|path| {
+				let metadata = self.system.get_metadata(&path).ok()?;
+				Some((path, metadata))
+			})
+			.collect())
+	}
+
+	fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.create_directory(path)
+	}
+
+	fn delete(&mut self, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.delete(path)?;
+		lock_results(&self.results).paths.retain(|x| x != path);
+		Ok(())
+	}
+
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		check_path(new_path)?;
+		self.system.rename(path, new_path)?;
+		for result in lock_results(&self.results).paths.iter_mut() {
+			if result == path {
+				*result = new_path.to_owned();
+			}
+		}
+		Ok(())
+	}
+
+	fn read_link(&mut self, path: &str) -> Result<String, Error> {
+		check_path(path)?;
+		self.system.read_link(path)
+	}
+
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.create_link(target, path)
+	}
+
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+		check_path(path)?;
+		self.system.open_read(path, offset)
+	}
+
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+		check_path(path)?;
+		self.system.open_write(path, append)
+	}
+
+	fn watch(&mut self, path: &str, sender: Sender<WatchEvent>) -> Result<Option<Watcher>, Error> {
+		if path == RESULTS_PATH {
+			lock_results(&self.results).senders.push(sender);
+			Ok(Some(Watcher::new(Box::new(()))))
+		} else {
+			self.system.watch(path, sender)
+		}
+	}
+
+	fn has_trash(&mut self) -> bool {
+		self.system.has_trash()
+	}
+
+	fn move_to_trash(&mut self, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.move_to_trash(path)?;
+		lock_results(&self.results).paths.retain(|x| x != path);
+		Ok(())
+	}
+}
+
+/// Fails for paths in the results directory, which only lists files.
+fn check_path(path: &str) -> Result<(), Error> {
+	if path.starts_with(RESULTS_PATH) {
+		Err(Error::IoError(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"Search results cannot be changed.",
+		)))
+	} else {
+		Ok(())
+	}
+}
+
+fn cancelled_error() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::Interrupted, "Cancelled."))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use std::fs;
+
+	fn file(size: u64, seconds: u64) -> Metadata {
+		Metadata {
+			size,
+			modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)),
+			..Metadata::default()
+		}
+	}
+
+	#[test]
+	fn size_and_date_bounds_are_inclusive_and_exclusive() {
+		let query = SearchQuery {
+			min_size: Some(10),
+			max_size: Some(20),
+			modified_after: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(100)),
+			modified_before: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(200)),
+			..SearchQuery::default()
+		};
+		assert!(query.is_metadata_match(&file(10, 100)));
+		assert!(query.is_metadata_match(&file(20, 199)));
+		assert!(!query.is_metadata_match(&file(9, 150)));
+		assert!(!query.is_metadata_match(&file(21, 150)));
+		assert!(!query.is_metadata_match(&file(15, 99)));
+		assert!(!query.is_metadata_match(&file(15, 200)));
+	}
+
+	#[test]
+	fn directories_only_match_criteria_without_size_or_content() {
+		let directory = Metadata {
+			is_dir: true,
+			..Metadata::default()
+		};
+		assert!(SearchQuery::default().is_metadata_match(&directory));
+		let query = SearchQuery {
+			max_size: Some(100),
+			..SearchQuery::default()
+		};
+		assert!(!query.is_metadata_match(&directory));
+		let query = SearchQuery {
+			content: Some(bytes::Regex::new("text").unwrap()),
+			..SearchQuery::default()
+		};
+		assert!(!query.is_metadata_match(&directory));
+	}
+
+	#[test]
+	fn files_are_found_by_name_and_content_in_subdirectories() {
+		let dir = TestDir::new();
+		fs::create_dir(dir.path().join("sub")).unwrap();
+		fs::write(dir.path().join("a.txt"), "some text").unwrap();
+		fs::write(dir.path().join("sub").join("b.txt"), "other").unwrap();
+		fs::write(dir.path().join("sub").join("c.bin"), b"text\0").unwrap();
+		fs::write(dir.path().join("sub").join("d.rs"), "text").unwrap();
+		#[cfg(unix)]
+		std::os::unix::fs::symlink("..", dir.path().join("sub").join("loop")).unwrap();
+
+		let search_files = |query: &SearchQuery| {
+			let mut found = Vec::new();
+			search(
+				&mut LocalSystem::default(),
+				dir.path().to_str().unwrap(),
+				query,
+				&mut |path| found.push(path.to_owned()),
+				&mut |_progress| true,
+			)
+			.unwrap();
+			found.sort();
+			found
+		};
+		let query = SearchQuery {
+			name: Some(Regex::new(r"\.txt$").unwrap()),
+			..SearchQuery::default()
+		};
+		assert_eq!(
+			search_files(&query),
+			[dir.join("a.txt"), dir.join("sub/b.txt")]
+		);
+		let query = SearchQuery {
+			content: Some(bytes::Regex::new("text").unwrap()),
+			..SearchQuery::default()
+		};
+		assert_eq!(
+			search_files(&query),
+			[dir.join("a.txt"), dir.join("sub/d.rs")]
+		);
+	}
+
+	#[test]
+	fn listed_results_are_up_to_date() {
+		let dir = TestDir::new();
+		fs::write(dir.path().join("a.txt"), "text").unwrap();
+		let results = Arc::new(Mutex::new(SearchResults::new(dir.path().to_str().unwrap())));
+		lock_results(&results).add(&dir.join("a.txt"));
+		let mut system =
+			SearchResultsSystem::new(Box::new(LocalSystem::default()), Arc::clone(&results));
+		let field_names = Arc::new(vec!["path".to_owned(), "size".to_owned()]);
+		let mut get_sizes = || {
+			let root = system.get_root(&field_names).unwrap();
+			let files = system.list_files(&root, &field_names).unwrap();
+			files[1..]
+				.iter()
+				.map(|file| match file.fields[1] {
+					Value::Size { bytes } => bytes,
+					_ => panic!("No size"),
+				})
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(get_sizes(), [4]);
+
+		fs::write(dir.path().join("a.txt"), "more text").unwrap();
+		assert_eq!(get_sizes(), [9]);
+	}
+}
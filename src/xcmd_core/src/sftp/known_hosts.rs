@@ -0,0 +1,235 @@
+use crate::api::Error;
+use sha2::{Digest, Sha256};
+use ssh2::{CheckResult, HostKeyType, KnownHostFileKind, KnownHosts, Session};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostKeyStatus {
+	Unknown,
+	Changed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostKeyDecision {
+	Reject,
+	AcceptOnce,
+	AcceptAndSave,
+}
+
+pub struct HostKey {
+	pub host: String,
+	pub key_type: String,
+	pub fingerprint: String,
+	pub status: HostKeyStatus,
+}
+
+pub struct KnownHostsFiles {
+	/// Host keys accepted in xcmd. New keys are only ever written here, or
+	/// only accepted once, if there is no such file.
+	pub store: Option<PathBuf>,
+	/// OpenSSH known hosts file, only read.
+	pub openssh: Option<PathBuf>,
+}
+
+impl KnownHostsFiles {
+	pub fn new(store: Option<PathBuf>) -> Self {
+		KnownHostsFiles {
+			store,
+			openssh: dirs::home_dir().map(|home_dir| home_dir.join(".ssh").join("known_hosts")),
+		}
+	}
+
+	/// Checks the host key of a session that completed its handshake against
+	/// the known hosts files, asking `confirm` about unknown or changed keys.
+	pub fn verify(
+		&self,
+		session: &Session,
+		host: &str,
+		port: u16,
+		confirm: &mut dyn FnMut(&HostKey) -> HostKeyDecision,
+	) -> Result<(), Error> {
+		let (key, key_type) = session
+			.host_key()
+			.ok_or_else(|| Error::ConfigError(format!("{} sent no host key.", host)))?;
+
+		let mut status = HostKeyStatus::Unknown;
+		let files = vec![self.store.as_ref(), self.openssh.as_ref()];
+		for file in files.into_iter().flatten() {
+			let known_hosts = read_known_hosts(session, file)?;
+			match known_hosts.check_port(host, port, key) {
+				CheckResult::Match => return Ok(()),
+				CheckResult::Mismatch => status = HostKeyStatus::Changed,
+				CheckResult::NotFound => {}
+				CheckResult::Failure => log::error!("Failed to check host key in {:?}.", file),
+			}
+		}
+
+		let host_key = HostKey {
+			host: host_entry_name(host, port),
+			key_type: get_key_type_name(key_type).to_owned(),
+			fingerprint: get_fingerprint(key),
+			status,
+		};
+		log::info!(
+			"Host key of {} is {:?}: {} {}",
+			host_key.host,
+			host_key.status,
+			host_key.key_type,
+			host_key.fingerprint
+		);
+		match confirm(&host_key) {
+			HostKeyDecision::Reject => match status {
+				HostKeyStatus::Changed => Err(Error::HostKeyMismatch {
+					host: host_key.host,
+					fingerprint: host_key.fingerprint,
+				}),
+				HostKeyStatus::Unknown => Err(Error::HostKeyRejected {
+					host: host_key.host,
+					fingerprint: host_key.fingerprint,
+				}),
+			},
+			HostKeyDecision::AcceptOnce => Ok(()),
+			HostKeyDecision::AcceptAndSave => match &self.store {
+				Some(store) => save(store, session, &host_key.host, key, key_type),
+				None => {
+					log::warn!(
+						"No file to save the host key of {} to, accepting it once.",
+						host_key.host
+					);
+					Ok(())
+				}
+			},
+		}
+	}
+}
+
+fn save(
+	store: &Path,
+	session: &Session,
+	host_entry: &str,
+	key: &[u8],
+	key_type: HostKeyType,
+) -> Result<(), Error> {
+	let mut known_hosts = read_known_hosts(session, store)?;
+	for known_host in known_hosts.iter()? {
+		if known_host.name() == Some(host_entry) {
+			known_hosts.remove(&known_host)?;
+		}
+	}
+	known_hosts.add(host_entry, key, "", key_type.into())?;
+	if let Some(parent) = store.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	known_hosts.write_file(store, KnownHostFileKind::OpenSSH)?;
+	log::info!("Saved host key of {} to {:?}.", host_entry, store);
+	Ok(())
+}
+
+fn read_known_hosts(session: &Session, file: &Path) -> Result<KnownHosts, Error> {
+	let mut known_hosts = session.known_hosts()?;
+	if file.exists() {
+		known_hosts.read_file(file, KnownHostFileKind::OpenSSH)?;
+	}
+	Ok(known_hosts)
+}
+
+fn host_entry_name(host: &str, port: u16) -> String {
+	if port == 22 {
+		host.to_owned()
+	} else {
+		format!("[{}]:{}", host, port)
+	}
+}
+
+fn get_key_type_name(key_type: HostKeyType) -> &'static str {
+	match key_type {
+		HostKeyType::Rsa => "ssh-rsa",
+		HostKeyType::Dss => "ssh-dss",
+		HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+		HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+		HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+		HostKeyType::Ed25519 => "ssh-ed25519",
+		HostKeyType::Unknown => "unknown",
+	}
+}
+
+/// Formats the key the way `ssh-keygen -l` does, e.g. `SHA256:nThbg6kXUpJW...`.
+fn get_fingerprint(key: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let hash = Sha256::digest(key);
+	let mut fingerprint = String::from("SHA256:");
+	for chunk in hash.chunks(3) {
+		let bytes = [
+			chunk[0],
+			*chunk.get(1).unwrap_or(&0),
+			*chunk.get(2).unwrap_or(&0),
+		];
+		let indices = [
+			bytes[0] >> 2,
+			(bytes[0] & 0x03) << 4 | bytes[1] >> 4,
+			(bytes[1] & 0x0f) << 2 | bytes[2] >> 6,
+			bytes[2] & 0x3f,
+		];
+		for index in &indices[..=chunk.len()] {
+			fingerprint.push(ALPHABET[*index as usize] as char);
+		}
+	}
+	fingerprint
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fingerprint_is_unpadded_base64_of_sha256() {
+		assert_eq!(
+			get_fingerprint(b""),
+			"SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU"
+		);
+		assert_eq!(
+			get_fingerprint(b"ssh-ed25519 test key"),
+			"SHA256:rNQiu1u0JDCNa9KtjQy3XH/8zGkR+GTgatoN3dPKg88"
+		);
+	}
+
+	#[test]
+	fn host_entry_name_includes_port_other_than_22() {
+		assert_eq!(host_entry_name("example.com", 22), "example.com");
+		assert_eq!(host_entry_name("example.com", 2222), "[example.com]:2222");
+	}
+
+	#[test]
+	fn saving_a_key_replaces_the_previous_one() {
+		let store = std::env::temp_dir().join(format!("xcmd-known-hosts-{}", std::process::id()));
+		let _ = fs::remove_file(&store);
+		let session = Session::new().unwrap();
+		let old_key = b"old key";
+		let new_key = b"new key";
+		save(
+			&store,
+			&session,
+			"[example.com]:2222",
+			old_key,
+			HostKeyType::Ed25519,
+		)
+		.unwrap();
+		save(
+			&store,
+			&session,
+			"[example.com]:2222",
+			new_key,
+			HostKeyType::Ed25519,
+		)
+		.unwrap();
+		let known_hosts = read_known_hosts(&session, &store).unwrap();
+		let result = known_hosts.check_port("example.com", 2222, new_key);
+		assert!(matches!(result, CheckResult::Match));
+		let result = known_hosts.check_port("example.com", 2222, old_key);
+		assert!(matches!(result, CheckResult::Mismatch));
+		let result = known_hosts.check_port("example.com", 22, new_key);
+		assert!(matches!(result, CheckResult::NotFound));
+		fs::remove_file(&store).unwrap();
+	}
+}
@@ -1,25 +1,71 @@
-use crate::api::{Error, File, Icon, System, Value};
-use ssh2::{FileStat, Session, Sftp};
+mod known_hosts;
+mod profile;
+
+pub use self::known_hosts::{HostKey, HostKeyDecision, HostKeyStatus, KnownHostsFiles};
+pub use self::profile::{expand_home, Authentication, ConnectionProfile};
+
+use crate::api::{Error, File, Icon, Metadata, System, Value, WatchEvent, Watcher};
+use ssh2::{FileStat, OpenFlags, OpenType, Session, Sftp};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::net::TcpStream;
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
 pub struct SftpSystem {
+	profile: ConnectionProfile,
+	session: Session,
 	sftp: Sftp,
 }
 
 impl SftpSystem {
-	pub fn new() -> SftpSystem {
-		let mut session = Session::new().unwrap();
-		let tcp = TcpStream::connect("hostname:port").unwrap();
+	pub fn connect(
+		profile: &ConnectionProfile,
+		known_hosts: &KnownHostsFiles,
+		confirm_host_key: &mut dyn FnMut(&HostKey) -> HostKeyDecision,
+	) -> Result<SftpSystem, Error> {
+		log::info!("Connecting to {}.", profile);
+		let tcp = TcpStream::connect((profile.host.as_str(), profile.port))?;
+		let mut session = Session::new()?;
 		session.set_tcp_stream(tcp);
-		session.handshake().unwrap();
-		session.userauth_password("username", "password").unwrap();
-		assert!(session.authenticated());
+		session.handshake()?;
+		known_hosts.verify(&session, &profile.host, profile.port, confirm_host_key)?;
 
-		SftpSystem {
-			sftp: session.sftp().unwrap(),
+		let user = profile.get_user();
+		match &profile.auth {
+			Authentication::Password { password } => {
+				session.userauth_password(&user, password.as_deref().unwrap_or(""))?;
+			}
+			Authentication::PrivateKey {
+				private_key,
+				public_key,
+				passphrase,
+			} => {
+				let public_key = public_key.as_ref().map(|x| expand_home(x));
+				session.userauth_pubkey_file(
+					&user,
+					public_key.as_deref(),
+					&expand_home(private_key),
+					passphrase.as_deref(),
+				)?;
+			}
+			Authentication::Agent => session.userauth_agent(&user)?,
 		}
+		if !session.authenticated() {
+			return Err(Error::AuthenticationError(profile.to_string()));
+		}
+		log::info!("Connected to {}.", profile);
+
+		Ok(SftpSystem {
+			profile: profile.clone(),
+			sftp: session.sftp()?,
+			session,
+		})
+	}
+
+	pub fn get_profile(&self) -> &ConnectionProfile {
+		&self.profile
 	}
 
 	fn get_sftp_file(
@@ -27,7 +73,7 @@ impl SftpSystem {
 		path: &Path,
 		name: Option<String>,
 		stat: Option<FileStat>,
-		field_names: &Rc<Vec<String>>,
+		field_names: &Arc<Vec<String>>,
 	) -> Result<File, Error> {
 		let path = Path::new(path);
 		let full_path = path.to_string_lossy().into_owned();
@@ -35,16 +81,16 @@ impl SftpSystem {
 			.file_name()
 			.map(|x| x.to_string_lossy().into_owned())
 			.unwrap_or_else(|| String::from(".."));
-		let stat = if let Some(stat) = stat {
-			Ok(stat)
+		let link_stat = stat.or_else(|| self.sftp.lstat(path).ok());
+		let is_link = link_stat
+			.as_ref()
+			.map_or(false, |x| x.file_type().is_symlink());
+		let stat = if is_link {
+			self.sftp.stat(path).ok()
 		} else {
-			self.sftp.stat(path)
-		};
-		let is_dir = if let Ok(stat) = &stat {
-			stat.is_dir()
-		} else {
-			false
+			link_stat.clone()
 		};
+		let is_dir = stat.as_ref().map_or(false, |x| x.is_dir());
 		let (name, extension) = if let Some(name) = name {
 			(name, "".to_owned())
 		} else if is_dir {
@@ -59,23 +105,57 @@ impl SftpSystem {
 					.unwrap_or_else(|| String::from("")),
 			)
 		};
-		let size = if let Ok(stat) = &stat {
-			stat.size.unwrap()
-		} else {
-			0
-		};
-		Ok(File::new(
-			field_names,
-			vec![
-				Value::Path {
-					path: full_path,
-					name: name,
+		let mut fields = Vec::with_capacity(field_names.len());
+		for field_name in field_names.iter() {
+			let value = match (field_name.as_str(), &stat) {
+				("path", _) => Value::Path {
+					path: full_path.clone(),
+					name: name.clone(),
 					icon: self.get_icon(is_dir, &extension),
 				},
-				Value::String { string: extension },
-				Value::Size { bytes: size },
-			],
-		))
+				("extension", _) => Value::String {
+					string: extension.clone(),
+				},
+				("size", Some(stat)) if !is_dir => Value::Size {
+					bytes: stat.size.unwrap_or(0),
+				},
+				(
+					"modified_on",
+					Some(FileStat {
+						mtime: Some(mtime), ..
+					}),
+				) => Value::DateTime {
+					time: UNIX_EPOCH + Duration::from_secs(*mtime),
+				},
+				("attributes", _) => match &link_stat {
+					Some(FileStat {
+						perm: Some(perm), ..
+					}) => Value::Permissions { mode: *perm },
+					_ => Value::Empty,
+				},
+				(
+					"owner",
+					Some(FileStat {
+						uid: Some(uid),
+						gid: Some(gid),
+						..
+					}),
+				) => Value::Owner {
+					user: uid.to_string(),
+					group: gid.to_string(),
+				},
+				("link_target", _) if is_link => self
+					.sftp
+					.readlink(path)
+					.map(|target| Value::Link {
+						target: target.to_string_lossy().into_owned(),
+					})
+					.unwrap_or(Value::Empty),
+				_ => Value::Empty,
+			};
+			fields.push(value);
+		}
+		Ok(File::new(field_names, is_dir, fields))
 	}
 
 	fn get_icon(&self, is_dir: bool, ext: &str) -> Icon {
@@ -86,14 +166,47 @@ impl SftpSystem {
 		};
 		Icon::Shell(file)
 	}
+
+	/// Describes the target of a link, or the link itself, if it is broken.
+	fn follow_link(&self, path: &Path, stat: &FileStat) -> Metadata {
+		if !stat.file_type().is_symlink() {
+			return get_sftp_metadata(stat);
+		}
+		let mut metadata = match self.sftp.stat(path) {
+			Ok(target) => get_sftp_metadata(&target),
+			Err(_) => get_sftp_metadata(stat),
+		};
+		metadata.is_link = true;
+		metadata
+	}
 }
 
 impl System for SftpSystem {
-	fn get_root(&mut self, field_names: &Rc<Vec<String>>) -> Result<File, Error> {
-		self.get_file("/", field_names)
+	fn get_location(&mut self) -> String {
+		self.profile.get_url()
+	}
+
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+		Ok(Box::new(SftpSystem {
+			profile: self.profile.clone(),
+			sftp: self.session.sftp()?,
+			session: self.session.clone(),
+		}))
 	}
 
-	fn get_file(&mut self, path: &str, field_names: &Rc<Vec<String>>) -> Result<File, Error> {
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		let root = if let Some(directory) = &self.profile.directory {
+			directory.to_owned()
+		} else {
+			self.sftp
+				.realpath(Path::new("."))
+				.map(|x| x.to_string_lossy().into_owned())
+				.unwrap_or_else(|_err| String::from("/"))
+		};
+		self.get_file(&root, field_names)
+	}
+
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
 		self.get_sftp_file(Path::new(path), None, None, field_names)
 	}
 
@@ -107,7 +220,7 @@ impl System for SftpSystem {
 	fn list_files(
 		&mut self,
 		parent_directory: &File,
-		field_names: &Rc<Vec<String>>,
+		field_names: &Arc<Vec<String>>,
 	) -> Result<Vec<File>, Error> {
 		let mut files: Vec<File> = Vec::new();
 		if let Some(path_index) = parent_directory.get_field_index("path") {
@@ -139,4 +252,118 @@ impl System for SftpSystem {
 		}
 		Ok(files)
 	}
+
+	fn join_path(&mut self, directory: &str, name: &str) -> String {
+		format!("{}/{}", directory.trim_end_matches('/'), name)
+	}
+
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+		let path = Path::new(path);
+		Ok(self.follow_link(path, &self.sftp.lstat(path)?))
+	}
+
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+		let time = metadata
+			.modified
+			.and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+			.map(|x| x.as_secs());
+		self.sftp.setstat(
+			Path::new(path),
+			FileStat {
+				size: None,
+				uid: None,
+				gid: None,
+				perm: metadata.permissions,
+				atime: time,
+				mtime: time,
+			},
+		)?;
+		Ok(())
+	}
+
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+		Ok(self
+			.sftp
+			.readdir(Path::new(path))?
+			.iter()
+			.map(|(child_path, stat)| {
+				(
+					child_path.to_string_lossy().into_owned(),
+					self.follow_link(child_path, stat),
+				)
+			})
+			.collect())
+	}
+
+	fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+		Ok(self.sftp.mkdir(Path::new(path), 0o755)?)
+	}
+
+	fn delete(&mut self, path: &str) -> Result<(), Error> {
+		if self.sftp.lstat(Path::new(path))?.is_dir() {
+			Ok(self.sftp.rmdir(Path::new(path))?)
+		} else {
+			Ok(self.sftp.unlink(Path::new(path))?)
+		}
+	}
+
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error> {
+		Ok(self
+			.sftp
+			.rename(Path::new(path), Path::new(new_path), None)?)
+	}
+
+	fn read_link(&mut self, path: &str) -> Result<String, Error> {
+		Ok(self
+			.sftp
+			.readlink(Path::new(path))?
+			.to_string_lossy()
+			.into_owned())
+	}
+
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		Ok(self.sftp.symlink(Path::new(target), Path::new(path))?)
+	}
+
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+		let mut file = self.sftp.open(Path::new(path))?;
+		if offset > 0 {
+			file.seek(SeekFrom::Start(offset))?;
+		}
+		Ok(Box::new(file))
+	}
+
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+		let flags = if append {
+			OpenFlags::WRITE | OpenFlags::CREATE
+		} else {
+			OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+		};
+		let mut file = self
+			.sftp
+			.open_mode(Path::new(path), flags, 0o644, OpenType::File)?;
+		if append {
+			file.seek(SeekFrom::End(0))?;
+		}
+		Ok(Box::new(file))
+	}
+
+	/// SFTP has no change notifications.
+	fn watch(
+		&mut self,
+		_path: &str,
+		_sender: Sender<WatchEvent>,
+	) -> Result<Option<Watcher>, Error> {
+		Ok(None)
+	}
+}
+
+fn get_sftp_metadata(stat: &FileStat) -> Metadata {
+	Metadata {
+		is_dir: stat.is_dir(),
+		size: stat.size.unwrap_or(0),
+		modified: stat.mtime.map(|x| UNIX_EPOCH + Duration::from_secs(x)),
+		permissions: stat.perm,
+		is_link: false,
+	}
 }
@@ -0,0 +1,135 @@
+use crate::api::Error;
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, serde_derive::Deserialize)]
+#[serde(tag = "method", rename_all = "kebab-case")]
+pub enum Authentication {
+	Password {
+		password: Option<String>,
+	},
+	PrivateKey {
+		private_key: PathBuf,
+		public_key: Option<PathBuf>,
+		passphrase: Option<String>,
+	},
+	Agent,
+}
+
+impl Default for Authentication {
+	fn default() -> Self {
+		Authentication::Agent
+	}
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize)]
+pub struct ConnectionProfile {
+	pub name: String,
+	pub host: String,
+	#[serde(default = "default_port")]
+	pub port: u16,
+	pub user: Option<String>,
+	pub directory: Option<String>,
+	#[serde(default)]
+	pub auth: Authentication,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct ConnectionProfiles {
+	#[serde(default)]
+	connection: Vec<ConnectionProfile>,
+}
+
+fn default_port() -> u16 {
+	22
+}
+
+impl ConnectionProfile {
+	pub fn parse_toml(toml: &str) -> Result<Vec<ConnectionProfile>, Error> {
+		Ok(toml::from_str::<ConnectionProfiles>(toml)?.connection)
+	}
+
+	/// Parses `sftp://[user@]host[:port][/path]`. The profile is named by its
+	/// URL without the path, which is returned separately.
+	pub fn parse_url(url: &str) -> Option<(ConnectionProfile, Option<String>)> {
+		if !url.starts_with("sftp://") {
+			return None;
+		}
+		let rest = &url["sftp://".len()..];
+		let (authority, path) = match rest.find('/') {
+			Some(index) => (&rest[..index], Some(rest[index..].to_owned())),
+			None => (rest, None),
+		};
+		let (user, host_port) = match authority.rfind('@') {
+			Some(index) => (Some(authority[..index].to_owned()), &authority[index + 1..]),
+			None => (None, authority),
+		};
+		let (host, port) = match host_port.rfind(':') {
+			Some(index) => (&host_port[..index], host_port[index + 1..].parse().ok()?),
+			None => (host_port, default_port()),
+		};
+		if host.is_empty() {
+			return None;
+		}
+		let mut profile = ConnectionProfile {
+			name: String::new(),
+			host: host.to_owned(),
+			port,
+			user,
+			directory: None,
+			auth: Authentication::default(),
+		};
+		profile.name = profile.get_url();
+		Some((profile, path))
+	}
+
+	/// Returns `sftp://user@host:port`, the location of the profile's files.
+	pub fn get_url(&self) -> String {
+		format!("sftp://{}@{}:{}", self.get_user(), self.host, self.port)
+	}
+
+	/// Tells, whether the profile connects to the same account as the other.
+	pub fn is_same_account(&self, other: &ConnectionProfile) -> bool {
+		self.host == other.host && self.port == other.port && self.get_user() == other.get_user()
+	}
+
+	pub fn get_user(&self) -> String {
+		self.user
+			.clone()
+			.or_else(|| env::var("USER").ok())
+			.or_else(|| env::var("USERNAME").ok())
+			.unwrap_or_default()
+	}
+
+	pub fn needs_password(&self) -> bool {
+		match &self.auth {
+			Authentication::Password { password } => password.is_none(),
+			_ => false,
+		}
+	}
+
+	pub fn set_password(&mut self, new_password: String) {
+		if let Authentication::Password { password } = &mut self.auth {
+			*password = Some(new_password);
+		}
+	}
+}
+
+impl std::fmt::Display for ConnectionProfile {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if self.port == default_port() {
+			write!(f, "{}@{}", self.get_user(), self.host)
+		} else {
+			write!(f, "{}@{}:{}", self.get_user(), self.host, self.port)
+		}
+	}
+}
+
+pub fn expand_home(path: &Path) -> PathBuf {
+	if let Ok(relative_path) = path.strip_prefix("~") {
+		if let Some(home_dir) = dirs::home_dir() {
+			return home_dir.join(relative_path);
+		}
+	}
+	path.to_owned()
+}
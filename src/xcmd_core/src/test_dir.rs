@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory for a test, removed with its contents when dropped.
+pub struct TestDir {
+	path: PathBuf,
+}
+
+impl TestDir {
+	pub fn new() -> Self {
+		let path = std::env::temp_dir().join(format!(
+			"xcmd-test-{}-{}",
+			process::id(),
+			COUNTER.fetch_add(1, Ordering::SeqCst)
+		));
+		fs::create_dir_all(&path).unwrap();
+		TestDir { path }
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Returns the path of the name in the directory as a string.
+	pub fn join(&self, name: &str) -> String {
+		self.path.join(name).to_string_lossy().into_owned()
+	}
+}
+
+impl Drop for TestDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
@@ -0,0 +1,933 @@
+use crate::api::{Error, Metadata, System};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+const STEP_DURATION: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferMode {
+	Copy,
+	Move,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConflictResolution {
+	Overwrite,
+	Skip,
+	Rename(String),
+	Resume,
+	Cancel,
+}
+
+pub struct Conflict {
+	pub source_path: String,
+	pub target_path: String,
+	pub source: Metadata,
+	pub target: Metadata,
+}
+
+pub enum TransferState {
+	Running,
+	Conflict(Conflict),
+	Finished,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+	pub total_files: u64,
+	pub total_bytes: u64,
+	pub done_files: u64,
+	pub done_bytes: u64,
+	pub current_path: String,
+	pub current_bytes: u64,
+	pub current_size: u64,
+}
+
+enum Task {
+	Rename {
+		source_path: String,
+		target_dir: String,
+		target_path: String,
+		metadata: Metadata,
+	},
+	CreateDirectory {
+		source_path: String,
+		target_path: String,
+	},
+	CopyFile {
+		source_path: String,
+		target_dir: String,
+		target_path: String,
+		metadata: Metadata,
+	},
+	FinishDirectory {
+		source_path: String,
+		target_path: String,
+		metadata: Metadata,
+	},
+	/// Links are created again, pointing to the same target, rather than
+	/// copying what they point to.
+	CopyLink {
+		source_path: String,
+		target_dir: String,
+		target_path: String,
+		metadata: Metadata,
+	},
+}
+
+struct ActiveCopy {
+	reader: Box<dyn Read + Send>,
+	writer: Box<dyn Write + Send>,
+	source_path: String,
+	target_path: String,
+	metadata: Metadata,
+}
+
+/// Copies or moves files between two systems in small steps, so that the
+/// caller stays responsive and can report progress in between.
+pub struct Transfer {
+	mode: TransferMode,
+	source: Box<dyn System>,
+	target: Box<dyn System>,
+	same_location: bool,
+	/// Paths to transfer with the directories to transfer them to.
+	roots: VecDeque<(String, String)>,
+	tasks: VecDeque<Task>,
+	active_copy: Option<ActiveCopy>,
+	pending_conflict: Option<Task>,
+	resolution: Option<ConflictResolution>,
+	default_resolution: Option<ConflictResolution>,
+	buffer: Vec<u8>,
+	progress: Progress,
+	errors: Vec<String>,
+	cancelled: bool,
+}
+
+impl Transfer {
+	pub fn new(
+		mode: TransferMode,
+		mut source: Box<dyn System>,
+		mut target: Box<dyn System>,
+		source_paths: Vec<String>,
+		target_dir: &str,
+	) -> Self {
+		let same_location = source.get_location() == target.get_location();
+		Transfer {
+			mode,
+			source,
+			target,
+			same_location,
+			roots: source_paths
+				.into_iter()
+				.map(|source_path| (source_path, target_dir.to_owned()))
+				.collect(),
+			tasks: VecDeque::new(),
+			active_copy: None,
+			pending_conflict: None,
+			resolution: None,
+			default_resolution: None,
+			buffer: vec![0; BUFFER_SIZE],
+			progress: Progress::default(),
+			errors: Vec::new(),
+			cancelled: false,
+		}
+	}
+
+	/// Adds a path to transfer to another directory than the one given to
+	/// `new`.
+	pub fn add(&mut self, source_path: &str, target_dir: &str) {
+		self.roots
+			.push_back((source_path.to_owned(), target_dir.to_owned()));
+	}
+
+	/// Answers all conflicts with the resolution, without asking.
+	pub fn set_default_resolution(&mut self, resolution: ConflictResolution) {
+		self.default_resolution = Some(resolution);
+	}
+
+	pub fn get_mode(&self) -> TransferMode {
+		self.mode
+	}
+
+	pub fn get_progress(&self) -> &Progress {
+		&self.progress
+	}
+
+	pub fn get_errors(&self) -> &[String] {
+		&self.errors
+	}
+
+	pub fn cancel(&mut self) {
+		self.cancelled = true;
+	}
+
+	/// Answers the conflict returned by the last step. With `apply_to_all`,
+	/// the same answer is used for the following conflicts.
+	pub fn resolve(&mut self, resolution: ConflictResolution, apply_to_all: bool) {
+		if resolution == ConflictResolution::Cancel {
+			self.cancel();
+		}
+		match resolution {
+			ConflictResolution::Rename(_) => {}
+			_ if apply_to_all => self.default_resolution = Some(resolution.clone()),
+			_ => {}
+		}
+		self.resolution = Some(resolution);
+	}
+
+	pub fn step(&mut self) -> TransferState {
+		let deadline = Instant::now() + STEP_DURATION;
+		while Instant::now() < deadline {
+			if self.cancelled {
+				self.active_copy = None;
+				self.tasks.clear();
+				self.roots.clear();
+				return TransferState::Finished;
+			}
+			if let Some((root, target_dir)) = self.roots.pop_front() {
+				if let Err(e) = self.scan_root(&root, target_dir) {
+					self.errors.push(format!("{}: {}", root, e));
+				}
+				continue;
+			}
+			if self.active_copy.is_some() {
+				if let Err(e) = self.copy_chunk() {
+					let active_copy = self.active_copy.take().expect("active copy");
+					self.errors
+						.push(format!("{}: {}", active_copy.source_path, e));
+				}
+				continue;
+			}
+			let task = if let Some(task) = self.pending_conflict.take() {
+				task
+			} else if let Some(task) = self.tasks.pop_front() {
+				task
+			} else {
+				return TransferState::Finished;
+			};
+			match self.run_task(task) {
+				Ok(Some(conflict)) => return TransferState::Conflict(conflict),
+				Ok(None) => {}
+				Err(e) => self.errors.push(e.to_string()),
+			}
+		}
+		TransferState::Running
+	}
+
+	fn scan_root(&mut self, source_path: &str, target_dir: String) -> Result<(), Error> {
+		let metadata = self.source.get_metadata(source_path)?;
+		let filename = self.source.get_filename(source_path);
+		let target_path = self.target.join_path(&target_dir, &filename);
+		if self.same_location && is_same_or_descendant(&target_path, source_path) {
+			return Err(Error::IoError(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Cannot copy or move a file onto itself.",
+			)));
+		}
+		if self.mode == TransferMode::Move && self.same_location {
+			self.progress.total_files += 1;
+			self.tasks.push_back(Task::Rename {
+				source_path: source_path.to_owned(),
+				target_dir,
+				target_path,
+				metadata,
+			});
+			Ok(())
+		} else {
+			self.scan(source_path, &target_dir, &target_path, metadata)
+		}
+	}
+
+	fn scan(
+		&mut self,
+		source_path: &str,
+		target_dir: &str,
+		target_path: &str,
+		metadata: Metadata,
+	) -> Result<(), Error> {
+		if metadata.is_link {
+			self.progress.total_files += 1;
+			self.tasks.push_back(Task::CopyLink {
+				source_path: source_path.to_owned(),
+				target_dir: target_dir.to_owned(),
+				target_path: target_path.to_owned(),
+				metadata,
+			});
+		} else if metadata.is_dir {
+			self.tasks.push_back(Task::CreateDirectory {
+				source_path: source_path.to_owned(),
+				target_path: target_path.to_owned(),
+			});
+			for (child_path, child_metadata) in self.source.read_dir(source_path)? {
+				let filename = self.source.get_filename(&child_path);
+				let child_target_path = self.target.join_path(target_path, &filename);
+				self.scan(&child_path, target_path, &child_target_path, child_metadata)?;
+			}
+			self.tasks.push_back(Task::FinishDirectory {
+				source_path: source_path.to_owned(),
+				target_path: target_path.to_owned(),
+				metadata,
+			});
+		} else {
+			self.progress.total_files += 1;
+			self.progress.total_bytes += metadata.size;
+			self.tasks.push_back(Task::CopyFile {
+				source_path: source_path.to_owned(),
+				target_dir: target_dir.to_owned(),
+				target_path: target_path.to_owned(),
+				metadata,
+			});
+		}
+		Ok(())
+	}
+
+	fn run_task(&mut self, task: Task) -> Result<Option<Conflict>, Error> {
+		match task {
+			Task::Rename {
+				source_path,
+				target_dir,
+				target_path,
+				metadata,
+			} => {
+				let resolution = match self.check_conflict(&source_path, &target_path, &metadata) {
+					Ok(resolution) => resolution,
+					Err(conflict) => {
+						self.pending_conflict = Some(Task::Rename {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(Some(conflict));
+					}
+				};
+				match resolution {
+					Some(ConflictResolution::Skip) => {
+						self.progress.done_files += 1;
+						return Ok(None);
+					}
+					Some(ConflictResolution::Rename(name)) => {
+						let target_path = self.target.join_path(&target_dir, &name);
+						self.tasks.push_front(Task::Rename {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(None);
+					}
+					// Existing directories are merged.
+					Some(_) if metadata.is_dir => {
+						self.progress.total_files -= 1;
+						return self
+							.scan(&source_path, &target_dir, &target_path, metadata)
+							.map(|()| None);
+					}
+					_ => {}
+				}
+				self.progress.current_path = source_path.clone();
+				// Renaming replaces an existing file, so the target is kept,
+				// if it fails. Resuming a renamed file means overwriting it.
+				let mut result = self.target.rename(&source_path, &target_path);
+				if let Err(ref e) = result {
+					// Some backends refuse to replace files.
+					if resolution.is_some()
+						&& !is_cross_device(e)
+						&& self.source.get_metadata(&source_path).is_ok()
+					{
+						log::info!(
+							"Failed to rename {} over {}: {}",
+							source_path,
+							target_path,
+							e
+						);
+						self.target.delete(&target_path)?;
+						result = self.target.rename(&source_path, &target_path);
+					}
+				}
+				match result {
+					Ok(()) => self.progress.done_files += 1,
+					Err(ref e) if is_cross_device(e) => {
+						// Renaming fails across devices, fall back to copy and delete.
+						log::info!("Failed to rename {}: {}", source_path, e);
+						self.progress.total_files -= 1;
+						// The copy runs next and must not ask about the same
+						// conflict again.
+						let tasks = std::mem::take(&mut self.tasks);
+						let scanned = self.scan(&source_path, &target_dir, &target_path, metadata);
+						self.tasks.extend(tasks);
+						scanned?;
+						self.resolution = resolution;
+					}
+					Err(e) => return Err(e),
+				}
+			}
+			Task::CreateDirectory {
+				source_path,
+				target_path,
+			} => {
+				self.progress.current_path = source_path;
+				match self.target.get_metadata(&target_path) {
+					Ok(ref target) if target.is_dir => {}
+					_ => self.target.create_directory(&target_path)?,
+				}
+			}
+			Task::CopyFile {
+				source_path,
+				target_dir,
+				target_path,
+				metadata,
+			} => {
+				let resolution = match self.check_conflict(&source_path, &target_path, &metadata) {
+					Ok(resolution) => resolution,
+					Err(conflict) => {
+						self.pending_conflict = Some(Task::CopyFile {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(Some(conflict));
+					}
+				};
+				let offset = match resolution {
+					Some(ConflictResolution::Skip) => {
+						self.progress.done_files += 1;
+						self.progress.done_bytes += metadata.size;
+						return Ok(None);
+					}
+					Some(ConflictResolution::Rename(name)) => {
+						let target_path = self.target.join_path(&target_dir, &name);
+						self.tasks.push_front(Task::CopyFile {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(None);
+					}
+					Some(ConflictResolution::Resume) => {
+						let target = self.target.get_metadata(&target_path)?;
+						if target.size <= metadata.size {
+							target.size
+						} else {
+							0
+						}
+					}
+					_ => 0,
+				};
+				self.progress.current_path = source_path.clone();
+				self.progress.current_size = metadata.size;
+				self.progress.current_bytes = offset;
+				self.progress.done_bytes += offset;
+				self.active_copy = Some(ActiveCopy {
+					reader: self.source.open_read(&source_path, offset)?,
+					writer: self.target.open_write(&target_path, offset > 0)?,
+					source_path,
+					target_path,
+					metadata,
+				});
+			}
+			Task::FinishDirectory {
+				source_path,
+				target_path,
+				metadata,
+			} => {
+				self.set_target_metadata(&target_path, &metadata);
+				if self.mode == TransferMode::Move {
+					if let Err(e) = self.source.delete(&source_path) {
+						// Directories with skipped files remain.
+						log::info!("Failed to delete {}: {}", source_path, e);
+					}
+				}
+			}
+			Task::CopyLink {
+				source_path,
+				target_dir,
+				target_path,
+				metadata,
+			} => {
+				let resolution = match self.check_conflict(&source_path, &target_path, &metadata) {
+					Ok(resolution) => resolution,
+					Err(conflict) => {
+						self.pending_conflict = Some(Task::CopyLink {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(Some(conflict));
+					}
+				};
+				match resolution {
+					Some(ConflictResolution::Skip) => {
+						self.progress.done_files += 1;
+						return Ok(None);
+					}
+					Some(ConflictResolution::Rename(name)) => {
+						let target_path = self.target.join_path(&target_dir, &name);
+						self.tasks.push_front(Task::CopyLink {
+							source_path,
+							target_dir,
+							target_path,
+							metadata,
+						});
+						return Ok(None);
+					}
+					Some(ConflictResolution::Overwrite) | Some(ConflictResolution::Resume) => {
+						self.target.delete(&target_path)?;
+					}
+					_ => {}
+				}
+				self.progress.current_path = source_path.clone();
+				let link_target = self.source.read_link(&source_path)?;
+				self.target.create_link(&link_target, &target_path)?;
+				if self.mode == TransferMode::Move {
+					self.source.delete(&source_path)?;
+				}
+				self.progress.done_files += 1;
+			}
+		}
+		Ok(None)
+	}
+
+	/// Returns the resolution to apply, if the target exists, or the conflict
+	/// to ask about.
+	fn check_conflict(
+		&mut self,
+		source_path: &str,
+		target_path: &str,
+		metadata: &Metadata,
+	) -> Result<Option<ConflictResolution>, Conflict> {
+		if let Some(resolution) = self.resolution.take() {
+			return Ok(Some(resolution));
+		}
+		match self.target.get_metadata(target_path) {
+			Ok(target) => {
+				if let Some(resolution) = &self.default_resolution {
+					Ok(Some(resolution.clone()))
+				} else {
+					Err(Conflict {
+						source_path: source_path.to_owned(),
+						target_path: target_path.to_owned(),
+						source: metadata.clone(),
+						target,
+					})
+				}
+			}
+			Err(_) => Ok(None),
+		}
+	}
+
+	fn copy_chunk(&mut self) -> Result<(), Error> {
+		let active_copy = self.active_copy.as_mut().expect("active copy");
+		let bytes = active_copy.reader.read(&mut self.buffer)?;
+		if bytes > 0 {
+			active_copy.writer.write_all(&self.buffer[..bytes])?;
+			self.progress.current_bytes += bytes as u64;
+			self.progress.done_bytes += bytes as u64;
+			return Ok(());
+		}
+
+		active_copy.writer.flush()?;
+		let active_copy = self.active_copy.take().expect("active copy");
+		drop(active_copy.reader);
+		drop(active_copy.writer);
+		self.set_target_metadata(&active_copy.target_path, &active_copy.metadata);
+		if self.mode == TransferMode::Move {
+			self.source.delete(&active_copy.source_path)?;
+		}
+		self.progress.done_files += 1;
+		Ok(())
+	}
+
+	fn set_target_metadata(&mut self, target_path: &str, metadata: &Metadata) {
+		if let Err(e) = self.target.set_metadata(target_path, metadata) {
+			log::info!("Failed to set metadata of {}: {}", target_path, e);
+		}
+	}
+}
+
+/// The error of renaming to another device, `EXDEV`.
+#[cfg(unix)]
+const CROSS_DEVICE_ERROR: i32 = 18;
+/// The error of renaming to another device, `ERROR_NOT_SAME_DEVICE`.
+#[cfg(windows)]
+const CROSS_DEVICE_ERROR: i32 = 17;
+
+/// Tells, whether renaming failed, as the target is on another device.
+fn is_cross_device(e: &Error) -> bool {
+	matches!(e, Error::IoError(e) if e.raw_os_error() == Some(CROSS_DEVICE_ERROR))
+}
+
+fn is_same_or_descendant(path: &str, ancestor: &str) -> bool {
+	path == ancestor
+		|| (path.starts_with(ancestor)
+			&& path[ancestor.len()..].starts_with(|c| c == '/' || c == '\\'))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::api::{File, WatchEvent, Watcher};
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use std::fs;
+	use std::sync::mpsc::Sender;
+	use std::sync::Arc;
+
+	fn new_transfer(mode: TransferMode, source_paths: Vec<String>, target: &TestDir) -> Transfer {
+		Transfer::new(
+			mode,
+			Box::new(LocalSystem::default()),
+			Box::new(LocalSystem::default()),
+			source_paths,
+			target.path().to_str().unwrap(),
+		)
+	}
+
+	/// Runs the transfer, answering conflicts with `resolve`. Returns the
+	/// number of conflicts and the errors.
+	fn run_resolving<F>(mut transfer: Transfer, mut resolve: F) -> (usize, Vec<String>)
+	where
+		F: FnMut(&Conflict) -> (ConflictResolution, bool),
+	{
+		let mut conflicts = 0;
+		loop {
+			match transfer.step() {
+				TransferState::Running => {}
+				TransferState::Conflict(conflict) => {
+					conflicts += 1;
+					let (resolution, apply_to_all) = resolve(&conflict);
+					transfer.resolve(resolution, apply_to_all);
+				}
+				TransferState::Finished => return (conflicts, transfer.get_errors().to_vec()),
+			}
+		}
+	}
+
+	fn run(transfer: Transfer) -> Vec<String> {
+		run_resolving(transfer, |_conflict| (ConflictResolution::Cancel, false)).1
+	}
+
+	/// Copies `a.txt` onto an existing one, answering the conflict once.
+	fn copy_onto_existing(resolution: ConflictResolution) -> (TestDir, TestDir) {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::write(source.path().join("a.txt"), "new").unwrap();
+		fs::write(target.path().join("a.txt"), "old").unwrap();
+		let transfer = new_transfer(TransferMode::Copy, vec![source.join("a.txt")], &target);
+		let (conflicts, errors) = run_resolving(transfer, |_conflict| (resolution.clone(), false));
+		assert_eq!((conflicts, errors), (1, Vec::new()));
+		(source, target)
+	}
+
+	#[test]
+	fn skipping_a_conflict_keeps_the_target() {
+		let (_source, target) = copy_onto_existing(ConflictResolution::Skip);
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "old");
+	}
+
+	#[test]
+	fn overwriting_a_conflict_replaces_the_target() {
+		let (_source, target) = copy_onto_existing(ConflictResolution::Overwrite);
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "new");
+	}
+
+	#[test]
+	fn renaming_a_conflict_copies_to_the_new_name() {
+		let (_source, target) = copy_onto_existing(ConflictResolution::Rename("b.txt".to_owned()));
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "old");
+		assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "new");
+	}
+
+	#[test]
+	fn resolution_applied_to_all_is_not_asked_again() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		let names = ["a.txt", "b.txt", "c.txt"];
+		for name in &names {
+			fs::write(source.path().join(name), "new").unwrap();
+			fs::write(target.path().join(name), "old").unwrap();
+		}
+		let source_paths = names.iter().map(|name| source.join(name)).collect();
+		let transfer = new_transfer(TransferMode::Copy, source_paths, &target);
+		let (conflicts, errors) =
+			run_resolving(transfer, |_conflict| (ConflictResolution::Overwrite, true));
+		assert_eq!((conflicts, errors), (1, Vec::new()));
+		for name in &names {
+			assert_eq!(fs::read_to_string(target.join(name)).unwrap(), "new");
+		}
+	}
+
+	#[test]
+	fn resuming_appends_to_a_shorter_target_and_restarts_a_longer_one() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::write(source.path().join("short.txt"), "0123456789").unwrap();
+		fs::write(source.path().join("long.txt"), "0123456789").unwrap();
+		// The start differs, to tell appending from copying again.
+		fs::write(target.path().join("short.txt"), "abcde").unwrap();
+		fs::write(target.path().join("long.txt"), "0123456789abc").unwrap();
+		let transfer = new_transfer(
+			TransferMode::Copy,
+			vec![source.join("short.txt"), source.join("long.txt")],
+			&target,
+		);
+		let (conflicts, errors) =
+			run_resolving(transfer, |_conflict| (ConflictResolution::Resume, false));
+		assert_eq!((conflicts, errors), (2, Vec::new()));
+		assert_eq!(
+			fs::read_to_string(target.join("short.txt")).unwrap(),
+			"abcde56789"
+		);
+		assert_eq!(
+			fs::read_to_string(target.join("long.txt")).unwrap(),
+			"0123456789"
+		);
+	}
+
+	#[test]
+	fn moving_removes_the_source() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::create_dir(source.path().join("directory")).unwrap();
+		fs::write(source.path().join("directory").join("a.txt"), "a").unwrap();
+		fs::write(source.path().join("b.txt"), "b").unwrap();
+		let transfer = new_transfer(
+			TransferMode::Move,
+			vec![source.join("directory"), source.join("b.txt")],
+			&target,
+		);
+		assert_eq!(run(transfer), Vec::<String>::new());
+		assert!(!source.path().join("directory").exists());
+		assert!(!source.path().join("b.txt").exists());
+		assert_eq!(
+			fs::read_to_string(target.path().join("directory").join("a.txt")).unwrap(),
+			"a"
+		);
+		assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "b");
+	}
+
+	#[test]
+	fn moving_onto_a_directory_merges_and_removes_the_source() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::create_dir(source.path().join("directory")).unwrap();
+		fs::write(source.path().join("directory").join("a.txt"), "a").unwrap();
+		fs::create_dir(target.path().join("directory")).unwrap();
+		fs::write(target.path().join("directory").join("b.txt"), "b").unwrap();
+		let transfer = new_transfer(TransferMode::Move, vec![source.join("directory")], &target);
+		// The directory is copied into the existing one and deleted.
+		let (conflicts, errors) =
+			run_resolving(transfer, |_conflict| (ConflictResolution::Overwrite, false));
+		assert_eq!((conflicts, errors), (1, Vec::new()));
+		assert!(!source.path().join("directory").exists());
+		let directory = target.path().join("directory");
+		assert_eq!(fs::read_to_string(directory.join("a.txt")).unwrap(), "a");
+		assert_eq!(fs::read_to_string(directory.join("b.txt")).unwrap(), "b");
+	}
+
+	/// A local system, which cannot rename to the target, as if it was on
+	/// another device.
+	struct OtherDeviceSystem {
+		system: LocalSystem,
+		fail_reads: bool,
+	}
+
+	impl OtherDeviceSystem {
+		fn new(fail_reads: bool) -> Box<dyn System> {
+			Box::new(OtherDeviceSystem {
+				system: LocalSystem::default(),
+				fail_reads,
+			})
+		}
+	}
+
+	impl System for OtherDeviceSystem {
+		fn get_location(&mut self) -> String {
+			self.system.get_location()
+		}
+		fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+			Ok(OtherDeviceSystem::new(self.fail_reads))
+		}
+		fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+			self.system.get_root(field_names)
+		}
+		fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+			self.system.get_file(path, field_names)
+		}
+		fn get_filename(&mut self, path: &str) -> String {
+			self.system.get_filename(path)
+		}
+		fn list_files(
+			&mut self,
+			parent_directory: &File,
+			field_names: &Arc<Vec<String>>,
+		) -> Result<Vec<File>, Error> {
+			self.system.list_files(parent_directory, field_names)
+		}
+		fn join_path(&mut self, directory: &str, name: &str) -> String {
+			self.system.join_path(directory, name)
+		}
+		fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+			self.system.get_metadata(path)
+		}
+		fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+			self.system.set_metadata(path, metadata)
+		}
+		fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+			self.system.read_dir(path)
+		}
+		fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+			self.system.create_directory(path)
+		}
+		fn delete(&mut self, path: &str) -> Result<(), Error> {
+			self.system.delete(path)
+		}
+		fn rename(&mut self, _path: &str, _new_path: &str) -> Result<(), Error> {
+			Err(Error::IoError(io::Error::from_raw_os_error(
+				CROSS_DEVICE_ERROR,
+			)))
+		}
+		fn read_link(&mut self, path: &str) -> Result<String, Error> {
+			self.system.read_link(path)
+		}
+		fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+			self.system.create_link(target, path)
+		}
+		fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+			if self.fail_reads {
+				Err(Error::IoError(io::Error::new(
+					io::ErrorKind::Other,
+					"Read failed.",
+				)))
+			} else {
+				self.system.open_read(path, offset)
+			}
+		}
+		fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+			self.system.open_write(path, append)
+		}
+		fn watch(
+			&mut self,
+			path: &str,
+			sender: Sender<WatchEvent>,
+		) -> Result<Option<Watcher>, Error> {
+			self.system.watch(path, sender)
+		}
+	}
+
+	/// Moves `a.txt` onto an existing one on another device, overwriting it.
+	fn move_to_other_device(fail_reads: bool) -> (Vec<String>, TestDir, TestDir) {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::write(source.path().join("a.txt"), "new").unwrap();
+		fs::write(target.path().join("a.txt"), "old").unwrap();
+		let transfer = Transfer::new(
+			TransferMode::Move,
+			OtherDeviceSystem::new(fail_reads),
+			OtherDeviceSystem::new(fail_reads),
+			vec![source.join("a.txt")],
+			target.path().to_str().unwrap(),
+		);
+		let (conflicts, errors) =
+			run_resolving(transfer, |_conflict| (ConflictResolution::Overwrite, false));
+		assert_eq!(conflicts, 1);
+		(errors, source, target)
+	}
+
+	#[test]
+	fn moving_to_another_device_copies_and_removes_the_source() {
+		let (errors, source, target) = move_to_other_device(false);
+		assert_eq!(errors, Vec::<String>::new());
+		assert!(!source.path().join("a.txt").exists());
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "new");
+	}
+
+	#[test]
+	fn failed_copy_to_another_device_keeps_the_overwritten_target() {
+		let (errors, source, target) = move_to_other_device(true);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(fs::read_to_string(source.join("a.txt")).unwrap(), "new");
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "old");
+	}
+
+	#[test]
+	fn cancelling_at_a_conflict_stops_the_transfer() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		for name in &["a.txt", "b.txt", "c.txt"] {
+			fs::write(source.path().join(name), "new").unwrap();
+		}
+		fs::write(target.path().join("b.txt"), "old").unwrap();
+		let transfer = new_transfer(
+			TransferMode::Copy,
+			vec![
+				source.join("a.txt"),
+				source.join("b.txt"),
+				source.join("c.txt"),
+			],
+			&target,
+		);
+		assert_eq!(run(transfer), Vec::<String>::new());
+		assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "new");
+		assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "old");
+		assert!(!target.path().join("c.txt").exists());
+	}
+
+	#[test]
+	fn cancelled_transfer_copies_nothing_more() {
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::write(source.path().join("a.txt"), "a").unwrap();
+		let mut transfer = new_transfer(TransferMode::Move, vec![source.join("a.txt")], &target);
+		transfer.cancel();
+		assert!(matches!(transfer.step(), TransferState::Finished));
+		assert!(source.path().join("a.txt").exists());
+		assert!(!target.path().join("a.txt").exists());
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn links_are_created_again_instead_of_followed() {
+		use std::os::unix::fs::symlink;
+		use std::path::Path;
+
+		let source = TestDir::new();
+		let target = TestDir::new();
+		fs::write(source.path().join("file.txt"), "content").unwrap();
+		fs::create_dir(source.path().join("directory")).unwrap();
+		// A link to the parent directory would copy forever, if followed.
+		symlink("..", source.path().join("directory").join("loop")).unwrap();
+		symlink("file.txt", source.path().join("file-link")).unwrap();
+		symlink("missing", source.path().join("broken-link")).unwrap();
+
+		let transfer = new_transfer(
+			TransferMode::Copy,
+			vec![
+				source.join("directory"),
+				source.join("file-link"),
+				source.join("broken-link"),
+			],
+			&target,
+		);
+		assert_eq!(run(transfer), Vec::<String>::new());
+
+		let target = target.path();
+		assert_eq!(
+			fs::read_link(target.join("directory").join("loop")).unwrap(),
+			Path::new("..")
+		);
+		assert_eq!(
+			fs::read_link(target.join("file-link")).unwrap(),
+			Path::new("file.txt")
+		);
+		assert_eq!(
+			fs::read_link(target.join("broken-link")).unwrap(),
+			Path::new("missing")
+		);
+	}
+}
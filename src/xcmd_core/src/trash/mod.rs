@@ -0,0 +1,712 @@
+use crate::api::{Error, File, Icon, Metadata, System, Value, WatchEvent, Watcher};
+use chrono::Local;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+/// The path of the directory listing the trashed files.
+pub const TRASH_PATH: &str = "trash:";
+
+const INFO_EXTENSION: &str = ".trashinfo";
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A file or a directory tree in a trash directory, as described by the
+/// freedesktop.org Trash specification.
+pub struct TrashItem {
+	/// Where the item is kept, in the `files` directory of the trash.
+	pub path: String,
+	/// Where the item was deleted from.
+	pub original_path: String,
+}
+
+/// Tells, whether deleted files can be moved to a trash on this platform.
+pub fn is_supported() -> bool {
+	cfg!(target_os = "linux")
+}
+
+/// The trash in the home directory, `~/.local/share/Trash` by default.
+fn get_home_trash() -> Option<PathBuf> {
+	dirs::data_dir().map(|x| x.join("Trash"))
+}
+
+/// Moves a local file or directory tree to the trash of its file system,
+/// recording its original path and the deletion time in a `.trashinfo` file.
+/// Files on other file systems than the home directory are moved to the
+/// `.Trash/$uid` or `.Trash-$uid` directory at the top of their mount point,
+/// as moving them to the home trash would copy them.
+pub fn move_to_trash(path: &str) -> Result<(), Error> {
+	if !is_supported() {
+		return Err(Error::IoError(io::Error::new(
+			io::ErrorKind::Other,
+			"There is no trash on this system.",
+		)));
+	}
+	let path = Path::new(path);
+	let name = match path.file_name() {
+		Some(name) => name.to_string_lossy().into_owned(),
+		None => {
+			return Err(invalid_input(&format!(
+				"{} cannot be trashed.",
+				path.display()
+			)))
+		}
+	};
+	let (trash, top_dir) = find_trash_directory(path)?;
+	let files_dir = trash.join("files");
+	let info_dir = trash.join("info");
+	create_trash_directory(&trash)?;
+	create_trash_directory(&files_dir)?;
+	create_trash_directory(&info_dir)?;
+	// Paths in the trash of a mount point are relative to its top directory.
+	let original_path = top_dir
+		.as_ref()
+		.and_then(|top_dir| path.strip_prefix(top_dir).ok())
+		.unwrap_or(path);
+	let (trash_name, mut info_file) = create_info_file(&files_dir, &info_dir, &name)?;
+	let info = format!(
+		"[Trash Info]\nPath={}\nDeletionDate={}\n",
+		encode_path(&original_path.to_string_lossy()),
+		Local::now().format(DATE_FORMAT)
+	);
+	let result = info_file
+		.write_all(info.as_bytes())
+		.and_then(|_| fs::rename(path, files_dir.join(&trash_name)));
+	drop(info_file);
+	if let Err(e) = result {
+		let info_path = info_dir.join(format!("{}{}", trash_name, INFO_EXTENSION));
+		if let Err(e) = fs::remove_file(&info_path) {
+			log::warn!("Failed to remove {}: {}", info_path.display(), e);
+		}
+		return Err(Error::IoError(e));
+	}
+	Ok(())
+}
+
+/// Returns the trash for the path and the top directory of its mount point,
+/// if it is not the home trash.
+#[cfg(unix)]
+fn find_trash_directory(path: &Path) -> Result<(PathBuf, Option<PathBuf>), Error> {
+	use std::os::unix::fs::MetadataExt;
+	let home_trash = get_home_trash().ok_or_else(|| {
+		Error::IoError(io::Error::new(
+			io::ErrorKind::NotFound,
+			"The home directory is unknown.",
+		))
+	})?;
+	let device = fs::symlink_metadata(path)?.dev();
+	let home_device = home_trash
+		.ancestors()
+		.find_map(|x| fs::metadata(x).ok())
+		.map(|x| x.dev());
+	if home_device == Some(device) {
+		return Ok((home_trash, None));
+	}
+	let mut top_dir = path;
+	while let Some(parent) = top_dir.parent() {
+		if fs::metadata(parent)?.dev() != device {
+			break;
+		}
+		top_dir = parent;
+	}
+	let uid = users::get_current_uid();
+	// An administrator may have prepared a shared, sticky `.Trash` directory.
+	let shared_trash = top_dir.join(".Trash");
+	let is_shared_trash = fs::symlink_metadata(&shared_trash)
+		.map(|x| x.is_dir() && x.mode() & 0o1000 != 0)
+		.unwrap_or(false);
+	let trash = if is_shared_trash {
+		shared_trash.join(uid.to_string())
+	} else {
+		top_dir.join(format!(".Trash-{}", uid))
+	};
+	Ok((trash, Some(top_dir.to_path_buf())))
+}
+
+#[cfg(not(unix))]
+fn find_trash_directory(_path: &Path) -> Result<(PathBuf, Option<PathBuf>), Error> {
+	Err(Error::IoError(io::Error::new(
+		io::ErrorKind::Other,
+		"There is no trash on this system.",
+	)))
+}
+
+/// Creates a directory of the trash, readable only by the user.
+fn create_trash_directory(path: &Path) -> Result<(), Error> {
+	if path.is_dir() {
+		return Ok(());
+	}
+	let mut builder = fs::DirBuilder::new();
+	builder.recursive(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::DirBuilderExt;
+		builder.mode(0o700);
+	}
+	Ok(builder.create(path)?)
+}
+
+/// Reserves a name in the trash by creating its info file. Names taken by
+/// other trashed files get a number, e.g. `notes.txt.2`.
+fn create_info_file(
+	files_dir: &Path,
+	info_dir: &Path,
+	name: &str,
+) -> Result<(String, fs::File), Error> {
+	let mut index = 1;
+	loop {
+		let trash_name = if index == 1 {
+			name.to_owned()
+		} else {
+			format!("{}.{}", name, index)
+		};
+		index += 1;
+		if fs::symlink_metadata(files_dir.join(&trash_name)).is_ok() {
+			continue;
+		}
+		let info_path = info_dir.join(format!("{}{}", trash_name, INFO_EXTENSION));
+		match OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(info_path)
+		{
+			Ok(file) => return Ok((trash_name, file)),
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+			Err(e) => return Err(Error::IoError(e)),
+		}
+	}
+}
+
+/// Returns the trash directories of the user with the top directory of their
+/// mount point, if it is not the home trash.
+fn get_trash_directories() -> Vec<(PathBuf, Option<PathBuf>)> {
+	let mut directories = Vec::new();
+	if let Some(home_trash) = get_home_trash() {
+		directories.push((home_trash, None));
+	}
+	#[cfg(unix)]
+	{
+		let uid = users::get_current_uid();
+		let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+		for line in mounts.lines() {
+			let top_dir = match line.split(' ').nth(1) {
+				Some(top_dir) => PathBuf::from(decode_mount_point(top_dir)),
+				None => continue,
+			};
+			for trash in &[
+				top_dir.join(".Trash").join(uid.to_string()),
+				top_dir.join(format!(".Trash-{}", uid)),
+			] {
+				if trash.join("info").is_dir()
+					&& directories.iter().all(|(directory, _)| directory != trash)
+				{
+					directories.push((trash.clone(), Some(top_dir.clone())));
+				}
+			}
+		}
+	}
+	directories
+}
+
+/// Lists the items of all trash directories of the user. Items without info
+/// file or with a broken one are skipped.
+pub fn list_trash() -> Vec<TrashItem> {
+	let mut items = Vec::new();
+	for (trash, top_dir) in get_trash_directories() {
+		let entries = match fs::read_dir(trash.join("info")) {
+			Ok(entries) => entries,
+			Err(_) => continue,
+		};
+		for entry in entries.flatten() {
+			let file_name = entry.file_name().to_string_lossy().into_owned();
+			let name = match file_name.strip_suffix(INFO_EXTENSION) {
+				Some(name) => name,
+				None => continue,
+			};
+			let path = trash.join("files").join(name);
+			if fs::symlink_metadata(&path).is_err() {
+				continue;
+			}
+			match read_info(&entry.path(), top_dir.as_deref()) {
+				Ok(original_path) => items.push(TrashItem {
+					path: path.to_string_lossy().into_owned(),
+					original_path,
+				}),
+				Err(e) => log::warn!("Failed to read {}: {}", entry.path().display(), e),
+			}
+		}
+	}
+	items
+}
+
+/// Returns the original path from an info file.
+fn read_info(info_path: &Path, top_dir: Option<&Path>) -> Result<String, Error> {
+	let info = fs::read_to_string(info_path)?;
+	let path = info
+		.lines()
+		.find_map(|line| line.strip_prefix("Path="))
+		.ok_or_else(|| invalid_input("The trash info has no path."))?;
+	let path = PathBuf::from(decode_path(path.trim()));
+	let path = match top_dir {
+		Some(top_dir) if path.is_relative() => top_dir.join(path),
+		_ => path,
+	};
+	Ok(path.to_string_lossy().into_owned())
+}
+
+/// Returns the info file and the top directory of the trash, if the path is
+/// an item of a trash, i.e. not a file inside a trashed directory.
+fn find_info(path: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
+	let files_dir = path.parent()?;
+	if files_dir.file_name()? != OsStr::new("files") {
+		return None;
+	}
+	let trash = files_dir.parent()?;
+	let name = path.file_name()?.to_string_lossy();
+	let info_path = trash
+		.join("info")
+		.join(format!("{}{}", name, INFO_EXTENSION));
+	if !info_path.is_file() {
+		return None;
+	}
+	let top_dir = if get_home_trash().as_deref() == Some(trash) {
+		None
+	} else if trash.file_name()?.to_string_lossy().starts_with(".Trash-") {
+		trash.parent().map(Path::to_path_buf)
+	} else {
+		trash.parent()?.parent().map(Path::to_path_buf)
+	};
+	Some((info_path, top_dir))
+}
+
+/// Tells, whether the path is an item of a trash.
+pub fn is_trash_item(path: &str) -> bool {
+	find_info(Path::new(path)).is_some()
+}
+
+/// Moves a trashed item back to its original path, creating the missing
+/// directories. Returns the original path.
+pub fn restore(path: &str) -> Result<String, Error> {
+	let (info_path, top_dir) = find_info(Path::new(path))
+		.ok_or_else(|| invalid_input(&format!("{} is not an item of the trash.", path)))?;
+	let original_path = read_info(&info_path, top_dir.as_deref())?;
+	if fs::symlink_metadata(&original_path).is_ok() {
+		return Err(Error::IoError(io::Error::new(
+			io::ErrorKind::AlreadyExists,
+			format!("{} already exists.", original_path),
+		)));
+	}
+	if let Some(parent) = Path::new(&original_path).parent() {
+		fs::create_dir_all(parent)?;
+	}
+	fs::rename(path, &original_path)?;
+	fs::remove_file(&info_path)?;
+	Ok(original_path)
+}
+
+/// Removes the info file of a trash item, which was deleted.
+fn remove_info(path: &str) -> Result<(), Error> {
+	if let Some((info_path, _top_dir)) = find_info(Path::new(path)) {
+		fs::remove_file(info_path)?;
+	}
+	Ok(())
+}
+
+/// Percent-encodes the path like an URL, keeping the separators.
+fn encode_path(path: &str) -> String {
+	let mut encoded = String::with_capacity(path.len());
+	for byte in path.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+				encoded.push(byte as char)
+			}
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
+
+fn decode_path(path: &str) -> String {
+	let bytes = path.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut index = 0;
+	while index < bytes.len() {
+		let hex = bytes
+			.get(index + 1..index + 3)
+			.and_then(|x| std::str::from_utf8(x).ok())
+			.and_then(|x| u8::from_str_radix(x, 16).ok());
+		match (bytes[index], hex) {
+			(b'%', Some(byte)) => {
+				decoded.push(byte);
+				index += 3;
+			}
+			(byte, _) => {
+				decoded.push(byte);
+				index += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Decodes the octal escapes of spaces and other characters in mount points.
+#[cfg(unix)]
+fn decode_mount_point(mount_point: &str) -> String {
+	let mut decoded = String::with_capacity(mount_point.len());
+	let mut rest = mount_point;
+	while let Some(index) = rest.find('\\') {
+		decoded.push_str(&rest[..index]);
+		let escape = rest.get(index + 1..index + 4);
+		match escape.and_then(|x| u8::from_str_radix(x, 8).ok()) {
+			Some(byte) => {
+				decoded.push(byte as char);
+				rest = &rest[index + 4..];
+			}
+			None => {
+				decoded.push('\\');
+				rest = &rest[index + 1..];
+			}
+		}
+	}
+	decoded.push_str(rest);
+	decoded
+}
+
+/// Lists the items of all trash directories in a virtual directory, named
+/// like the deleted files and with their original path as link target. Other
+/// paths are passed to the wrapped system, so that trashed directories can be
+/// browsed and their files viewed and copied.
+pub struct TrashSystem {
+	system: Box<dyn System>,
+	/// The directory the parent item of the trash leads to.
+	origin: String,
+}
+
+impl TrashSystem {
+	pub fn new(system: Box<dyn System>, origin: &str) -> Self {
+		TrashSystem {
+			system,
+			origin: origin.to_owned(),
+		}
+	}
+
+	fn get_trash_directory(&self, name: &str, field_names: &Arc<Vec<String>>) -> File {
+		let fields = field_names
+			.iter()
+			.map(|field_name| match field_name.as_str() {
+				"path" => Value::Path {
+					path: TRASH_PATH.to_owned(),
+					name: name.to_owned(),
+					icon: Icon::Shell("C:\\.".to_owned()),
+				},
+				_ => Value::Empty,
+			})
+			.collect();
+		File::new(field_names, true, fields)
+	}
+
+	/// Returns the files of the trash items, named by their original name.
+	fn get_item_files(&mut self, field_names: &Arc<Vec<String>>) -> Result<Vec<File>, Error> {
+		let original_paths: HashMap<String, String> = list_trash()
+			.into_iter()
+			.map(|item| (item.path, item.original_path))
+			.collect();
+		let mut files_dirs: Vec<&str> = original_paths
+			.keys()
+			.filter_map(|path| Path::new(path).parent()?.to_str())
+			.collect();
+		files_dirs.sort_unstable();
+		files_dirs.dedup();
+		let mut files = Vec::with_capacity(original_paths.len());
+		for files_dir in files_dirs {
+			let files_dir = self.system.get_file(files_dir, field_names)?;
+			for mut file in self.system.list_files(&files_dir, field_names)? {
+				let original_path = match get_path(&file).and_then(|x| original_paths.get(x)) {
+					Some(original_path) => original_path.clone(),
+					None => continue,
+				};
+				let original_name = Path::new(&original_path);
+				let (name, extension) = if file.is_dir {
+					(
+						format!("[{}]", self.system.get_filename(&original_path)),
+						String::new(),
+					)
+				} else {
+					(
+						original_name
+							.file_stem()
+							.map(|x| x.to_string_lossy().into_owned())
+							.unwrap_or_default(),
+						original_name
+							.extension()
+							.map(|x| x.to_string_lossy().into_owned())
+							.unwrap_or_default(),
+					)
+				};
+				for (field_name, value) in field_names.iter().zip(file.fields.iter_mut()) {
+					match (field_name.as_str(), value) {
+						("path", Value::Path { name: x, .. }) => *x = name.clone(),
+						("extension", value) => {
+							*value = Value::String {
+								string: extension.clone(),
+							}
+						}
+						("link_target", value) => {
+							*value = Value::Link {
+								target: original_path.clone(),
+							}
+						}
+						_ => {}
+					}
+				}
+				files.push(file);
+			}
+		}
+		Ok(files)
+	}
+}
+
+fn get_path(file: &File) -> Option<&String> {
+	match file.get_field_index("path").map(|x| &file.fields[x]) {
+		Some(Value::Path { path, .. }) => Some(path),
+		_ => None,
+	}
+}
+
+impl System for TrashSystem {
+	fn get_location(&mut self) -> String {
+		self.system.get_location()
+	}
+
+	fn duplicate(&mut self) -> Result<Box<dyn System>, Error> {
+		Ok(Box::new(TrashSystem::new(
+			self.system.duplicate()?,
+			&self.origin,
+		)))
+	}
+
+	fn get_root(&mut self, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		Ok(self.get_trash_directory("[Trash]", field_names))
+	}
+
+	fn get_file(&mut self, path: &str, field_names: &Arc<Vec<String>>) -> Result<File, Error> {
+		if path == TRASH_PATH {
+			Ok(self.get_trash_directory("[Trash]", field_names))
+		} else {
+			self.system.get_file(path, field_names)
+		}
+	}
+
+	fn get_filename(&mut self, path: &str) -> String {
+		if path == TRASH_PATH {
+			"Trash".to_owned()
+		} else {
+			self.system.get_filename(path)
+		}
+	}
+
+	fn list_files(
+		&mut self,
+		parent_directory: &File,
+		field_names: &Arc<Vec<String>>,
+	) -> Result<Vec<File>, Error> {
+		let parent_path = get_path(parent_directory).cloned().unwrap_or_default();
+		if parent_path != TRASH_PATH {
+			let mut files = self.system.list_files(parent_directory, field_names)?;
+			// Trashed directories lead back to the trash.
+			if is_trash_item(&parent_path) {
+				for file in files.iter_mut().take(1) {
+					if let Some(Value::Path { name, .. }) =
+						file.get_field_index("path").map(|x| &file.fields[x])
+					{
+						if name == "[..]" {
+							*file = self.get_trash_directory("[..]", field_names);
+						}
+					}
+				}
+			}
+			return Ok(files);
+		}
+		let mut files = Vec::new();
+		// The parent item leaves the trash for the directory it was opened from.
+		let mut parent = self.system.get_file(&self.origin, field_names)?;
+		if let Some(path_index) = parent.get_field_index("path") {
+			if let Value::Path { name, .. } = &mut parent.fields[path_index] {
+				*name = "[..]".to_owned();
+			}
+		}
+		files.push(parent);
+		files.extend(self.get_item_files(field_names)?);
+		Ok(files)
+	}
+
+	fn join_path(&mut self, directory: &str, name: &str) -> String {
+		self.system.join_path(directory, name)
+	}
+
+	fn get_metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+		if path == TRASH_PATH {
+			Ok(Metadata {
+				is_dir: true,
+				..Metadata::default()
+			})
+		} else {
+			check_path(path)?;
+			self.system.get_metadata(path)
+		}
+	}
+
+	fn set_metadata(&mut self, path: &str, metadata: &Metadata) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.set_metadata(path, metadata)
+	}
+
+	fn read_dir(&mut self, path: &str) -> Result<Vec<(String, Metadata)>, Error> {
+		if path != TRASH_PATH {
+			check_path(path)?;
+			return self.system.read_dir(path);
+		}
+		Ok(list_trash()
+			.into_iter()
+			.filter_map(|item| {
+				let metadata = self.system.get_metadata(&item.path).ok()?;
+				Some((item.path, metadata))
+			})
+			.collect())
+	}
+
+	fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.create_directory(path)
+	}
+
+	fn delete(&mut self, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		let is_item = is_trash_item(path);
+		self.system.delete(path)?;
+		if is_item {
+			remove_info(path)?;
+		}
+		Ok(())
+	}
+
+	fn rename(&mut self, path: &str, new_path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		check_path(new_path)?;
+		if is_trash_item(path) {
+			return Err(invalid_input(
+				"Items of the trash cannot be renamed; restore them first.",
+			));
+		}
+		self.system.rename(path, new_path)
+	}
+
+	fn read_link(&mut self, path: &str) -> Result<String, Error> {
+		check_path(path)?;
+		self.system.read_link(path)
+	}
+
+	fn create_link(&mut self, target: &str, path: &str) -> Result<(), Error> {
+		check_path(path)?;
+		self.system.create_link(target, path)
+	}
+
+	fn open_read(&mut self, path: &str, offset: u64) -> Result<Box<dyn Read + Send>, Error> {
+		check_path(path)?;
+		self.system.open_read(path, offset)
+	}
+
+	fn open_write(&mut self, path: &str, append: bool) -> Result<Box<dyn Write + Send>, Error> {
+		check_path(path)?;
+		self.system.open_write(path, append)
+	}
+
+	fn watch(&mut self, path: &str, sender: Sender<WatchEvent>) -> Result<Option<Watcher>, Error> {
+		if path == TRASH_PATH {
+			// The trash directories are spread over the mount points; poll them.
+			Ok(None)
+		} else {
+			self.system.watch(path, sender)
+		}
+	}
+}
+
+/// Fails for paths in the trash directory, which only lists files.
+fn check_path(path: &str) -> Result<(), Error> {
+	if path.starts_with(TRASH_PATH) {
+		Err(invalid_input(
+			"The trash cannot be changed; delete files to move them there.",
+		))
+	} else {
+		Ok(())
+	}
+}
+
+fn invalid_input(message: &str) -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_dir::TestDir;
+
+	#[test]
+	fn paths_are_percent_encoded() {
+		let path = "/home/user/My Files/ä%.txt";
+		let encoded = encode_path(path);
+		assert_eq!(encoded, "/home/user/My%20Files/%C3%A4%25.txt");
+		assert_eq!(decode_path(&encoded), path);
+		assert_eq!(decode_path("100%zz%2"), "100%zz%2");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn mount_points_are_unescaped() {
+		assert_eq!(decode_mount_point(r"/media/my\040disk"), "/media/my disk");
+		assert_eq!(decode_mount_point(r"/media/a\b"), r"/media/a\b");
+	}
+
+	#[test]
+	fn info_files_give_the_original_path() {
+		let dir = TestDir::new();
+		let info_path = dir.path().join("notes.txt.trashinfo");
+		fs::write(
+			&info_path,
+			"[Trash Info]\nPath=docs/my%20notes.txt\nDeletionDate=2024-01-02T03:04:05\n",
+		)
+		.unwrap();
+		let top_dir = Path::new("/media/disk");
+		assert_eq!(
+			read_info(&info_path, Some(top_dir)).unwrap(),
+			top_dir.join("docs").join("my notes.txt").to_string_lossy()
+		);
+
+		fs::write(&info_path, "[Trash Info]\nPath=/home/user/notes.txt\n").unwrap();
+		assert_eq!(
+			read_info(&info_path, Some(top_dir)).unwrap(),
+			"/home/user/notes.txt"
+		);
+
+		fs::write(&info_path, "[Trash Info]\n").unwrap();
+		assert!(read_info(&info_path, None).is_err());
+	}
+
+	#[test]
+	fn taken_names_get_a_number() {
+		let dir = TestDir::new();
+		let files_dir = dir.path().join("files");
+		let info_dir = dir.path().join("info");
+		fs::create_dir(&files_dir).unwrap();
+		fs::create_dir(&info_dir).unwrap();
+		fs::write(files_dir.join("notes.txt"), "").unwrap();
+		let (name, _file) = create_info_file(&files_dir, &info_dir, "notes.txt").unwrap();
+		assert_eq!(name, "notes.txt.2");
+		let (name, _file) = create_info_file(&files_dir, &info_dir, "notes.txt").unwrap();
+		assert_eq!(name, "notes.txt.3");
+		assert!(info_dir.join("notes.txt.3.trashinfo").is_file());
+	}
+}
@@ -0,0 +1,170 @@
+use crate::api::{Error, Metadata, System};
+use std::cmp::Reverse;
+use std::io;
+
+/// Sizes of the files in a directory tree.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+	pub bytes: u64,
+	pub files: u64,
+	pub directories: u64,
+}
+
+/// Adds up the sizes of the files below the path, or returns the size of the
+/// file. Links are counted as files and not followed. Directories, which
+/// cannot be read, are skipped. `report` is called with the usage found so
+/// far and stops, if it returns false.
+pub fn get_usage(
+	system: &mut dyn System,
+	path: &str,
+	report: &mut dyn FnMut(&Usage) -> bool,
+) -> Result<Usage, Error> {
+	let metadata = system.get_metadata(path)?;
+	let mut usage = Usage::default();
+	if !is_directory(&metadata) {
+		usage.files = 1;
+		usage.bytes = get_size(&metadata);
+		return Ok(usage);
+	}
+	let mut directories = vec![path.to_owned()];
+	while let Some(directory) = directories.pop() {
+		let children = match system.read_dir(&directory) {
+			Ok(children) => children,
+			Err(e) => {
+				log::warn!("Failed to read {}: {}", directory, e);
+				continue;
+			}
+		};
+		for (child_path, metadata) in children {
+			if is_directory(&metadata) {
+				usage.directories += 1;
+				directories.push(child_path);
+			} else {
+				usage.files += 1;
+				usage.bytes += get_size(&metadata);
+			}
+		}
+		if !report(&usage) {
+			return Err(cancelled_error());
+		}
+	}
+	Ok(usage)
+}
+
+/// The total size of a child of a directory.
+pub struct ChildUsage {
+	pub path: String,
+	pub is_dir: bool,
+	pub usage: Usage,
+}
+
+/// Returns the usage of each child of the directory, the largest first.
+/// `report` is called with the total usage found so far.
+pub fn get_children_usage(
+	system: &mut dyn System,
+	directory: &str,
+	report: &mut dyn FnMut(&Usage) -> bool,
+) -> Result<Vec<ChildUsage>, Error> {
+	let mut children = Vec::new();
+	let mut total = Usage::default();
+	for (path, metadata) in system.read_dir(directory)? {
+		let usage = if is_directory(&metadata) {
+			get_usage(system, &path, &mut |usage| {
+				report(&Usage {
+					bytes: total.bytes + usage.bytes,
+					files: total.files + usage.files,
+					directories: total.directories + usage.directories,
+				})
+			})?
+		} else {
+			Usage {
+				bytes: get_size(&metadata),
+				files: 1,
+				directories: 0,
+			}
+		};
+		total.bytes += usage.bytes;
+		total.files += usage.files;
+		total.directories += usage.directories;
+		children.push(ChildUsage {
+			path,
+			is_dir: metadata.is_dir,
+			usage,
+		});
+		if !report(&total) {
+			return Err(cancelled_error());
+		}
+	}
+	children.sort_by_key(|child| Reverse(child.usage.bytes));
+	Ok(children)
+}
+
+/// Tells, whether the item is a directory to descend into.
+fn is_directory(metadata: &Metadata) -> bool {
+	metadata.is_dir && !metadata.is_link
+}
+
+/// Returns the size of a file. Links do not take the size of their target.
+fn get_size(metadata: &Metadata) -> u64 {
+	if metadata.is_link {
+		0
+	} else {
+		metadata.size
+	}
+}
+
+fn cancelled_error() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::Interrupted, "Cancelled."))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::local::LocalSystem;
+	use crate::test_dir::TestDir;
+	use std::fs;
+
+	#[test]
+	fn usage_adds_up_files_below_the_directory() {
+		let dir = TestDir::new();
+		fs::create_dir_all(dir.path().join("a").join("b")).unwrap();
+		fs::write(dir.path().join("a").join("one"), "1").unwrap();
+		fs::write(dir.path().join("a").join("b").join("two"), "22").unwrap();
+		fs::write(dir.path().join("four"), "4444").unwrap();
+		#[cfg(unix)]
+		std::os::unix::fs::symlink("..", dir.path().join("a").join("b").join("loop")).unwrap();
+
+		let mut system = LocalSystem::default();
+		let usage = get_usage(&mut system, &dir.join("a"), &mut |_usage| true).unwrap();
+		assert_eq!(usage.bytes, 3);
+		assert_eq!(usage.files, if cfg!(unix) { 3 } else { 2 });
+		assert_eq!(usage.directories, 1);
+
+		let children =
+			get_children_usage(&mut system, dir.path().to_str().unwrap(), &mut |_usage| {
+				true
+			})
+			.unwrap();
+		let children: Vec<(String, bool, u64)> = children
+			.into_iter()
+			.map(|child| (child.path, child.is_dir, child.usage.bytes))
+			.collect();
+		assert_eq!(
+			children,
+			[(dir.join("four"), false, 4), (dir.join("a"), true, 3)]
+		);
+	}
+
+	#[test]
+	fn usage_of_a_file_is_its_size() {
+		let dir = TestDir::new();
+		fs::write(dir.path().join("file"), "1234").unwrap();
+		let usage = get_usage(
+			&mut LocalSystem::default(),
+			&dir.join("file"),
+			&mut |_usage| true,
+		)
+		.unwrap();
+		assert_eq!((usage.bytes, usage.files, usage.directories), (4, 1, 0));
+	}
+}